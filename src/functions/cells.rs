@@ -0,0 +1,138 @@
+use calamine::{DataType, Reader};
+use std::path::Path;
+
+use crate::functions::{find_worksheet_argument, TableFunction};
+use crate::spreadsheet::cache::get_cache;
+use crate::spreadsheet::cells::CellRange;
+use crate::spreadsheet::manager::DataManagerError;
+use crate::spreadsheet::writer::get_cell_reference;
+
+/// `SELECT * FROM xlite_cells('./book.xlsx', 'Sheet1')` returns one row per non-empty
+/// cell, for worksheets laid out as forms rather than tables.
+pub struct CellsFunction;
+
+impl TableFunction for CellsFunction {
+    const NAME: &'static [u8] = b"xlite_cells\0";
+    const COLUMNS: &'static [&'static str] = &["row", "column", "address", "value", "type", "formula"];
+    const PARAMETERS: &'static [&'static str] = &["filename", "worksheet", "range"];
+    const REQUIRED: usize = 2;
+
+    fn call(args: &[DataType]) -> Result<Vec<Vec<DataType>>, String> {
+        let filename = match &args[0] {
+            DataType::String(filename) => filename,
+            _ => return Err(DataManagerError::NoFilename.to_string()),
+        };
+        let range = match &args[2] {
+            DataType::String(range) => {
+                Some(CellRange::try_parse(range).ok_or_else(|| format!("Invalid RANGE '{}'", range))?)
+            }
+            DataType::Empty => None,
+            value => return Err(format!("Invalid RANGE '{}'", value)),
+        };
+        list_cells(Path::new(filename), &args[1], range).map_err(|err| err.to_string())
+    }
+}
+
+/// Returns the 1-based row and column, the address, the value, the type and the formula
+/// of the non-empty cells of the worksheet (a name or a 0-based position) within the range.
+fn list_cells(
+    path: &Path,
+    worksheet: &DataType,
+    range: Option<CellRange>,
+) -> Result<Vec<Vec<DataType>>, DataManagerError> {
+    let cache = get_cache();
+    let names = cache
+        .with_sheets(path, |sheets| sheets.sheet_names().to_vec())
+        .map_err(DataManagerError::Calamine)?;
+    let worksheet = find_worksheet_argument(&names, worksheet)?;
+
+    let sheet = match cache
+        .worksheet_range(path, worksheet.as_str())
+        .map_err(DataManagerError::Calamine)?
+    {
+        Some(sheet) => sheet,
+        None => return Ok(Vec::new()),
+    };
+    let (origin, (start, end)) = match (sheet.start(), sheet.end()) {
+        (Some(start), Some(end)) => match range {
+            Some(range) => (start, range.resolve(start, end)),
+            None => (start, (start, end)),
+        },
+        _ => return Ok(Vec::new()),
+    };
+
+    let formulas = cache
+        .worksheet_formula(path, worksheet.as_str())
+        .map_err(DataManagerError::Calamine)?;
+
+    // used cells are positioned relative to the first cell of the worksheet range
+    let rows = sheet
+        .used_cells()
+        .map(|(row, column, value)| ((origin.0 + row as u32, origin.1 + column as u32), value))
+        .filter(|((row, column), _)| *row >= start.0 && *row <= end.0 && *column >= start.1 && *column <= end.1)
+        .map(|((row, column), value)| {
+            let (value, kind) = match value {
+                DataType::String(_) => (value.clone(), "string"),
+                DataType::Int(_) => (value.clone(), "int"),
+                DataType::Float(_) => (value.clone(), "float"),
+                DataType::Bool(_) => (value.clone(), "bool"),
+                DataType::DateTime(_) => (value.clone(), "datetime"),
+                DataType::Error(err) => (DataType::String(err.to_string()), "error"),
+                DataType::Empty => (DataType::Empty, "empty"),
+            };
+            let formula = formulas
+                .as_ref()
+                .and_then(|formulas| formulas.get_value((row, column)))
+                .filter(|formula| !formula.is_empty())
+                .map(|formula| DataType::String(format!("={}", formula)))
+                .unwrap_or(DataType::Empty);
+            vec![
+                DataType::Int(row as i64 + 1),
+                DataType::Int(column as i64 + 1),
+                DataType::String(get_cell_reference(row, column)),
+                value,
+                DataType::String(kind.to_string()),
+                formula,
+            ]
+        })
+        .collect();
+
+    Ok(rows)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn list_cells_returns_cells_of_range() {
+        let rows = list_cells(
+            Path::new("./tests/abcdef.xlsx"),
+            &DataType::Int(0),
+            CellRange::try_parse("B2:C2"),
+        )
+        .unwrap();
+
+        assert_eq!(
+            rows,
+            vec![
+                vec![
+                    DataType::Int(2),
+                    DataType::Int(2),
+                    DataType::String("B2".to_string()),
+                    DataType::Float(11.0),
+                    DataType::String("float".to_string()),
+                    DataType::Empty,
+                ],
+                vec![
+                    DataType::Int(2),
+                    DataType::Int(3),
+                    DataType::String("C2".to_string()),
+                    DataType::String("eleven".to_string()),
+                    DataType::String("string".to_string()),
+                    DataType::Empty,
+                ],
+            ]
+        );
+    }
+}
@@ -0,0 +1,81 @@
+use calamine::{DataType, Reader};
+use std::path::Path;
+
+use crate::functions::{find_worksheet_argument, TableFunction};
+use crate::spreadsheet::cache::get_cache;
+use crate::spreadsheet::detect::detect_table;
+use crate::spreadsheet::manager::DataManagerError;
+
+/// `SELECT * FROM xlite_detect('./book.xlsx', 'Sheet1')` reports the range and the header
+/// AUTO picks, to be copied into a fixed table definition.
+pub struct DetectFunction;
+
+impl TableFunction for DetectFunction {
+    const NAME: &'static [u8] = b"xlite_detect\0";
+    const COLUMNS: &'static [&'static str] = &["range", "header", "rows", "columns", "options"];
+    const PARAMETERS: &'static [&'static str] = &["filename", "worksheet"];
+    const REQUIRED: usize = 2;
+
+    fn call(args: &[DataType]) -> Result<Vec<Vec<DataType>>, String> {
+        let filename = match &args[0] {
+            DataType::String(filename) => filename,
+            _ => return Err(DataManagerError::NoFilename.to_string()),
+        };
+        detect(Path::new(filename), &args[1]).map_err(|err| err.to_string())
+    }
+}
+
+/// Returns one row with the detected range, the number of header and data rows, the number
+/// of columns and the matching RANGE and HEADER options, no row for a blank worksheet.
+fn detect(path: &Path, worksheet: &DataType) -> Result<Vec<Vec<DataType>>, DataManagerError> {
+    let cache = get_cache();
+    let names = cache
+        .with_sheets(path, |sheets| sheets.sheet_names().to_vec())
+        .map_err(DataManagerError::Calamine)?;
+    let worksheet = find_worksheet_argument(&names, worksheet)?;
+
+    let sheet = match cache
+        .worksheet_range(path, worksheet.as_str())
+        .map_err(DataManagerError::Calamine)?
+    {
+        Some(sheet) => sheet,
+        None => return Ok(Vec::new()),
+    };
+    let detected = match detect_table(&sheet) {
+        Some(detected) => detected,
+        None => return Ok(Vec::new()),
+    };
+
+    let mut options = format!("RANGE '{}'", detected.get_reference());
+    if detected.header_rows > 0 {
+        options.push_str(format!(", HEADER '{}'", detected.header_rows).as_str());
+    }
+    Ok(vec![vec![
+        DataType::String(detected.get_reference()),
+        DataType::Int(detected.header_rows as i64),
+        DataType::Int(detected.get_height() as i64),
+        DataType::Int(detected.get_width() as i64),
+        DataType::String(options),
+    ]])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detect_reports_range_and_header() {
+        let rows = detect(Path::new("./tests/blanks.xlsx"), &DataType::String("Sheet1".to_string())).unwrap();
+
+        assert_eq!(
+            rows,
+            vec![vec![
+                DataType::String("A1:B3".to_string()),
+                DataType::Int(1),
+                DataType::Int(2),
+                DataType::Int(2),
+                DataType::String("RANGE 'A1:B3', HEADER '1'".to_string()),
+            ]]
+        );
+    }
+}
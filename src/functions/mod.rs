@@ -0,0 +1,310 @@
+pub mod cells;
+pub mod detect;
+pub mod sheets;
+pub mod xlite;
+
+use calamine::DataType;
+use std::ffi::c_void;
+use std::marker::PhantomData;
+use std::os::raw::{c_char, c_int, c_longlong};
+
+use crate::sqlite::{
+    sqlite3, sqlite3_context, sqlite3_index_info, sqlite3_int64, sqlite3_module, sqlite3_value,
+    sqlite3_vtab, sqlite3_vtab_cursor, SQLITE_CONSTRAINT, SQLITE_INDEX_CONSTRAINT_EQ,
+    SQLITE_OK,
+};
+use crate::spreadsheet::columns::Column;
+use crate::spreadsheet::manager::DataManagerError;
+use crate::spreadsheet::worksheets::{find_worksheet, find_worksheet_by_index};
+use crate::utils::{declare_table, read_value, set_error_message, yield_result};
+use crate::{sqlite3_api, Module};
+
+/// A read-only eponymous table whose rows are computed from its arguments,
+/// e.g. `SELECT * FROM xlite_sheets('./book.xlsx')`.
+pub trait TableFunction {
+    /// Name of the table, nul-terminated.
+    const NAME: &'static [u8];
+    /// Names of the result columns.
+    const COLUMNS: &'static [&'static str];
+    /// Names of the hidden parameter columns, in the order of the arguments.
+    const PARAMETERS: &'static [&'static str];
+    /// Number of leading parameters that must be provided.
+    const REQUIRED: usize;
+
+    /// Computes the rows for the given arguments, missing optional arguments are `Empty`.
+    fn call(args: &[DataType]) -> Result<Vec<Vec<DataType>>, String>;
+}
+
+/// Finds the worksheet argument of a function, a name or a 0-based position.
+fn find_worksheet_argument(names: &[String], worksheet: &DataType) -> Result<String, DataManagerError> {
+    match worksheet {
+        DataType::String(name) => find_worksheet(names, name),
+        DataType::Int(index) if *index >= 0 => find_worksheet_by_index(names, *index as usize),
+        _ => return Err(DataManagerError::NoWorksheet),
+    }
+    .map_err(DataManagerError::Unsupported)
+}
+
+#[repr(C)]
+struct FunctionTable<F: TableFunction> {
+    // must be at the beginning
+    base: sqlite3_vtab,
+    function: PhantomData<F>,
+}
+
+#[repr(C)]
+struct FunctionCursor {
+    // must be at the beginning
+    base: sqlite3_vtab_cursor,
+    args: Vec<DataType>,
+    rows: Vec<Vec<DataType>>,
+    row: usize,
+}
+
+/// Builds an eponymous-only module for the table function `F`.
+pub const fn function_module<F: TableFunction>() -> Module {
+    Module {
+        base: sqlite3_module {
+            iVersion: 0,
+            xCreate: None,
+            xConnect: Some(x_connect::<F>),
+            xBestIndex: Some(x_best_index::<F>),
+            xDisconnect: Some(x_disconnect::<F>),
+            xDestroy: Some(x_disconnect::<F>),
+            xOpen: Some(x_open),
+            xClose: Some(x_close),
+            xFilter: Some(x_filter::<F>),
+            xNext: Some(x_next),
+            xEof: Some(x_eof),
+            xColumn: Some(x_column::<F>),
+            xRowid: Some(x_rowid),
+            xUpdate: None,
+            xBegin: None,
+            xSync: None,
+            xCommit: None,
+            xRollback: None,
+            xFindFunction: None,
+            xRename: None,
+            xSavepoint: None,
+            xRelease: None,
+            xRollbackTo: None,
+            xShadowName: None,
+        },
+        name: F::NAME,
+    }
+}
+
+unsafe extern "C" fn x_connect<F: TableFunction>(
+    db: *mut sqlite3,
+    _p_aux: *mut c_void,
+    _argc: c_int,
+    _argv: *const *const c_char,
+    pp_vtab: *mut *mut sqlite3_vtab,
+    _pz_err: *mut *mut c_char,
+) -> c_int {
+    let columns = F::COLUMNS.iter().map(|c| Column::new(c.to_string())).collect();
+    let parameters = F::PARAMETERS.iter().map(|c| c.to_string()).collect();
+    let result = declare_table(db, sqlite3_api, columns, parameters);
+    if result != SQLITE_OK {
+        return result;
+    }
+
+    let p_new: Box<FunctionTable<F>> = Box::new(FunctionTable {
+        base: sqlite3_vtab {
+            pModule: std::ptr::null_mut(),
+            nRef: 0,
+            zErrMsg: std::ptr::null_mut(),
+        },
+        function: PhantomData,
+    });
+    *pp_vtab = Box::into_raw(p_new) as *mut sqlite3_vtab;
+
+    SQLITE_OK
+}
+
+unsafe extern "C" fn x_best_index<F: TableFunction>(
+    _p_vtab: *mut sqlite3_vtab,
+    p_info: *mut sqlite3_index_info,
+) -> c_int {
+    let info = &mut *p_info;
+
+    match bind_parameters(info, F::COLUMNS.len(), F::PARAMETERS.len(), F::REQUIRED) {
+        Some(_) => {
+            info.estimatedCost = 1000.0;
+            info.estimatedRows = 1000;
+            SQLITE_OK
+        }
+        None => SQLITE_CONSTRAINT,
+    }
+}
+
+/// Passes the equality constraints on the `count` parameter columns starting at
+/// column `first` to x_filter, the first of them are required. idxNum holds the
+/// bitmask of the parameters that are present.
+///
+/// Returns the number of arguments passed, or `None` if the plan can't be used.
+pub unsafe fn bind_parameters(
+    info: &mut sqlite3_index_info,
+    first: usize,
+    count: usize,
+    required: usize,
+) -> Option<c_int> {
+    let n = info.nConstraint as usize;
+    let constraints = std::slice::from_raw_parts(info.aConstraint, n);
+    let usage = std::slice::from_raw_parts_mut(info.aConstraintUsage, n);
+
+    let mut present = vec![None; count];
+    for (i, constraint) in constraints.iter().enumerate() {
+        if constraint.iColumn < first as c_int
+            || constraint.iColumn >= (first + count) as c_int
+            || constraint.op as c_int != SQLITE_INDEX_CONSTRAINT_EQ
+        {
+            continue;
+        }
+        if constraint.usable == 0 {
+            // the plan can't be used without its arguments
+            return None;
+        }
+        present[constraint.iColumn as usize - first] = Some(i);
+    }
+
+    if present.iter().take(required).any(|p| p.is_none()) {
+        return None;
+    }
+
+    let mut mask = 0;
+    let mut argv_index = 0;
+    for (parameter, constraint) in present.iter().enumerate() {
+        if let Some(i) = constraint {
+            argv_index += 1;
+            mask |= 1 << parameter;
+            usage[*i].argvIndex = argv_index;
+            usage[*i].omit = 1;
+        }
+    }
+    info.idxNum = mask;
+
+    Some(argv_index)
+}
+
+/// Reads the arguments bound by `bind_parameters`, missing ones are `Empty`.
+/// Returns the arguments and the remaining values of argv.
+pub unsafe fn read_parameters(
+    idx_num: c_int,
+    count: usize,
+    values: &[*mut sqlite3_value],
+) -> (Vec<DataType>, &[*mut sqlite3_value]) {
+    let mut used = 0;
+    let mut args = Vec::with_capacity(count);
+    for parameter in 0..count {
+        let value = if idx_num & (1 << parameter) != 0 {
+            used += 1;
+            values.get(used - 1).and_then(|v| read_value(sqlite3_api, *v))
+        } else {
+            None
+        };
+        args.push(value.unwrap_or_default());
+    }
+
+    (args, &values[used.min(values.len())..])
+}
+
+unsafe extern "C" fn x_disconnect<F: TableFunction>(p_vtab: *mut sqlite3_vtab) -> c_int {
+    if !p_vtab.is_null() {
+        let table = Box::from_raw(p_vtab as *mut FunctionTable<F>);
+        drop(table);
+    }
+
+    SQLITE_OK
+}
+
+unsafe extern "C" fn x_open(
+    p_vtab: *mut sqlite3_vtab,
+    pp_cursor: *mut *mut sqlite3_vtab_cursor,
+) -> c_int {
+    let cursor: Box<FunctionCursor> = Box::new(FunctionCursor {
+        base: sqlite3_vtab_cursor { pVtab: p_vtab },
+        args: Vec::new(),
+        rows: Vec::new(),
+        row: 0,
+    });
+    *pp_cursor = Box::into_raw(cursor) as _;
+
+    SQLITE_OK
+}
+
+unsafe extern "C" fn x_close(p_cursor: *mut sqlite3_vtab_cursor) -> c_int {
+    if !p_cursor.is_null() {
+        let cursor = Box::from_raw(p_cursor as *mut FunctionCursor);
+        drop(cursor);
+    }
+
+    SQLITE_OK
+}
+
+unsafe extern "C" fn x_filter<F: TableFunction>(
+    p_cursor: *mut sqlite3_vtab_cursor,
+    idx_num: c_int,
+    _idx_str: *const c_char,
+    argc: c_int,
+    argv: *mut *mut sqlite3_value,
+) -> c_int {
+    let cursor = &mut *(p_cursor as *mut FunctionCursor);
+    let values = std::slice::from_raw_parts(argv, argc as usize);
+    let (args, _) = read_parameters(idx_num, F::PARAMETERS.len(), values);
+
+    match F::call(&args) {
+        Ok(rows) => {
+            cursor.args = args;
+            cursor.rows = rows;
+            cursor.row = 0;
+            SQLITE_OK
+        }
+        Err(err) => set_error_message(sqlite3_api, cursor.base.pVtab, err),
+    }
+}
+
+unsafe extern "C" fn x_next(p_cursor: *mut sqlite3_vtab_cursor) -> c_int {
+    let cursor = &mut *(p_cursor as *mut FunctionCursor);
+    cursor.row += 1;
+
+    SQLITE_OK
+}
+
+unsafe extern "C" fn x_eof(p_cursor: *mut sqlite3_vtab_cursor) -> c_int {
+    let cursor = &mut *(p_cursor as *mut FunctionCursor);
+    if cursor.row < cursor.rows.len() {
+        0
+    } else {
+        1
+    }
+}
+
+unsafe extern "C" fn x_column<F: TableFunction>(
+    p_cursor: *mut sqlite3_vtab_cursor,
+    p_context: *mut sqlite3_context,
+    column: c_int,
+) -> c_int {
+    let cursor = &mut *(p_cursor as *mut FunctionCursor);
+    let column = column as usize;
+
+    // parameter columns echo the arguments
+    let value = if column < F::COLUMNS.len() {
+        cursor.rows[cursor.row].get(column)
+    } else {
+        cursor.args.get(column - F::COLUMNS.len())
+    };
+    yield_result(p_context, sqlite3_api, value.unwrap_or(&DataType::Empty));
+
+    SQLITE_OK
+}
+
+unsafe extern "C" fn x_rowid(
+    p_cursor: *mut sqlite3_vtab_cursor,
+    p_rowid: *mut sqlite3_int64,
+) -> c_int {
+    let cursor = &mut *(p_cursor as *mut FunctionCursor);
+    *p_rowid = cursor.row as c_longlong;
+
+    SQLITE_OK
+}
@@ -0,0 +1,149 @@
+use calamine::{DataType, Reader};
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+use zip::ZipArchive;
+
+use crate::functions::TableFunction;
+use crate::spreadsheet::cache::get_cache;
+use crate::spreadsheet::cells::CellRange;
+use crate::spreadsheet::manager::DataManagerError;
+use crate::spreadsheet::writer::get_cell_reference;
+use crate::spreadsheet::xlsx::{read_dimension, read_sheet_entries, PackageError, SheetEntry};
+
+/// `SELECT * FROM xlite_sheets('./book.xlsx')` lists the worksheets of a workbook.
+pub struct SheetsFunction;
+
+impl TableFunction for SheetsFunction {
+    const NAME: &'static [u8] = b"xlite_sheets\0";
+    const COLUMNS: &'static [&'static str] =
+        &["name", "position", "visibility", "dimension", "rows", "columns"];
+    const PARAMETERS: &'static [&'static str] = &["filename"];
+    const REQUIRED: usize = 1;
+
+    fn call(args: &[DataType]) -> Result<Vec<Vec<DataType>>, String> {
+        let filename = match &args[0] {
+            DataType::String(filename) => filename,
+            _ => return Err(DataManagerError::NoFilename.to_string()),
+        };
+        list_sheets(Path::new(filename)).map_err(|err| err.to_string())
+    }
+}
+
+fn list_sheets(path: &Path) -> Result<Vec<Vec<DataType>>, DataManagerError> {
+    let cache = get_cache();
+    let names = cache
+        .with_sheets(path, |sheets| sheets.sheet_names().to_vec())
+        .map_err(DataManagerError::Calamine)?;
+
+    // calamine doesn't expose the sheet state, it is read from the package for .xlsx files
+    // along with the declared dimension, sparing the parsing of every worksheet
+    let entries = read_xlsx_entries(path).map_err(DataManagerError::Package)?;
+
+    let mut rows = Vec::with_capacity(names.len());
+    for (position, name) in names.into_iter().enumerate() {
+        let entry = entries.as_ref().and_then(|entries| entries.iter().find(|e| e.0.name == name));
+        let visibility = match &entries {
+            Some(_) => match entry {
+                Some((entry, _)) => DataType::String(
+                    entry.state.clone().unwrap_or_else(|| "visible".to_string()),
+                ),
+                None => DataType::Empty,
+            },
+            None => DataType::Empty,
+        };
+
+        let bounds = match entry.and_then(|(_, dimension)| parse_dimension(dimension.as_deref()?)) {
+            Some(bounds) => Some(bounds),
+            None => cache
+                .worksheet_range(path, name.as_str())
+                .map_err(DataManagerError::Calamine)?
+                .and_then(|r| r.start().zip(r.end())),
+        };
+        let (dimension, height, width) = match bounds {
+            Some((start, end)) => (
+                DataType::String(format!(
+                    "{}:{}",
+                    get_cell_reference(start.0, start.1),
+                    get_cell_reference(end.0, end.1)
+                )),
+                DataType::Int((end.0 - start.0 + 1) as i64),
+                DataType::Int((end.1 - start.1 + 1) as i64),
+            ),
+            None => (DataType::Empty, DataType::Int(0), DataType::Int(0)),
+        };
+
+        rows.push(vec![
+            DataType::String(name),
+            DataType::Int(position as i64),
+            visibility,
+            dimension,
+            height,
+            width,
+        ]);
+    }
+
+    Ok(rows)
+}
+
+/// Returns the first and last (row, column) positions of a declared dimension. A single
+/// cell is left to the parsing of the worksheet, `A1` being also the dimension of a blank one.
+fn parse_dimension(dimension: &str) -> Option<((u32, u32), (u32, u32))> {
+    let range = CellRange::try_parse(dimension)?;
+    let (start, end) = (range.get_start(), range.get_end());
+    if [start.get_x(), start.get_y(), end.get_x(), end.get_y()].contains(&0) || !dimension.contains(':') {
+        return None;
+    }
+    let (start, end) = (start.to_zero_indexed(), end.to_zero_indexed());
+    (start.0 <= end.0 && start.1 <= end.1).then_some((start, end))
+}
+
+/// A worksheet of an .xlsx package and its declared dimension.
+type XlsxSheet = (SheetEntry, Option<String>);
+
+fn read_xlsx_entries(path: &Path) -> Result<Option<Vec<XlsxSheet>>, PackageError> {
+    match path.extension().and_then(|e| e.to_str()) {
+        Some(ext) if ext.eq_ignore_ascii_case("xlsx") || ext.eq_ignore_ascii_case("xlsm") => {
+            let mut archive = ZipArchive::new(BufReader::new(File::open(path)?))?;
+            let mut entries = Vec::new();
+            for entry in read_sheet_entries(&mut archive)? {
+                let dimension = read_dimension(&mut archive, entry.part.as_str())?;
+                entries.push((entry, dimension));
+            }
+            Ok(Some(entries))
+        }
+        _ => Ok(None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn list_sheets_reports_used_range() {
+        let rows = list_sheets(Path::new("./tests/abcdef.xlsx")).unwrap();
+
+        assert_eq!(
+            rows,
+            vec![vec![
+                DataType::String("Sheet1".to_string()),
+                DataType::Int(0),
+                DataType::String("visible".to_string()),
+                DataType::String("A1:D6".to_string()),
+                DataType::Int(6),
+                DataType::Int(4),
+            ]]
+        );
+    }
+
+    #[test]
+    fn list_sheets_reads_declared_dimension() {
+        let rows = list_sheets(Path::new("./tests/abcdef_colnames.xlsx")).unwrap();
+
+        assert_eq!(rows[0][3], DataType::String("A1:D7".to_string()));
+        assert_eq!((&rows[0][4], &rows[0][5]), (&DataType::Int(7), &DataType::Int(4)));
+        assert_eq!(parse_dimension("A1"), None);
+        assert_eq!(parse_dimension("B2:C"), None);
+    }
+}
@@ -0,0 +1,92 @@
+use calamine::DataType;
+
+use crate::spreadsheet::cells::CellIndex;
+use crate::spreadsheet::columns::Column;
+use crate::spreadsheet::manager::{DataManager, DataManagerBuilder, DataManagerError};
+
+/// Number of data columns of the `xlite(...)` table function, named `A` to `ZZ`.
+pub const COLUMNS: usize = 702;
+
+/// Hidden parameter columns of the `xlite(...)` table function, in the order of the arguments.
+pub const PARAMETERS: [&str; 4] = ["filename", "worksheet", "range", "colnames"];
+
+/// Names of the data columns of the `xlite(...)` table function.
+pub fn get_columns() -> Vec<Column> {
+    (1..=COLUMNS as u32)
+        .map(|x| Column::new(CellIndex::new(x, 1).get_x_as_string()))
+        .collect()
+}
+
+/// Opens the manager for the arguments of `xlite('file', 'sheet' or index, 'range', colnames)`.
+pub fn open_manager(args: &[DataType]) -> Result<DataManager, DataManagerError> {
+    let mut builder = DataManagerBuilder::new();
+
+    match args.first() {
+        Some(DataType::String(file)) => builder = builder.file(file.clone()),
+        _ => return Err(DataManagerError::NoFilename),
+    }
+    match args.get(1) {
+        Some(DataType::String(worksheet)) => builder = builder.worksheet(worksheet.clone()),
+        // a number selects the worksheet by its 0-based position
+        Some(DataType::Int(index)) if *index >= 0 => builder = builder.sheet_index(*index as usize),
+        _ => return Err(DataManagerError::NoWorksheet),
+    }
+    match args.get(2) {
+        Some(DataType::String(range)) => builder = builder.range(range.clone()),
+        Some(DataType::Empty) | None => {}
+        Some(value) => {
+            return Err(DataManagerError::Unsupported(format!(
+                "Invalid RANGE '{}'",
+                value
+            )))
+        }
+    }
+
+    // colnames is the 1-based row holding the column names
+    let colnames = match args.get(3) {
+        Some(DataType::String(row)) => row.parse::<i64>().map(DataType::Int).ok(),
+        value => value.cloned(),
+    };
+    match (colnames, args.get(3)) {
+        (Some(DataType::Int(row)), _) if row > 0 => builder = builder.colnames_row(row as u32 - 1),
+        (_, Some(DataType::Empty) | None) => {}
+        (_, Some(value)) => {
+            return Err(DataManagerError::Unsupported(format!(
+                "Invalid colnames row '{}'",
+                value
+            )))
+        }
+    }
+
+    builder.open()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn get_columns_names_columns_by_letter() {
+        let columns = get_columns();
+
+        assert_eq!(columns.len(), COLUMNS);
+        assert_eq!(columns.first().unwrap().get_name(), "A");
+        assert_eq!(columns.last().unwrap().get_name(), "ZZ");
+    }
+
+    #[test]
+    fn open_manager_requires_filename_and_worksheet() {
+        assert!(matches!(open_manager(&[]), Err(DataManagerError::NoFilename)));
+        assert!(matches!(
+            open_manager(&[DataType::String("./tests/abcdef.xlsx".to_string())]),
+            Err(DataManagerError::NoWorksheet)
+        ));
+        assert!(open_manager(&[
+            DataType::String("./tests/abcdef.xlsx".to_string()),
+            DataType::String("Sheet1".to_string()),
+            DataType::String("A2:C".to_string()),
+            DataType::Empty,
+        ])
+        .is_ok());
+    }
+}
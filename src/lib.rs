@@ -1,28 +1,45 @@
 #![allow(non_upper_case_globals)]
 #![allow(dead_code)]
 
+mod functions;
 mod options;
+mod snapshot;
 mod spreadsheet;
 pub(crate) mod sqlite;
 mod utils;
 
-use calamine::DataType;
-use std::ffi::c_void;
+use calamine::{DataType, Range};
+use std::borrow::Cow;
+use std::collections::BTreeSet;
+use std::ffi::{c_void, CStr};
 use std::os::raw::{c_char, c_int, c_longlong};
 use std::sync::{Arc, Mutex};
 
+use crate::functions::{
+    bind_parameters, cells::CellsFunction, detect::DetectFunction, function_module, read_parameters,
+    sheets::SheetsFunction, xlite,
+};
 use crate::options::{parse_option, UsingOption};
+use crate::snapshot::{
+    create_snapshot, drop_snapshot, is_shadow_name, is_snapshot_stale, load_snapshot, load_source, rename_snapshot,
+    snapshot_exists, store_snapshot,
+};
 use crate::spreadsheet::{
+    cells::CellIndex,
+    constraints::{decode_plan, encode_plan, Constraint, ConstraintOp, ConstraintTarget},
     manager::{DataManager, DataManagerBuilder, DataManagerError},
     reader::DataReader,
 };
 use crate::sqlite::{
     sqlite3, sqlite3_api_routines, sqlite3_context, sqlite3_index_info, sqlite3_int64,
-    sqlite3_module, sqlite3_value, sqlite3_vtab, sqlite3_vtab_cursor, SQLITE_ERROR, SQLITE_OK,
+    sqlite3_module, sqlite3_value, sqlite3_vtab, sqlite3_vtab_cursor, SQLITE_CONSTRAINT, SQLITE_ERROR,
+    SQLITE_INDEX_CONSTRAINT_EQ, SQLITE_INDEX_CONSTRAINT_GE, SQLITE_INDEX_CONSTRAINT_GT,
+    SQLITE_INDEX_CONSTRAINT_LE, SQLITE_INDEX_CONSTRAINT_LT, SQLITE_INDEX_SCAN_UNIQUE, SQLITE_OK,
     SQLITE_OK_LOAD_PERMANENTLY,
 };
 use crate::utils::{
-    collect_options_from_args, declare_table, error_to_sqlite3_string, yield_result,
+    collect_options_from_args, collect_strings_from_raw, declare_table, error_to_sqlite3_string, log_warning,
+    read_string_from_raw, read_value, set_error_message, string_to_sqlite3_string, yield_result,
 };
 
 #[no_mangle]
@@ -39,7 +56,11 @@ pub struct Module {
 pub struct VirtualTable {
     // must be at the beginning
     base: sqlite3_vtab,
-    manager: Arc<Mutex<DataManager>>,
+    db: *mut sqlite3,
+    schema: String,
+    name: String,
+    // None for the xlite(...) table function, which opens the file in x_filter
+    manager: Option<Arc<Mutex<DataManager>>>,
 }
 
 #[repr(C)]
@@ -47,13 +68,16 @@ struct VirtualCursor {
     // must be at the beginning
     base: sqlite3_vtab_cursor,
     reader: Arc<Mutex<DataReader>>,
+    // arguments and first worksheet column of the xlite(...) table function
+    function: Option<(Vec<DataType>, u32)>,
 }
 
 pub const XLITE_MODULE: Module = Module {
     base: sqlite3_module {
-        iVersion: 0,
+        iVersion: 3,
+        // the same function for both makes xlite available as an eponymous table
         xCreate: Some(x_create),
-        xConnect: Some(x_connect),
+        xConnect: Some(x_create),
         xBestIndex: Some(x_best_index),
         xDisconnect: Some(x_disconnect),
         xDestroy: Some(x_destroy),
@@ -64,47 +88,70 @@ pub const XLITE_MODULE: Module = Module {
         xEof: Some(x_eof),
         xColumn: Some(x_column),
         xRowid: Some(x_rowid),
-        xUpdate: None,
-        xBegin: None,
-        xSync: None,
-        xCommit: None,
-        xRollback: None,
+        xUpdate: Some(x_update),
+        xBegin: Some(x_begin),
+        xSync: Some(x_sync),
+        xCommit: Some(x_commit),
+        xRollback: Some(x_rollback),
         xFindFunction: None,
-        xRename: None,
+        xRename: Some(x_rename),
         xSavepoint: None,
         xRelease: None,
         xRollbackTo: None,
-        xShadowName: None,
+        xShadowName: Some(x_shadow_name),
     },
     name: b"xlite\0",
 };
 
+pub const XLITE_SHEETS_MODULE: Module = function_module::<SheetsFunction>();
+
+pub const XLITE_CELLS_MODULE: Module = function_module::<CellsFunction>();
+
+pub const XLITE_DETECT_MODULE: Module = function_module::<DetectFunction>();
+
+/// Registers the `xlite` module and the table functions on the given connection.
+///
+/// # Safety
+///
+/// Must be called by SQLite with valid `db` and `p_api` pointers.
 #[no_mangle]
 pub unsafe extern "C" fn register_module(
     db: *mut sqlite3,
     pz_err_msg: *mut *mut c_char,
     p_api: *mut sqlite3_api_routines,
 ) -> c_int {
-    let name = XLITE_MODULE.name;
+    let modules: [&'static Module; 4] = [
+        &XLITE_MODULE,
+        &XLITE_SHEETS_MODULE,
+        &XLITE_CELLS_MODULE,
+        &XLITE_DETECT_MODULE,
+    ];
+
+    for module in modules {
+        let result = ((*p_api).create_module.unwrap())(
+            db,
+            module.name.as_ptr() as *const c_char,
+            module as *const Module as *const sqlite3_module,
+            std::ptr::null_mut(),
+        );
 
-    let result = ((*p_api).create_module.unwrap())(
-        db,
-        name.as_ptr() as *const c_char,
-        &XLITE_MODULE as *const Module as *const sqlite3_module,
-        std::ptr::null_mut(),
-    );
-
-    if result != SQLITE_OK {
-        let err = format!("Failed to create module, status: {}", result);
-        if let Some(ptr) = error_to_sqlite3_string(sqlite3_api, err) {
-            *pz_err_msg = ptr;
+        if result != SQLITE_OK {
+            let err = format!("Failed to create module, status: {}", result);
+            if let Some(ptr) = error_to_sqlite3_string(sqlite3_api, err) {
+                *pz_err_msg = ptr;
+            }
+            return SQLITE_ERROR;
         }
-        SQLITE_ERROR
-    } else {
-        SQLITE_OK_LOAD_PERMANENTLY
     }
+
+    SQLITE_OK_LOAD_PERMANENTLY
 }
 
+/// Extension entry point, invoked by SQLite when the library is loaded.
+///
+/// # Safety
+///
+/// Must be called by SQLite with valid `db` and `p_api` pointers.
 #[no_mangle]
 pub unsafe extern "C" fn sqlite3_xlite_init(
     db: *mut sqlite3,
@@ -117,9 +164,10 @@ pub unsafe extern "C" fn sqlite3_xlite_init(
     if result != SQLITE_OK {
         return result;
     } else {
-        let result = ((*p_api).auto_extension.unwrap())(Some(std::mem::transmute(
-            register_module as *const (),
-        )));
+        let result = ((*p_api).auto_extension.unwrap())(Some(std::mem::transmute::<
+            *const (),
+            unsafe extern "C" fn(),
+        >(register_module as *const ())));
         if result != SQLITE_OK {
             return result;
         }
@@ -137,15 +185,57 @@ unsafe extern "C" fn x_create(
     pp_vtab: *mut *mut sqlite3_vtab,
     pz_err: *mut *mut c_char,
 ) -> c_int {
+    init_table(db, argc, argv, pp_vtab, pz_err)
+}
+
+unsafe fn init_table(
+    db: *mut sqlite3,
+    argc: c_int,
+    argv: *const *const c_char,
+    pp_vtab: *mut *mut sqlite3_vtab,
+    pz_err: *mut *mut c_char,
+) -> c_int {
+    // argv[1] is the name of the database, argv[2] is the name of the table
+    let args = collect_strings_from_raw(argc as usize, argv);
+    let schema = args.get(1).cloned().unwrap_or_default();
+    let name = args.get(2).cloned().unwrap_or_default();
+
+    // the eponymous table, named after the module, is the xlite(...) table function
+    if args.len() <= 3 && args.first() == Some(&name) {
+        let hidden = xlite::PARAMETERS.iter().map(|p| p.to_string()).collect();
+        let result = declare_table(db, sqlite3_api, xlite::get_columns(), hidden);
+        let p_new: Box<VirtualTable> = Box::new(VirtualTable {
+            base: sqlite3_vtab {
+                pModule: std::ptr::null_mut(),
+                nRef: 0,
+                zErrMsg: std::ptr::null_mut(),
+            },
+            db,
+            schema,
+            name,
+            manager: None,
+        });
+        *pp_vtab = Box::into_raw(p_new) as *mut sqlite3_vtab;
+        return result;
+    }
+
     let options = collect_options_from_args(argc, argv);
-    let manager = DataManagerBuilder::from_options(options).open();
+    let manager = open_manager(db, DataManagerBuilder::from_options(options), &schema, &name)
+        .and_then(|mut manager| Ok((manager.get_columns()?, manager)));
 
     let mut result: c_int = SQLITE_ERROR;
 
     match manager {
-        Ok(mut manager) => {
-            let columns = manager.get_columns();
-            result = declare_table(db, sqlite3_api, columns);
+        Ok((columns, mut manager)) => {
+            for warning in manager.take_warnings() {
+                log_warning(sqlite3_api, warning);
+            }
+            let mut hidden = manager.get_hidden_columns();
+            // snapshot tables take commands through a hidden column named after the table
+            if manager.is_snapshot() {
+                hidden.push(name.clone());
+            }
+            result = declare_table(db, sqlite3_api, columns, hidden);
 
             let p_new: Box<VirtualTable> = Box::new(VirtualTable {
                 base: sqlite3_vtab {
@@ -153,17 +243,15 @@ unsafe extern "C" fn x_create(
                     nRef: 0,
                     zErrMsg: std::ptr::null_mut(),
                 },
-                manager: Arc::new(Mutex::new(manager)),
+                db,
+                schema,
+                name,
+                manager: Some(Arc::new(Mutex::new(manager))),
             });
             *pp_vtab = Box::into_raw(p_new) as *mut sqlite3_vtab;
         }
         Err(err) => {
-            let msg = match err {
-                DataManagerError::NoFilename => "Filename is not provided".to_string(),
-                DataManagerError::NoWorksheet => "Worksheet is not provided".to_string(),
-                DataManagerError::Calamine(e) => format!("{}", e),
-            };
-            if let Some(ptr) = error_to_sqlite3_string(sqlite3_api, msg) {
+            if let Some(ptr) = error_to_sqlite3_string(sqlite3_api, err.to_string()) {
                 *pz_err = ptr;
                 return SQLITE_ERROR;
             }
@@ -173,34 +261,173 @@ unsafe extern "C" fn x_create(
     result
 }
 
-#[no_mangle]
-unsafe extern "C" fn x_connect(
+unsafe fn open_manager(
     db: *mut sqlite3,
-    p_aux: *mut c_void,
-    argc: c_int,
-    argv: *const *const c_char,
-    pp_vtab: *mut *mut sqlite3_vtab,
-    pz_err: *mut *mut c_char,
-) -> c_int {
-    x_create(db, p_aux, argc, argv, pp_vtab, pz_err)
+    builder: DataManagerBuilder,
+    schema: &str,
+    name: &str,
+) -> Result<DataManager, DataManagerError> {
+    if !builder.is_snapshot() {
+        return builder.open();
+    }
+
+    // the snapshot is taken by CREATE VIRTUAL TABLE and loaded on later connections
+    let exists = snapshot_exists(db, sqlite3_api, schema, name).map_err(DataManagerError::Snapshot)?;
+    if !exists {
+        let mut manager = builder.open()?;
+        let range = manager.read_worksheet_range()?;
+        create_snapshot(
+            db,
+            sqlite3_api,
+            schema,
+            name,
+            manager.get_file(),
+            &manager.get_source(),
+            &range,
+        )
+        .map_err(DataManagerError::Snapshot)?;
+        manager.set_snapshot((*range).clone());
+        Ok(manager)
+    } else {
+        let range = load_snapshot(db, sqlite3_api, schema, name)
+            .map_err(DataManagerError::Snapshot)?;
+        let source = load_source(db, sqlite3_api, schema, name).map_err(DataManagerError::Snapshot)?;
+        let manager = builder.open_snapshot(range, source)?;
+        let stale = is_snapshot_stale(db, sqlite3_api, schema, name, manager.get_file())
+            .map_err(DataManagerError::Snapshot)?;
+        if stale {
+            log_warning(sqlite3_api, format!(
+                "The snapshot of table '{}' is older than '{}', run INSERT INTO {}({}) VALUES ('refresh') to load it again",
+                name,
+                manager.get_file().display(),
+                name,
+                name
+            ));
+        }
+        Ok(manager)
+    }
 }
 
 #[no_mangle]
 unsafe extern "C" fn x_best_index(
-    _p_vtab: *mut sqlite3_vtab,
-    _arg1: *mut sqlite3_index_info,
+    p_vtab: *mut sqlite3_vtab,
+    p_info: *mut sqlite3_index_info,
 ) -> c_int {
+    let table = &mut *(p_vtab as *mut VirtualTable);
+    let info = &mut *p_info;
+
+    let n = info.nConstraint as usize;
+    let constraints = std::slice::from_raw_parts(info.aConstraint, n);
+    let usage = std::slice::from_raw_parts_mut(info.aConstraintUsage, n);
+
+    // the xlite(...) table function takes its arguments through the hidden parameter columns
+    let mut parameters = 0;
+    if table.manager.is_none() {
+        match bind_parameters(info, xlite::COLUMNS, xlite::PARAMETERS.len(), 1) {
+            Some(n) => parameters = n,
+            None => return SQLITE_CONSTRAINT,
+        }
+    }
+
+    let hint = table.manager.as_ref().and_then(|m| m.lock().unwrap().get_rows_hint());
+    let rows = match hint {
+        Some((_, height)) => height as f64,
+        None => 1_000_000.0,
+    };
+    let mut scanned = rows;
+    let mut estimated = rows;
+    let mut unique = false;
+
+    let mut plan = Vec::new();
+    for (i, constraint) in constraints.iter().enumerate() {
+        if constraint.usable == 0 {
+            continue;
+        }
+
+        let op = match constraint.op as c_int {
+            SQLITE_INDEX_CONSTRAINT_EQ => ConstraintOp::Eq,
+            SQLITE_INDEX_CONSTRAINT_GT => ConstraintOp::Gt,
+            SQLITE_INDEX_CONSTRAINT_GE => ConstraintOp::Ge,
+            SQLITE_INDEX_CONSTRAINT_LT => ConstraintOp::Lt,
+            SQLITE_INDEX_CONSTRAINT_LE => ConstraintOp::Le,
+            _ => continue,
+        };
+
+        if table.manager.is_none() && constraint.iColumn >= xlite::COLUMNS as c_int {
+            continue;
+        }
+
+        let target = if constraint.iColumn < 0 {
+            ConstraintTarget::RowId
+        } else if is_binary_collation(p_info, i) {
+            ConstraintTarget::Column(constraint.iColumn as usize)
+        } else {
+            continue;
+        };
+
+        // rowid constraints narrow the scanned rows, column constraints
+        // only narrow the returned rows
+        match (target, op) {
+            (ConstraintTarget::RowId, ConstraintOp::Eq) => {
+                scanned = 1.0;
+                estimated = 1.0;
+                unique = true;
+            }
+            (ConstraintTarget::RowId, _) => {
+                scanned /= 2.0;
+                estimated /= 2.0;
+            }
+            (ConstraintTarget::Column(_), ConstraintOp::Eq) => estimated /= 10.0,
+            (ConstraintTarget::Column(_), _) => estimated /= 2.0,
+        }
+
+        plan.push((target, op));
+        usage[i].argvIndex = parameters + plan.len() as c_int;
+        usage[i].omit = 0;
+    }
+
+    if !plan.is_empty() {
+        if let Some(ptr) = string_to_sqlite3_string(sqlite3_api, encode_plan(&plan)) {
+            info.idxStr = ptr;
+            info.needToFreeIdxStr = 1;
+        }
+    }
+
+    let estimated = estimated.max(1.0).min(scanned.max(1.0));
+    info.estimatedRows = estimated as sqlite3_int64;
+    info.estimatedCost = scanned.max(1.0) + estimated;
+    if unique {
+        info.idxFlags |= SQLITE_INDEX_SCAN_UNIQUE;
+    }
+
     SQLITE_OK
 }
 
+unsafe fn is_binary_collation(p_info: *mut sqlite3_index_info, i: usize) -> bool {
+    let collation = ((*sqlite3_api).vtab_collation.unwrap())(p_info, i as c_int);
+    collation.is_null() || CStr::from_ptr(collation).to_bytes().eq_ignore_ascii_case(b"BINARY")
+}
+
 #[no_mangle]
 unsafe extern "C" fn x_disconnect(p_vtab: *mut sqlite3_vtab) -> c_int {
-    x_destroy(p_vtab)
+    if !p_vtab.is_null() {
+        let table = Box::from_raw(p_vtab as *mut VirtualTable);
+        drop(table);
+    }
+
+    SQLITE_OK
 }
 
 #[no_mangle]
 unsafe extern "C" fn x_destroy(p_vtab: *mut sqlite3_vtab) -> c_int {
     if !p_vtab.is_null() {
+        let table = &mut *(p_vtab as *mut VirtualTable);
+        if is_snapshot(table) {
+            if let Err(err) = drop_snapshot(table.db, sqlite3_api, &table.schema, &table.name) {
+                return set_vtab_error(p_vtab, DataManagerError::Snapshot(err));
+            }
+        }
+
         let table = Box::from_raw(p_vtab as *mut VirtualTable);
         drop(table);
     }
@@ -213,14 +440,11 @@ unsafe extern "C" fn x_open(
     p_vtab: *mut sqlite3_vtab,
     pp_cursor: *mut *mut sqlite3_vtab_cursor,
 ) -> c_int {
-    let table = &mut *(p_vtab as *mut VirtualTable);
-    let manager = Arc::clone(&table.manager);
-    let mut lock = manager.lock().unwrap();
-    let reader = lock.read();
-
+    // the actual reader is created in x_filter, once the constraints are known
     let cursor: Box<VirtualCursor> = Box::new(VirtualCursor {
         base: sqlite3_vtab_cursor { pVtab: p_vtab },
-        reader: Arc::new(Mutex::new(reader)),
+        reader: Arc::new(Mutex::new(DataReader::new(Arc::new(Range::empty())))),
+        function: None,
     });
     *pp_cursor = Box::into_raw(cursor) as _;
 
@@ -239,12 +463,92 @@ unsafe extern "C" fn x_close(p_cursor: *mut sqlite3_vtab_cursor) -> c_int {
 
 #[no_mangle]
 unsafe extern "C" fn x_filter(
-    _arg1: *mut sqlite3_vtab_cursor,
-    _idx_num: c_int,
-    _idx_str: *const c_char,
-    _argc: c_int,
-    _argv: *mut *mut sqlite3_value,
+    p_cursor: *mut sqlite3_vtab_cursor,
+    idx_num: c_int,
+    idx_str: *const c_char,
+    argc: c_int,
+    argv: *mut *mut sqlite3_value,
 ) -> c_int {
+    let cursor = &mut *(p_cursor as *mut VirtualCursor);
+    let table = &mut *(cursor.base.pVtab as *mut VirtualTable);
+
+    let plan = if idx_str.is_null() {
+        Vec::new()
+    } else {
+        decode_plan(read_string_from_raw(idx_str).as_str())
+    };
+    let args = std::slice::from_raw_parts(argv, argc as usize);
+
+    let (manager, args) = match &table.manager {
+        Some(manager) => (Arc::clone(manager), args),
+        None => {
+            let (parameters, args) = read_parameters(idx_num, xlite::PARAMETERS.len(), args);
+            let manager = match xlite::open_manager(&parameters) {
+                Ok(manager) => manager,
+                Err(err) => return set_vtab_error(cursor.base.pVtab, err),
+            };
+            cursor.function = Some((parameters, 0));
+            (Arc::new(Mutex::new(manager)), args)
+        }
+    };
+    let mut manager = manager.lock().unwrap();
+
+    // columns of the table function are worksheet columns, the reader
+    // indexes them from the first column of the range
+    let first_column = if cursor.function.is_some() {
+        let columns = match manager.get_column_span() {
+            Ok(columns) => columns,
+            Err(err) => return set_vtab_error(cursor.base.pVtab, err),
+        };
+        // the columns past the last one of the table function would be dropped
+        if let Some((_, last)) = columns.filter(|columns| columns.1 as usize >= xlite::COLUMNS) {
+            let err = DataManagerError::Unsupported(format!(
+                "The range ends at column {}, past the last column {} of xlite(...)",
+                CellIndex::new(last + 1, 1).get_x_as_string(),
+                CellIndex::new(xlite::COLUMNS as u32, 1).get_x_as_string()
+            ));
+            return set_vtab_error(cursor.base.pVtab, err);
+        }
+        columns.map(|columns| columns.0).unwrap_or(0)
+    } else {
+        0
+    };
+    if let Some((_, first)) = cursor.function.as_mut() {
+        *first = first_column;
+    }
+
+    let constraints = plan
+        .into_iter()
+        .zip(args.iter())
+        .filter_map(|((target, op), value)| {
+            let target = match target {
+                ConstraintTarget::Column(column) => {
+                    ConstraintTarget::Column(column.checked_sub(first_column as usize)?)
+                }
+                target => target,
+            };
+            read_value(sqlite3_api, *value).map(|value| Constraint::new(target, op, value))
+        })
+        .collect();
+
+    let reader = manager.read(constraints);
+    for warning in manager.take_warnings() {
+        log_warning(sqlite3_api, warning);
+    }
+    let mut reader = match reader {
+        Ok(reader) => reader,
+        Err(err) => return set_vtab_error(cursor.base.pVtab, err),
+    };
+    if cursor.function.is_some() {
+        // the column names can't be taken from the row, so it is skipped as the header
+        if let Some(row) = manager.get_colnames_row() {
+            reader = reader.excluding(Arc::new(BTreeSet::from([row])));
+        }
+    }
+
+    let lock = Arc::clone(&cursor.reader);
+    *lock.lock().unwrap() = reader;
+
     SQLITE_OK
 }
 
@@ -278,15 +582,33 @@ unsafe extern "C" fn x_column(
     p_context: *mut sqlite3_context,
     column: c_int,
 ) -> c_int {
+    // the value is not needed when an UPDATE leaves the column unchanged
+    if ((*sqlite3_api).vtab_nochange.unwrap())(p_context) != 0 {
+        return SQLITE_OK;
+    }
+
     let cursor = &mut *(p_cursor as *mut VirtualCursor);
     let lock = Arc::clone(&cursor.reader);
     let reader = lock.lock().unwrap();
 
-    let value = reader.get_value(column as usize);
+    let value = match &cursor.function {
+        Some((parameters, _)) if column as usize >= xlite::COLUMNS => {
+            Ok(parameters.get(column as usize - xlite::COLUMNS).map(Cow::Borrowed))
+        }
+        Some((_, first)) => match (column as usize).checked_sub(*first as usize) {
+            Some(i) => reader.get_converted_value(i),
+            None => Ok(None),
+        },
+        None => reader.get_converted_value(column as usize),
+    };
+    let value = match value {
+        Ok(value) => value,
+        Err(err) => return set_error_message(sqlite3_api, cursor.base.pVtab, err),
+    };
     yield_result(
         p_context,
         sqlite3_api,
-        match value {
+        match &value {
             Some(data) => data,
             None => &DataType::Empty,
         },
@@ -308,3 +630,234 @@ unsafe extern "C" fn x_rowid(
 
     SQLITE_OK
 }
+
+#[no_mangle]
+unsafe extern "C" fn x_update(
+    p_vtab: *mut sqlite3_vtab,
+    argc: c_int,
+    argv: *mut *mut sqlite3_value,
+    p_rowid: *mut sqlite3_int64,
+) -> c_int {
+    let table = &mut *(p_vtab as *mut VirtualTable);
+    let args = std::slice::from_raw_parts(argv, argc as usize);
+
+    let manager = match &table.manager {
+        Some(manager) => Arc::clone(manager),
+        None => {
+            return set_vtab_error(
+                p_vtab,
+                DataManagerError::Unsupported("xlite() is read-only".to_string()),
+            )
+        }
+    };
+
+    if manager.lock().unwrap().is_snapshot() {
+        return match read_command(args) {
+            Some(command) => run_command(table, command.as_str()),
+            None => set_vtab_error(
+                p_vtab,
+                DataManagerError::Unsupported("Snapshot tables are read-only".to_string()),
+            ),
+        };
+    }
+
+    // DELETE: argv[0] is the rowid of the row to delete
+    if argc == 1 {
+        let result = match read_rowid(args[0]) {
+            Some(rowid) => manager.lock().unwrap().delete(rowid),
+            None => return SQLITE_OK,
+        };
+        return match result {
+            Ok(_) => SQLITE_OK,
+            Err(err) => set_vtab_error(p_vtab, err),
+        };
+    }
+
+    let old_rowid = read_rowid(args[0]);
+    let new_rowid = read_rowid(args[1]);
+
+    let mut values = Vec::with_capacity(args.len() - 2);
+    for value in &args[2..] {
+        // columns not changed by an UPDATE are reported by x_column as "no change"
+        if old_rowid.is_some() && ((*sqlite3_api).value_nochange.unwrap())(*value) != 0 {
+            values.push(None);
+            continue;
+        }
+        match read_value(sqlite3_api, *value) {
+            Some(value) => values.push(Some(value)),
+            None => {
+                return set_vtab_error(
+                    p_vtab,
+                    DataManagerError::Unsupported("BLOB values are not supported".to_string()),
+                )
+            }
+        }
+    }
+
+    match old_rowid {
+        // INSERT: argv[0] is NULL, argv[1] is the requested rowid
+        None => {
+            if new_rowid.is_some() {
+                return set_vtab_error(
+                    p_vtab,
+                    DataManagerError::Unsupported("Rowid can not be set explicitly".to_string()),
+                );
+            }
+
+            let values = values.into_iter().map(|v| v.unwrap_or_default()).collect();
+            let result = manager.lock().unwrap().insert(values);
+            match result {
+                Ok(rowid) => {
+                    *p_rowid = rowid as sqlite3_int64;
+                    SQLITE_OK
+                }
+                Err(err) => set_vtab_error(p_vtab, err),
+            }
+        }
+        // UPDATE: argv[0] is the old rowid, argv[1] is the new one
+        Some(rowid) => {
+            if new_rowid != Some(rowid) {
+                return set_vtab_error(
+                    p_vtab,
+                    DataManagerError::Unsupported("Rowid can not be changed".to_string()),
+                );
+            }
+
+            let result = manager.lock().unwrap().update(rowid, values);
+            match result {
+                Ok(_) => SQLITE_OK,
+                Err(err) => set_vtab_error(p_vtab, err),
+            }
+        }
+    }
+}
+
+/// Reads the value of the hidden command column of INSERT INTO t(t) VALUES('command').
+unsafe fn read_command(args: &[*mut sqlite3_value]) -> Option<String> {
+    if args.len() < 3 || read_value(sqlite3_api, args[0]) != Some(DataType::Empty) {
+        return None;
+    }
+    match read_value(sqlite3_api, args[args.len() - 1]) {
+        Some(DataType::String(command)) => Some(command),
+        _ => None,
+    }
+}
+
+unsafe fn run_command(table: &mut VirtualTable, command: &str) -> c_int {
+    let p_vtab = table as *mut VirtualTable as *mut sqlite3_vtab;
+
+    if !command.eq_ignore_ascii_case("refresh") {
+        return set_vtab_error(
+            p_vtab,
+            DataManagerError::Unsupported(format!("Unknown command '{}'", command)),
+        );
+    }
+
+    let manager = match &table.manager {
+        Some(manager) => Arc::clone(manager),
+        None => return SQLITE_OK,
+    };
+    let mut manager = manager.lock().unwrap();
+
+    let range = match manager.read_worksheet_range() {
+        Ok(range) => range,
+        Err(err) => return set_vtab_error(p_vtab, err),
+    };
+    let stored = store_snapshot(
+        table.db,
+        sqlite3_api,
+        &table.schema,
+        &table.name,
+        manager.get_file(),
+        &manager.get_source(),
+        &range,
+    );
+
+    match stored {
+        Ok(_) => {
+            manager.set_snapshot((*range).clone());
+            SQLITE_OK
+        }
+        Err(err) => set_vtab_error(p_vtab, DataManagerError::Snapshot(err)),
+    }
+}
+
+unsafe fn read_rowid(value: *mut sqlite3_value) -> Option<u32> {
+    match read_value(sqlite3_api, value) {
+        Some(DataType::Int(n)) => u32::try_from(n).ok(),
+        _ => None,
+    }
+}
+
+#[no_mangle]
+unsafe extern "C" fn x_begin(_p_vtab: *mut sqlite3_vtab) -> c_int {
+    SQLITE_OK
+}
+
+#[no_mangle]
+unsafe extern "C" fn x_sync(p_vtab: *mut sqlite3_vtab) -> c_int {
+    let table = &mut *(p_vtab as *mut VirtualTable);
+    let result = match &table.manager {
+        Some(manager) => manager.lock().unwrap().sync(),
+        None => Ok(()),
+    };
+    match result {
+        Ok(_) => SQLITE_OK,
+        Err(err) => set_vtab_error(p_vtab, err),
+    }
+}
+
+#[no_mangle]
+unsafe extern "C" fn x_commit(p_vtab: *mut sqlite3_vtab) -> c_int {
+    let table = &mut *(p_vtab as *mut VirtualTable);
+    if let Some(manager) = &table.manager {
+        manager.lock().unwrap().commit();
+    }
+
+    SQLITE_OK
+}
+
+#[no_mangle]
+unsafe extern "C" fn x_rollback(p_vtab: *mut sqlite3_vtab) -> c_int {
+    let table = &mut *(p_vtab as *mut VirtualTable);
+    if let Some(manager) = &table.manager {
+        manager.lock().unwrap().rollback();
+    }
+
+    SQLITE_OK
+}
+
+#[no_mangle]
+unsafe extern "C" fn x_rename(p_vtab: *mut sqlite3_vtab, z_new: *const c_char) -> c_int {
+    let table = &mut *(p_vtab as *mut VirtualTable);
+    let new_name = read_string_from_raw(z_new);
+
+    if is_snapshot(table) {
+        if let Err(err) = rename_snapshot(table.db, sqlite3_api, &table.schema, &table.name, &new_name) {
+            return set_vtab_error(p_vtab, DataManagerError::Snapshot(err));
+        }
+    }
+    table.name = new_name;
+
+    SQLITE_OK
+}
+
+fn is_snapshot(table: &VirtualTable) -> bool {
+    match &table.manager {
+        Some(manager) => manager.lock().unwrap().is_snapshot(),
+        None => false,
+    }
+}
+
+#[no_mangle]
+unsafe extern "C" fn x_shadow_name(name: *const c_char) -> c_int {
+    if is_shadow_name(name) {
+        1
+    } else {
+        0
+    }
+}
+
+unsafe fn set_vtab_error(p_vtab: *mut sqlite3_vtab, err: DataManagerError) -> c_int {
+    set_error_message(sqlite3_api, p_vtab, err.to_string())
+}
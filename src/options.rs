@@ -1,23 +1,86 @@
 use nom::branch::alt;
-use nom::bytes::complete::{escaped, tag, tag_no_case};
-use nom::character::complete::{alpha1, digit0, digit1, multispace0, multispace1, none_of};
-use nom::combinator::{map, recognize};
+use nom::bytes::complete::{escaped, is_not, tag, tag_no_case};
+use nom::character::complete::{alpha1, digit1, multispace0, multispace1, none_of};
+use nom::combinator::{map, opt, recognize, verify};
+use nom::multi::many1;
 use nom::{IResult, Parser};
 use nom::sequence::{delimited, preceded, separated_pair, terminated, tuple};
 
 pub enum UsingOption {
     File(String),
     Worksheet(String),
+    Worksheets(String),
+    SheetIndex(String),
     Range(String),
+    Auto(bool),
+    Name(String),
+    Table(String),
     ColNames(String),
+    Snapshot(bool),
+    Provenance(bool),
+    Formulas(bool),
+    Merged(String),
+    FillDown(String),
+    SkipEmpty(bool),
+    Trim(bool),
+    UntilBlank(bool),
+    Sample(String),
+    Types(String),
+    Schema(String),
+    TypeErrors(String),
+    FileErrors(String),
+    Dates(String),
+    Errors(String),
+    Normalize(String),
+    Header(String),
+    HeaderSeparator(String),
+    /// An option with a mistyped value, e.g. `MERGED 'fil'`, reported when the table is created.
+    Invalid(String, String),
 }
 
+/// Options taking a flag, a number or one of a few values.
+const CHECKED_OPTIONS: &[&str] = &[
+    "SHEET_INDEX", "AUTO", "COLNAMES", "HEADER", "SNAPSHOT", "PROVENANCE", "FORMULAS", "MERGED",
+    "SKIP_EMPTY", "TRIM", "UNTIL_BLANK", "SAMPLE", "TYPE_ERRORS", "FILE_ERRORS", "DATES", "ERRORS",
+    "NORMALIZE",
+];
+
 pub fn parse_option(input: &str) -> IResult<&str, UsingOption> {
+    // alt takes at most 21 parsers, the options are grouped
     parse_with_spaces(alt((
-        parse_filename_option,
-        parse_worksheet_option,
-        parse_range_option,
-        parse_colnames_option,
+        alt((
+            parse_filename_option,
+            parse_worksheet_option,
+            parse_worksheets_option,
+            parse_sheet_index_option,
+            parse_range_option,
+            parse_auto_option,
+            parse_name_option,
+            parse_table_option,
+            parse_colnames_option,
+            parse_snapshot_option,
+            parse_provenance_option,
+            parse_formulas_option,
+            parse_merged_option,
+            parse_filldown_option,
+            )),
+        alt((
+            parse_sample_option,
+            parse_types_option,
+            parse_schema_option,
+            parse_type_errors_option,
+            parse_file_errors_option,
+            parse_dates_option,
+            parse_errors_option,
+            parse_normalize_option,
+            parse_header_separator_option,
+            parse_header_option,
+            parse_skip_empty_option,
+            parse_trim_option,
+            parse_until_blank_option,
+            // anything else is kept to be reported as an invalid value
+            parse_invalid_option,
+            )),
         ))).parse(input)
 }
 
@@ -37,18 +100,77 @@ fn parse_worksheet_option(input: &str) -> IResult<&str, UsingOption> {
         |(_, n)| UsingOption::Worksheet(n.to_string()))(input)
 }
 
-fn parse_range_option(input: &str) -> IResult<&str, UsingOption> {
-    let option = tag_no_case("RANGE");
+fn parse_worksheets_option(input: &str) -> IResult<&str, UsingOption> {
+    let option = tag_no_case("WORKSHEETS");
+    let list = parse_quoted;
+
+    map(separated_pair(option, multispace1, list),
+        |(_, l)| UsingOption::Worksheets(l.to_string()))(input)
+}
 
-    let range = recognize(
-        tuple((alpha1, digit0, tag(":"), alpha1, digit0))
-    );
+fn parse_sheet_index_option(input: &str) -> IResult<&str, UsingOption> {
+    let option = tag_no_case("SHEET_INDEX");
 
     let value = preceded(
-        tag("'"), terminated(range, tag("'")));
+        tag("'"), terminated(digit1, tag("'")));
 
     map(separated_pair(option, multispace1, value),
-        |t: (&str, &str)| UsingOption::Range(t.1.to_string()))(input)
+        |t: (&str, &str)| UsingOption::SheetIndex(t.1.to_string()))(input)
+}
+
+fn parse_range_option(input: &str) -> IResult<&str, UsingOption> {
+    let option = tag_no_case("RANGE");
+
+    // 'A1:F10' or 'Sheet 1!A1:F10'
+    let plain = map(
+        delimited(
+            tag("'"),
+            recognize(tuple((opt(terminated(is_not("'!"), tag("!"))), parse_cell_range))),
+            tag("'")),
+        |r: &str| r.to_string());
+    // Excel's 'Sheet 1'!A1:F10, written '''Sheet 1''!A1:F10' with the quotes doubled
+    let sheet_quoted = map(
+        delimited(
+            tag("'"),
+            recognize(tuple((parse_sheet_name, tag("!"), parse_cell_range))),
+            tag("'")),
+        |r: &str| r.replace("''", "'"));
+    // anything else is kept to be reported as an invalid range
+    let invalid = map(parse_quoted, |r: &str| r.to_string());
+
+    map(separated_pair(option, multispace1, alt((sheet_quoted, plain, invalid))),
+        |(_, r)| UsingOption::Range(r))(input)
+}
+
+fn parse_cell_range(input: &str) -> IResult<&str, &str> {
+    recognize(tuple((parse_cell_reference, opt(preceded(tag(":"), opt(parse_cell_reference))))))(input)
+}
+
+fn parse_cell_reference(input: &str) -> IResult<&str, &str> {
+    let row = || tuple((opt(tag("$")), digit1));
+    let column = tuple((opt(tag("$")), alpha1, opt(row())));
+
+    recognize(alt((recognize(column), recognize(row()))))(input)
+}
+
+fn parse_sheet_name(input: &str) -> IResult<&str, &str> {
+    recognize(delimited(tag("''"), many1(alt((is_not("'"), tag("''''")))), tag("''")))(input)
+}
+
+fn parse_name_option(input: &str) -> IResult<&str, UsingOption> {
+    let option = tag_no_case("NAME");
+    let name = parse_quoted;
+
+    map(separated_pair(option, multispace1, name),
+        |(_, n)| UsingOption::Name(n.to_string()))(input)
+}
+
+fn parse_table_option(input: &str) -> IResult<&str, UsingOption> {
+    let option = tag_no_case("TABLE");
+    let table = parse_quoted;
+
+    map(separated_pair(option, multispace1, table),
+        |(_, t)| UsingOption::Table(t.to_string()))(input)
 }
 
 fn parse_colnames_option(input: &str) -> IResult<&str, UsingOption> {
@@ -61,6 +183,191 @@ fn parse_colnames_option(input: &str) -> IResult<&str, UsingOption> {
         |t: (&str, &str)| UsingOption::ColNames(t.1.to_string()))(input)
 }
 
+fn parse_header_option(input: &str) -> IResult<&str, UsingOption> {
+    let option = tag_no_case("HEADER");
+
+    let value = preceded(
+        tag("'"), terminated(digit1, tag("'")));
+
+    map(separated_pair(option, multispace1, value),
+        |t: (&str, &str)| UsingOption::Header(t.1.to_string()))(input)
+}
+
+fn parse_header_separator_option(input: &str) -> IResult<&str, UsingOption> {
+    let option = tag_no_case("HEADER_SEPARATOR");
+    let separator = parse_quoted;
+
+    map(separated_pair(option, multispace1, separator),
+        |(_, s)| UsingOption::HeaderSeparator(s.to_string()))(input)
+}
+
+fn parse_snapshot_option(input: &str) -> IResult<&str, UsingOption> {
+    let option = tag_no_case("SNAPSHOT");
+    let value = parse_flag;
+
+    map(separated_pair(option, multispace1, value),
+        |(_, v)| UsingOption::Snapshot(v))(input)
+}
+
+fn parse_auto_option(input: &str) -> IResult<&str, UsingOption> {
+    let option = tag_no_case("AUTO");
+    let value = parse_flag;
+
+    map(separated_pair(option, multispace1, value),
+        |(_, v)| UsingOption::Auto(v))(input)
+}
+
+fn parse_provenance_option(input: &str) -> IResult<&str, UsingOption> {
+    let option = tag_no_case("PROVENANCE");
+    let value = parse_flag;
+
+    map(separated_pair(option, multispace1, value),
+        |(_, v)| UsingOption::Provenance(v))(input)
+}
+
+fn parse_formulas_option(input: &str) -> IResult<&str, UsingOption> {
+    let option = tag_no_case("FORMULAS");
+    let value = parse_flag;
+
+    map(separated_pair(option, multispace1, value),
+        |(_, v)| UsingOption::Formulas(v))(input)
+}
+
+fn parse_merged_option(input: &str) -> IResult<&str, UsingOption> {
+    let option = tag_no_case("MERGED");
+    let policy = alt((tag_no_case("fill"), tag_no_case("keep")));
+
+    let value = delimited(tag("'"), policy, tag("'"));
+
+    map(separated_pair(option, multispace1, value),
+        |(_, p): (&str, &str)| UsingOption::Merged(p.to_string()))(input)
+}
+
+fn parse_filldown_option(input: &str) -> IResult<&str, UsingOption> {
+    let option = tag_no_case("FILLDOWN");
+    let columns = parse_quoted;
+
+    map(separated_pair(option, multispace1, columns),
+        |(_, c)| UsingOption::FillDown(c.to_string()))(input)
+}
+
+fn parse_skip_empty_option(input: &str) -> IResult<&str, UsingOption> {
+    let option = tag_no_case("SKIP_EMPTY");
+    let value = parse_flag;
+
+    map(separated_pair(option, multispace1, value),
+        |(_, v)| UsingOption::SkipEmpty(v))(input)
+}
+
+fn parse_trim_option(input: &str) -> IResult<&str, UsingOption> {
+    let option = tag_no_case("TRIM");
+    let value = parse_flag;
+
+    map(separated_pair(option, multispace1, value),
+        |(_, v)| UsingOption::Trim(v))(input)
+}
+
+fn parse_until_blank_option(input: &str) -> IResult<&str, UsingOption> {
+    let option = tag_no_case("UNTIL_BLANK");
+    let value = parse_flag;
+
+    map(separated_pair(option, multispace1, value),
+        |(_, v)| UsingOption::UntilBlank(v))(input)
+}
+
+fn parse_sample_option(input: &str) -> IResult<&str, UsingOption> {
+    let option = tag_no_case("SAMPLE");
+
+    let value = preceded(
+        tag("'"), terminated(digit1, tag("'")));
+
+    map(separated_pair(option, multispace1, value),
+        |t: (&str, &str)| UsingOption::Sample(t.1.to_string()))(input)
+}
+
+fn parse_types_option(input: &str) -> IResult<&str, UsingOption> {
+    let option = tag_no_case("TYPES");
+    let types = parse_quoted;
+
+    map(separated_pair(option, multispace1, types),
+        |(_, t)| UsingOption::Types(t.to_string()))(input)
+}
+
+fn parse_schema_option(input: &str) -> IResult<&str, UsingOption> {
+    let option = tag_no_case("SCHEMA");
+    let schema = parse_quoted;
+
+    map(separated_pair(option, multispace1, schema),
+        |(_, s)| UsingOption::Schema(s.to_string()))(input)
+}
+
+fn parse_type_errors_option(input: &str) -> IResult<&str, UsingOption> {
+    let option = tag_no_case("TYPE_ERRORS");
+    let policy = alt((tag_no_case("null"), tag_no_case("keep"), tag_no_case("fail")));
+
+    let value = delimited(tag("'"), policy, tag("'"));
+
+    map(separated_pair(option, multispace1, value),
+        |(_, p): (&str, &str)| UsingOption::TypeErrors(p.to_string()))(input)
+}
+
+fn parse_dates_option(input: &str) -> IResult<&str, UsingOption> {
+    let option = tag_no_case("DATES");
+    let format = alt((tag_no_case("serial"), tag_no_case("iso"), tag_no_case("julian"), tag_no_case("unix")));
+
+    let value = delimited(tag("'"), format, tag("'"));
+
+    map(separated_pair(option, multispace1, value),
+        |(_, f): (&str, &str)| UsingOption::Dates(f.to_string()))(input)
+}
+
+fn parse_file_errors_option(input: &str) -> IResult<&str, UsingOption> {
+    let option = tag_no_case("FILE_ERRORS");
+    let policy = alt((tag_no_case("fail"), tag_no_case("skip")));
+
+    let value = delimited(tag("'"), policy, tag("'"));
+
+    map(separated_pair(option, multispace1, value),
+        |(_, p): (&str, &str)| UsingOption::FileErrors(p.to_string()))(input)
+}
+
+fn parse_errors_option(input: &str) -> IResult<&str, UsingOption> {
+    let option = tag_no_case("ERRORS");
+    let policy = alt((tag_no_case("null"), tag_no_case("text"), tag_no_case("fail")));
+
+    let value = delimited(tag("'"), policy, tag("'"));
+
+    map(separated_pair(option, multispace1, value),
+        |(_, p): (&str, &str)| UsingOption::Errors(p.to_string()))(input)
+}
+
+fn parse_normalize_option(input: &str) -> IResult<&str, UsingOption> {
+    let option = tag_no_case("NORMALIZE");
+    let style = alt((tag_no_case("raw"), tag_no_case("trim"), tag_no_case("lower"), tag_no_case("snake")));
+
+    let value = delimited(tag("'"), style, tag("'"));
+
+    map(separated_pair(option, multispace1, value),
+        |(_, s): (&str, &str)| UsingOption::Normalize(s.to_string()))(input)
+}
+
+fn parse_invalid_option(input: &str) -> IResult<&str, UsingOption> {
+    let option = verify(
+        recognize(many1(alt((alpha1, tag("_"))))),
+        |o: &str| CHECKED_OPTIONS.iter().any(|checked| checked.eq_ignore_ascii_case(o)));
+    let value = parse_quoted;
+
+    map(separated_pair(option, multispace1, value),
+        |(o, v): (&str, &str)| UsingOption::Invalid(o.to_uppercase(), v.to_string()))(input)
+}
+
+fn parse_flag(input: &str) -> IResult<&str, bool> {
+    let yes = map(alt((tag_no_case("yes"), tag_no_case("true"), tag_no_case("on"), tag("1"))), |_| true);
+    let no = map(alt((tag_no_case("no"), tag_no_case("false"), tag_no_case("off"), tag("0"))), |_| false);
+
+    delimited(tag("'"), alt((yes, no)), tag("'"))(input)
+}
+
 fn parse_with_spaces<'a, T>(parser: impl Parser<&'a str, T, nom::error::Error<&'a str>>)
     -> impl Parser<&'a str, T, nom::error::Error<&'a str>> {
     preceded(multispace0, terminated(parser, multispace0))
@@ -109,6 +416,17 @@ mod tests {
         }
     }
 
+    #[test]
+    fn parse_worksheets_option_produces_list() {
+        let (output, option) = parse_option("WORKSHEETS '2024-*, Summary'").unwrap();
+
+        assert_eq!(output, "");
+        match option {
+            UsingOption::Worksheets(list) => assert_eq!(list, "2024-*, Summary"),
+            _ => panic!("Expected worksheets option")
+        }
+    }
+
     #[test]
     fn parse_sheet_option_produces_sheet_name() {
         let (output, option) = parse_worksheet_option("SHEET 'Sheet 1'").unwrap();
@@ -133,6 +451,282 @@ mod tests {
         }
     }
 
+    #[test]
+    fn parse_range_option_accepts_excel_references() {
+        for (input, expected) in [
+            ("RANGE '$A$1:$F$100'", "$A$1:$F$100"),
+            ("RANGE 'a1:f10'", "a1:f10"),
+            ("RANGE 'A5:'", "A5:"),
+            ("RANGE 'B:D'", "B:D"),
+            ("RANGE '2:10'", "2:10"),
+            ("RANGE 'Sheet 1!A1:F'", "Sheet 1!A1:F"),
+            ("RANGE '''Sheet 1''!A1:F'", "'Sheet 1'!A1:F"),
+            ("RANGE '''Bob''''s''!B2'", "'Bob''s'!B2"),
+            ("RANGE 'A1-F10'", "A1-F10"),
+        ] {
+            let (output, option) = parse_range_option(input).unwrap();
+
+            assert_eq!(output, "");
+            match option {
+                UsingOption::Range(range) => assert_eq!(range, expected),
+                _ => panic!("Expected range option")
+            }
+        }
+        assert!(parse_range_option("RANGE A1:F10").is_err());
+    }
+
+    #[test]
+    fn parse_sheet_index_option_produces_index() {
+        let (output, option) = parse_option("SHEET_INDEX '1'").unwrap();
+
+        assert_eq!(output, "");
+        match option {
+            UsingOption::SheetIndex(index) => assert_eq!(index, "1"),
+            _ => panic!("Expected sheet index option")
+        }
+    }
+
+    #[test]
+    fn parse_name_option_produces_name() {
+        let (output, option) = parse_name_option("NAME 'Q3_Actuals'").unwrap();
+
+        assert_eq!(output, "");
+        match option {
+            UsingOption::Name(name) => assert_eq!(name, "Q3_Actuals"),
+            _ => panic!("Expected name option")
+        }
+    }
+
+    #[test]
+    fn parse_table_option_produces_table() {
+        let (output, option) = parse_table_option("TABLE 'SalesTable'").unwrap();
+
+        assert_eq!(output, "");
+        match option {
+            UsingOption::Table(table) => assert_eq!(table, "SalesTable"),
+            _ => panic!("Expected table option")
+        }
+    }
+
+    #[test]
+    fn parse_snapshot_option_produces_flag() {
+        let (output, option) = parse_snapshot_option("SNAPSHOT 'yes'").unwrap();
+
+        assert_eq!(output, "");
+        match option {
+            UsingOption::Snapshot(snapshot) => assert!(snapshot),
+            _ => panic!("Expected snapshot option")
+        }
+    }
+
+    #[test]
+    fn parse_auto_option_produces_flag() {
+        let (output, option) = parse_option("AUTO 'yes'").unwrap();
+
+        assert_eq!(output, "");
+        match option {
+            UsingOption::Auto(auto) => assert!(auto),
+            _ => panic!("Expected auto option")
+        }
+    }
+
+    #[test]
+    fn parse_provenance_option_produces_flag() {
+        let (output, option) = parse_provenance_option("PROVENANCE 'yes'").unwrap();
+
+        assert_eq!(output, "");
+        match option {
+            UsingOption::Provenance(provenance) => assert!(provenance),
+            _ => panic!("Expected provenance option")
+        }
+    }
+
+    #[test]
+    fn parse_formulas_option_produces_flag() {
+        let (output, option) = parse_option("FORMULAS 'yes'").unwrap();
+
+        assert_eq!(output, "");
+        match option {
+            UsingOption::Formulas(formulas) => assert!(formulas),
+            _ => panic!("Expected formulas option")
+        }
+    }
+
+    #[test]
+    fn parse_merged_option_produces_policy() {
+        let (output, option) = parse_option("MERGED 'fill'").unwrap();
+
+        assert_eq!(output, "");
+        match option {
+            UsingOption::Merged(policy) => assert_eq!(policy, "fill"),
+            _ => panic!("Expected merged option")
+        }
+    }
+
+    #[test]
+    fn parse_blank_rows_options_produce_flags() {
+        match parse_option("SKIP_EMPTY 'yes'").unwrap().1 {
+            UsingOption::SkipEmpty(skip) => assert!(skip),
+            _ => panic!("Expected skip empty option")
+        }
+        match parse_option("TRIM 'no'").unwrap().1 {
+            UsingOption::Trim(trim) => assert!(!trim),
+            _ => panic!("Expected trim option")
+        }
+        match parse_option("UNTIL_BLANK 'on'").unwrap().1 {
+            UsingOption::UntilBlank(until_blank) => assert!(until_blank),
+            _ => panic!("Expected until blank option")
+        }
+    }
+
+    #[test]
+    fn parse_filldown_option_produces_columns() {
+        let (output, option) = parse_option("FILLDOWN 'A,C'").unwrap();
+
+        assert_eq!(output, "");
+        match option {
+            UsingOption::FillDown(columns) => assert_eq!(columns, "A,C"),
+            _ => panic!("Expected filldown option")
+        }
+    }
+
+    #[test]
+    fn parse_sample_option_produces_row_count() {
+        let (output, option) = parse_sample_option("SAMPLE '250'").unwrap();
+
+        assert_eq!(output, "");
+        match option {
+            UsingOption::Sample(rows) => assert_eq!(rows, "250"),
+            _ => panic!("Expected sample option")
+        }
+    }
+
+    #[test]
+    fn parse_types_option_produces_types() {
+        let (output, option) = parse_types_option("TYPES 'INTEGER,TEXT,REAL'").unwrap();
+
+        assert_eq!(output, "");
+        match option {
+            UsingOption::Types(types) => assert_eq!(types, "INTEGER,TEXT,REAL"),
+            _ => panic!("Expected types option")
+        }
+    }
+
+    #[test]
+    fn parse_schema_option_produces_schema() {
+        let (output, option) = parse_schema_option("SCHEMA 'CREATE TABLE x(id INTEGER)'").unwrap();
+
+        assert_eq!(output, "");
+        match option {
+            UsingOption::Schema(schema) => assert_eq!(schema, "CREATE TABLE x(id INTEGER)"),
+            _ => panic!("Expected schema option")
+        }
+    }
+
+    #[test]
+    fn parse_type_errors_option_produces_policy() {
+        let (output, option) = parse_type_errors_option("TYPE_ERRORS 'fail'").unwrap();
+
+        assert_eq!(output, "");
+        match option {
+            UsingOption::TypeErrors(policy) => assert_eq!(policy, "fail"),
+            _ => panic!("Expected type errors option")
+        }
+        assert!(parse_type_errors_option("TYPE_ERRORS 'ignore'").is_err());
+    }
+
+    #[test]
+    fn parse_dates_option_produces_format() {
+        let (output, option) = parse_dates_option("DATES 'ISO'").unwrap();
+
+        assert_eq!(output, "");
+        match option {
+            UsingOption::Dates(format) => assert_eq!(format, "ISO"),
+            _ => panic!("Expected dates option")
+        }
+    }
+
+    #[test]
+    fn parse_file_errors_option_produces_policy() {
+        let (output, option) = parse_option("FILE_ERRORS 'skip'").unwrap();
+
+        assert_eq!(output, "");
+        match option {
+            UsingOption::FileErrors(policy) => assert_eq!(policy, "skip"),
+            _ => panic!("Expected file errors option")
+        }
+    }
+
+    #[test]
+    fn parse_errors_option_produces_policy() {
+        let (output, option) = parse_errors_option("ERRORS 'text'").unwrap();
+
+        assert_eq!(output, "");
+        match option {
+            UsingOption::Errors(policy) => assert_eq!(policy, "text"),
+            _ => panic!("Expected errors option")
+        }
+    }
+
+    #[test]
+    fn parse_normalize_option_produces_style() {
+        let (output, option) = parse_normalize_option("NORMALIZE 'snake'").unwrap();
+
+        assert_eq!(output, "");
+        match option {
+            UsingOption::Normalize(style) => assert_eq!(style, "snake"),
+            _ => panic!("Expected normalize option")
+        }
+        assert!(parse_normalize_option("NORMALIZE 'upper'").is_err());
+    }
+
+    #[test]
+    fn parse_header_option_produces_rows() {
+        let (output, option) = parse_option("HEADER '2'").unwrap();
+
+        assert_eq!(output, "");
+        match option {
+            UsingOption::Header(rows) => assert_eq!(rows, "2"),
+            _ => panic!("Expected header option")
+        }
+    }
+
+    #[test]
+    fn parse_header_separator_option_produces_separator() {
+        let (output, option) = parse_option("HEADER_SEPARATOR ' - '").unwrap();
+
+        assert_eq!(output, "");
+        match option {
+            UsingOption::HeaderSeparator(separator) => assert_eq!(separator, " - "),
+            _ => panic!("Expected header separator option")
+        }
+    }
+
+    #[test]
+    fn parse_option_keeps_invalid_values() {
+        for (input, expected) in [
+            ("MERGED 'fil'", ("MERGED", "fil")),
+            ("dates 'iso8601'", ("DATES", "iso8601")),
+            ("FORMULAS 'maybe'", ("FORMULAS", "maybe")),
+            ("SAMPLE 'all'", ("SAMPLE", "all")),
+        ] {
+            let (output, option) = parse_option(input).unwrap();
+
+            assert_eq!(output, "");
+            match option {
+                UsingOption::Invalid(option, value) => assert_eq!((option.as_str(), value.as_str()), expected),
+                _ => panic!("Expected invalid option")
+            }
+        }
+        assert!(parse_option("COLOR 'red'").is_err());
+    }
+
+    #[test]
+    fn parse_flag_rejects_unknown_values() {
+        assert!(parse_flag("'maybe'").is_err());
+        assert!(!parse_flag("'OFF'").unwrap().1);
+    }
+
     #[test]
     fn parse_colnames_option_produces_colname() {
         let (output, option) = parse_colnames_option("COLNAMES '152'").unwrap();
@@ -0,0 +1,457 @@
+use crate::sqlite::{
+    sqlite3, sqlite3_api_routines, sqlite3_stmt, SQLITE_DONE, SQLITE_FLOAT, SQLITE_INTEGER,
+    SQLITE_OK, SQLITE_ROW, SQLITE_TEXT,
+};
+use crate::spreadsheet::manager::TableSource;
+use calamine::{Cell, CellErrorType, DataType, Range};
+use std::ffi::{CStr, CString};
+use std::io::Read;
+use std::os::raw::{c_char, c_int};
+use std::path::Path;
+use std::time::UNIX_EPOCH;
+
+/// Suffixes of the shadow tables owned by a virtual table created with SNAPSHOT.
+pub const SHADOW_NAMES: [&str; 2] = ["snapshot", "source"];
+
+/// Copies the cells of the worksheet into new shadow tables.
+pub unsafe fn create_snapshot(
+    db: *mut sqlite3,
+    api: *mut sqlite3_api_routines,
+    schema: &str,
+    name: &str,
+    file: &Path,
+    table: &TableSource,
+    range: &Range<DataType>,
+) -> Result<(), String> {
+    execute(db, api, format!(
+        "CREATE TABLE IF NOT EXISTS {}(row INTEGER, col INTEGER, kind TEXT, value, PRIMARY KEY(row, col)) WITHOUT ROWID",
+        shadow_table(schema, name, "snapshot")
+    ).as_str())?;
+    execute(db, api, format!(
+        "CREATE TABLE IF NOT EXISTS {}(key TEXT PRIMARY KEY, value) WITHOUT ROWID",
+        shadow_table(schema, name, "source")
+    ).as_str())?;
+
+    store_snapshot(db, api, schema, name, file, table, range)
+}
+
+/// Replaces the content of the shadow tables with the cells of the worksheet, where
+/// they were read from and the size, modification time and checksum of the file.
+pub unsafe fn store_snapshot(
+    db: *mut sqlite3,
+    api: *mut sqlite3_api_routines,
+    schema: &str,
+    name: &str,
+    file: &Path,
+    table: &TableSource,
+    range: &Range<DataType>,
+) -> Result<(), String> {
+    let snapshot = shadow_table(schema, name, "snapshot");
+    let source = shadow_table(schema, name, "source");
+
+    execute(db, api, format!("DELETE FROM {}", snapshot).as_str())?;
+    execute(db, api, format!("DELETE FROM {}", source).as_str())?;
+
+    let mut stmt = Statement::prepare(db, api, format!(
+        "INSERT INTO {}(row, col, kind, value) VALUES (?1, ?2, ?3, ?4)", snapshot
+    ).as_str())?;
+    for (row, col, value) in range.used_cells() {
+        let (row, col) = match range.start() {
+            Some(start) => (start.0 as i64 + row as i64, start.1 as i64 + col as i64),
+            None => continue,
+        };
+        stmt.bind_value(1, &DataType::Int(row));
+        stmt.bind_value(2, &DataType::Int(col));
+        stmt.bind_value(3, &DataType::String(get_kind(value).to_string()));
+        stmt.bind_value(4, value);
+        stmt.execute()?;
+    }
+
+    let metadata = std::fs::metadata(file).map_err(|e| e.to_string())?;
+    let (start, end) = match (range.start(), range.end()) {
+        (Some(start), Some(end)) => (start, end),
+        _ => ((0, 0), (0, 0)),
+    };
+
+    let mut entries = vec![
+        ("filename".to_string(), DataType::String(file.display().to_string())),
+        ("worksheet".to_string(), DataType::String(table.worksheet.clone())),
+        ("range".to_string(), table.range.clone().map_or(DataType::Empty, DataType::String)),
+        ("header_rows".to_string(), DataType::Int(table.header_rows as i64)),
+        ("size".to_string(), DataType::Int(metadata.len() as i64)),
+        ("mtime".to_string(), get_modified(&metadata)),
+        ("crc32".to_string(), DataType::Int(get_checksum(file)? as i64)),
+        ("empty".to_string(), DataType::Bool(range.is_empty())),
+        ("start_row".to_string(), DataType::Int(start.0 as i64)),
+        ("start_col".to_string(), DataType::Int(start.1 as i64)),
+        ("end_row".to_string(), DataType::Int(end.0 as i64)),
+        ("end_col".to_string(), DataType::Int(end.1 as i64)),
+    ];
+    // the column names of a TABLE, `column_1`, `column_2`, ...
+    for (i, column) in table.column_names.iter().flatten().enumerate() {
+        entries.push((format!("column_{}", i + 1), DataType::String(column.clone())));
+    }
+
+    let mut stmt = Statement::prepare(db, api, format!(
+        "INSERT INTO {}(key, value) VALUES (?1, ?2)", source
+    ).as_str())?;
+    for (key, value) in entries {
+        stmt.bind_value(1, &DataType::String(key));
+        stmt.bind_value(2, &value);
+        stmt.execute()?;
+    }
+
+    Ok(())
+}
+
+/// Restores the worksheet range stored in the shadow tables.
+pub unsafe fn load_snapshot(
+    db: *mut sqlite3,
+    api: *mut sqlite3_api_routines,
+    schema: &str,
+    name: &str,
+) -> Result<Range<DataType>, String> {
+    let mut stmt = Statement::prepare(db, api, format!(
+        "SELECT key, value FROM {}", shadow_table(schema, name, "source")
+    ).as_str())?;
+    let mut bounds = [0u32; 4];
+    let mut empty = true;
+    while stmt.step()? {
+        let index = match stmt.column_value(0) {
+            DataType::String(key) => match key.as_str() {
+                "start_row" => 0,
+                "start_col" => 1,
+                "end_row" => 2,
+                "end_col" => 3,
+                "empty" => {
+                    empty = stmt.column_value(1) != DataType::Int(0);
+                    continue;
+                }
+                _ => continue,
+            },
+            _ => continue,
+        };
+        if let DataType::Int(n) = stmt.column_value(1) {
+            bounds[index] = n as u32;
+        }
+    }
+
+    let mut stmt = Statement::prepare(db, api, format!(
+        "SELECT row, col, kind, value FROM {} ORDER BY row, col", shadow_table(schema, name, "snapshot")
+    ).as_str())?;
+    let mut cells = Vec::new();
+    while stmt.step()? {
+        let (row, col) = match (stmt.column_value(0), stmt.column_value(1)) {
+            (DataType::Int(row), DataType::Int(col)) => (row as u32, col as u32),
+            _ => continue,
+        };
+        let kind = match stmt.column_value(2) {
+            DataType::String(kind) => kind,
+            _ => continue,
+        };
+        cells.push(Cell::new((row, col), from_kind(kind.as_str(), stmt.column_value(3))));
+    }
+
+    let (start, end) = ((bounds[0], bounds[1]), (bounds[2], bounds[3]));
+    if empty || start > end {
+        Ok(Range::empty())
+    } else if cells.is_empty() {
+        Ok(Range::new(start, end))
+    } else {
+        Ok(Range::from_sparse(cells).range(start, end))
+    }
+}
+
+/// Restores where the cells of the snapshot were read from.
+pub unsafe fn load_source(
+    db: *mut sqlite3,
+    api: *mut sqlite3_api_routines,
+    schema: &str,
+    name: &str,
+) -> Result<TableSource, String> {
+    let mut stmt = Statement::prepare(db, api, format!(
+        "SELECT key, value FROM {}", shadow_table(schema, name, "source")
+    ).as_str())?;
+    let mut source = TableSource::default();
+    let mut columns = Vec::new();
+    while stmt.step()? {
+        let key = match stmt.column_value(0) {
+            DataType::String(key) => key,
+            _ => continue,
+        };
+        match (key.as_str(), stmt.column_value(1)) {
+            ("worksheet", DataType::String(worksheet)) => source.worksheet = worksheet,
+            ("range", DataType::String(range)) => source.range = Some(range),
+            ("header_rows", DataType::Int(n)) => source.header_rows = n as u32,
+            (key, DataType::String(column)) => {
+                if let Some(Ok(i)) = key.strip_prefix("column_").map(str::parse::<usize>) {
+                    columns.push((i, column));
+                }
+            }
+            _ => {}
+        }
+    }
+    if !columns.is_empty() {
+        columns.sort();
+        source.column_names = Some(columns.into_iter().map(|(_, column)| column).collect());
+    }
+    Ok(source)
+}
+
+/// Checks whether the file has changed since the snapshot was taken.
+///
+/// The size and modification time are compared first, the checksum only when one of them differs,
+/// so that touching the file without changing it is not reported. A missing file is not stale.
+pub unsafe fn is_snapshot_stale(
+    db: *mut sqlite3,
+    api: *mut sqlite3_api_routines,
+    schema: &str,
+    name: &str,
+    file: &Path,
+) -> Result<bool, String> {
+    let metadata = match std::fs::metadata(file) {
+        Ok(metadata) => metadata,
+        Err(_) => return Ok(false),
+    };
+
+    let mut stmt = Statement::prepare(db, api, format!(
+        "SELECT key, value FROM {} WHERE key IN ('size', 'mtime', 'crc32')", shadow_table(schema, name, "source")
+    ).as_str())?;
+    let (mut size, mut modified, mut checksum) = (DataType::Empty, DataType::Empty, DataType::Empty);
+    while stmt.step()? {
+        match stmt.column_value(0) {
+            DataType::String(key) if key == "size" => size = stmt.column_value(1),
+            DataType::String(key) if key == "mtime" => modified = stmt.column_value(1),
+            DataType::String(key) if key == "crc32" => checksum = stmt.column_value(1),
+            _ => {}
+        }
+    }
+
+    if size == DataType::Int(metadata.len() as i64) && modified == get_modified(&metadata) {
+        return Ok(false);
+    }
+    Ok(checksum != DataType::Int(get_checksum(file)? as i64))
+}
+
+/// Checks whether the shadow tables of the snapshot have already been created.
+pub unsafe fn snapshot_exists(
+    db: *mut sqlite3,
+    api: *mut sqlite3_api_routines,
+    schema: &str,
+    name: &str,
+) -> Result<bool, String> {
+    let mut stmt = Statement::prepare(db, api, format!(
+        "SELECT 1 FROM {}.sqlite_master WHERE type = 'table' AND name = ?1", quote_identifier(schema)
+    ).as_str())?;
+    stmt.bind_text(1, format!("{}_{}", name, SHADOW_NAMES[1]).as_str());
+    stmt.step()
+}
+
+pub unsafe fn drop_snapshot(
+    db: *mut sqlite3,
+    api: *mut sqlite3_api_routines,
+    schema: &str,
+    name: &str,
+) -> Result<(), String> {
+    for suffix in SHADOW_NAMES {
+        execute(db, api, format!("DROP TABLE IF EXISTS {}", shadow_table(schema, name, suffix)).as_str())?;
+    }
+    Ok(())
+}
+
+pub unsafe fn rename_snapshot(
+    db: *mut sqlite3,
+    api: *mut sqlite3_api_routines,
+    schema: &str,
+    name: &str,
+    new_name: &str,
+) -> Result<(), String> {
+    for suffix in SHADOW_NAMES {
+        execute(db, api, format!(
+            "ALTER TABLE {} RENAME TO {}",
+            shadow_table(schema, name, suffix),
+            quote_identifier(format!("{}_{}", new_name, suffix).as_str())
+        ).as_str())?;
+    }
+    Ok(())
+}
+
+pub fn quote_identifier(name: &str) -> String {
+    format!("\"{}\"", name.replace('"', "\"\""))
+}
+
+fn shadow_table(schema: &str, name: &str, suffix: &str) -> String {
+    format!(
+        "{}.{}",
+        quote_identifier(schema),
+        quote_identifier(format!("{}_{}", name, suffix).as_str())
+    )
+}
+
+fn get_modified(metadata: &std::fs::Metadata) -> DataType {
+    metadata
+        .modified()
+        .ok()
+        .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+        .map(|d| DataType::Int(d.as_secs() as i64))
+        .unwrap_or(DataType::Empty)
+}
+
+fn get_checksum(file: &Path) -> Result<u32, String> {
+    let mut file = std::fs::File::open(file).map_err(|e| e.to_string())?;
+    let mut hasher = crc32fast::Hasher::new();
+    let mut buf = [0u8; 64 * 1024];
+    loop {
+        let n = file.read(&mut buf).map_err(|e| e.to_string())?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buf[..n]);
+    }
+    Ok(hasher.finalize())
+}
+
+fn get_kind(value: &DataType) -> &'static str {
+    match value {
+        DataType::Int(_) => "int",
+        DataType::Float(_) => "float",
+        DataType::String(_) => "string",
+        DataType::Bool(_) => "bool",
+        DataType::DateTime(_) => "datetime",
+        DataType::Error(_) => "error",
+        DataType::Empty => "empty",
+    }
+}
+
+fn from_kind(kind: &str, value: DataType) -> DataType {
+    match (kind, value) {
+        ("int", DataType::Int(n)) => DataType::Int(n),
+        ("float", DataType::Float(f)) => DataType::Float(f),
+        ("float", DataType::Int(n)) => DataType::Float(n as f64),
+        ("string", DataType::String(s)) => DataType::String(s),
+        ("bool", DataType::Int(n)) => DataType::Bool(n != 0),
+        ("datetime", DataType::Float(f)) => DataType::DateTime(f),
+        ("datetime", DataType::Int(n)) => DataType::DateTime(n as f64),
+        ("error", DataType::String(s)) => DataType::Error(parse_error(s.as_str())),
+        _ => DataType::Empty,
+    }
+}
+
+fn parse_error(s: &str) -> CellErrorType {
+    match s {
+        "#DIV/0!" => CellErrorType::Div0,
+        "#N/A" => CellErrorType::NA,
+        "#NAME?" => CellErrorType::Name,
+        "#NULL!" => CellErrorType::Null,
+        "#NUM!" => CellErrorType::Num,
+        "#REF!" => CellErrorType::Ref,
+        "#DATA!" => CellErrorType::GettingData,
+        _ => CellErrorType::Value,
+    }
+}
+
+unsafe fn execute(db: *mut sqlite3, api: *mut sqlite3_api_routines, sql: &str) -> Result<(), String> {
+    let mut stmt = Statement::prepare(db, api, sql)?;
+    stmt.execute()
+}
+
+unsafe fn get_error_message(db: *mut sqlite3, api: *mut sqlite3_api_routines) -> String {
+    let msg = ((*api).errmsg.unwrap())(db);
+    if msg.is_null() {
+        "unknown error".to_string()
+    } else {
+        CStr::from_ptr(msg).to_string_lossy().into_owned()
+    }
+}
+
+/// A prepared statement, finalized when dropped.
+struct Statement {
+    db: *mut sqlite3,
+    api: *mut sqlite3_api_routines,
+    stmt: *mut sqlite3_stmt,
+    // bound strings must outlive the execution of the statement
+    texts: Vec<CString>,
+}
+
+impl Statement {
+    unsafe fn prepare(db: *mut sqlite3, api: *mut sqlite3_api_routines, sql: &str) -> Result<Self, String> {
+        let sql = CString::new(sql).map_err(|e| e.to_string())?;
+        let mut stmt = std::ptr::null_mut();
+        let result = ((*api).prepare_v2.unwrap())(db, sql.as_ptr(), -1, &mut stmt, std::ptr::null_mut());
+        if result != SQLITE_OK {
+            return Err(get_error_message(db, api));
+        }
+        Ok(Self { db, api, stmt, texts: Vec::new() })
+    }
+
+    unsafe fn bind_value(&mut self, index: c_int, value: &DataType) {
+        let api = self.api;
+        match value {
+            DataType::Int(n) => {
+                ((*api).bind_int64.unwrap())(self.stmt, index, *n);
+            }
+            DataType::Float(f) | DataType::DateTime(f) => {
+                ((*api).bind_double.unwrap())(self.stmt, index, *f);
+            }
+            DataType::Bool(b) => {
+                ((*api).bind_int64.unwrap())(self.stmt, index, if *b { 1 } else { 0 });
+            }
+            DataType::String(s) => self.bind_text(index, s.as_str()),
+            DataType::Error(e) => self.bind_text(index, e.to_string().as_str()),
+            DataType::Empty => {
+                ((*api).bind_null.unwrap())(self.stmt, index);
+            }
+        }
+    }
+
+    unsafe fn bind_text(&mut self, index: c_int, s: &str) {
+        let text = CString::new(s.replace('\0', "")).unwrap_or_default();
+        ((*self.api).bind_text.unwrap())(self.stmt, index, text.as_ptr(), -1, None);
+        self.texts.push(text);
+    }
+
+    /// Steps the statement, returns `true` if a row is available.
+    unsafe fn step(&mut self) -> Result<bool, String> {
+        match ((*self.api).step.unwrap())(self.stmt) {
+            SQLITE_ROW => Ok(true),
+            SQLITE_DONE => Ok(false),
+            _ => Err(get_error_message(self.db, self.api)),
+        }
+    }
+
+    /// Runs the statement to completion and resets it for the next bindings.
+    unsafe fn execute(&mut self) -> Result<(), String> {
+        while self.step()? {}
+        ((*self.api).reset.unwrap())(self.stmt);
+        self.texts.clear();
+        Ok(())
+    }
+
+    unsafe fn column_value(&self, index: c_int) -> DataType {
+        let api = self.api;
+        match ((*api).column_type.unwrap())(self.stmt, index) {
+            SQLITE_INTEGER => DataType::Int(((*api).column_int64.unwrap())(self.stmt, index)),
+            SQLITE_FLOAT => DataType::Float(((*api).column_double.unwrap())(self.stmt, index)),
+            SQLITE_TEXT => {
+                let raw = ((*api).column_text.unwrap())(self.stmt, index);
+                let len = ((*api).column_bytes.unwrap())(self.stmt, index);
+                let bytes = std::slice::from_raw_parts(raw, len as usize);
+                DataType::String(String::from_utf8_lossy(bytes).into_owned())
+            }
+            _ => DataType::Empty,
+        }
+    }
+}
+
+impl Drop for Statement {
+    fn drop(&mut self) {
+        unsafe {
+            ((*self.api).finalize.unwrap())(self.stmt);
+        }
+    }
+}
+
+pub unsafe fn is_shadow_name(name: *const c_char) -> bool {
+    let name = CStr::from_ptr(name).to_string_lossy();
+    SHADOW_NAMES.iter().any(|suffix| name.eq_ignore_ascii_case(suffix))
+}
@@ -0,0 +1,342 @@
+use crate::spreadsheet::xlsx::{read_shared_formulas, PackageError};
+use calamine::{open_workbook_auto, Cell, DataType, Range, Reader, Sheets, XlsxError};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::BufReader;
+use std::mem::size_of;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::SystemTime;
+use zip::ZipArchive;
+
+/// Environment variable holding the cache capacity in megabytes, `0` disables caching.
+pub const CACHE_SIZE_ENV: &str = "XLITE_CACHE_SIZE";
+
+const DEFAULT_CACHE_SIZE_MB: usize = 256;
+
+/// Charged for each opened workbook, which keeps its file open and its shared strings in memory.
+const WORKBOOK_COST: usize = 1024 * 1024;
+
+/// Opened workbooks kept at most, whatever the capacity, to bound the open file handles.
+const MAX_WORKBOOKS: usize = 32;
+
+static CACHE: OnceLock<WorkbookCache> = OnceLock::new();
+
+/// Identifies the version of a file on disk, a change in either field invalidates the cache.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct FileStamp {
+    len: u64,
+    modified: Option<SystemTime>,
+}
+
+impl FileStamp {
+    pub fn read(path: &Path) -> std::io::Result<Self> {
+        let metadata = std::fs::metadata(path)?;
+        Ok(Self {
+            len: metadata.len(),
+            modified: metadata.modified().ok(),
+        })
+    }
+}
+
+struct CacheEntry {
+    stamp: FileStamp,
+    // locked on its own, so that parsing a worksheet doesn't block the other workbooks
+    sheets: Arc<Mutex<Sheets<BufReader<File>>>>,
+    ranges: HashMap<String, Arc<Range<DataType>>>,
+    formulas: HashMap<String, Arc<Range<String>>>,
+    size: usize,
+    last_used: u64,
+}
+
+struct CacheState {
+    entries: HashMap<PathBuf, CacheEntry>,
+    clock: u64,
+}
+
+/// Process-wide cache of opened workbooks and parsed worksheets, shared by all tables.
+/// Workbooks are opened and worksheets parsed outside of the cache lock.
+pub struct WorkbookCache {
+    state: Mutex<CacheState>,
+    capacity: usize,
+}
+
+impl WorkbookCache {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            state: Mutex::new(CacheState {
+                entries: HashMap::new(),
+                clock: 0,
+            }),
+            capacity,
+        }
+    }
+
+    /// Runs `f` against the opened workbook, (re)opening it if the file has changed.
+    pub fn with_sheets<T>(
+        &self,
+        path: &Path,
+        f: impl FnOnce(&mut Sheets<BufReader<File>>) -> T,
+    ) -> Result<T, calamine::Error> {
+        let (_, _, sheets) = self.prepare(path)?;
+        let mut sheets = sheets.lock().unwrap();
+        Ok(f(&mut sheets))
+    }
+
+    /// Returns the parsed range of the worksheet, or `None` if the worksheet does not exist.
+    pub fn worksheet_range(
+        &self,
+        path: &Path,
+        worksheet: &str,
+    ) -> Result<Option<Arc<Range<DataType>>>, calamine::Error> {
+        self.load(
+            path,
+            worksheet,
+            |entry| &mut entry.ranges,
+            |sheets| sheets.worksheet_range(worksheet),
+            estimate_size,
+        )
+    }
+
+    /// Returns the formulas of the worksheet, or `None` if the worksheet does not exist.
+    /// The cells sharing the formula of an `.xlsx` block get the formula translated to them.
+    pub fn worksheet_formula(
+        &self,
+        path: &Path,
+        worksheet: &str,
+    ) -> Result<Option<Arc<Range<String>>>, calamine::Error> {
+        self.load(
+            path,
+            worksheet,
+            |entry| &mut entry.formulas,
+            |sheets| {
+                let formulas = sheets.worksheet_formula(worksheet)?;
+                match sheets {
+                    Sheets::Xlsx(_) => Some(formulas.and_then(|f| expand_shared_formulas(path, worksheet, f))),
+                    _ => Some(formulas),
+                }
+            },
+            estimate_formulas_size,
+        )
+    }
+
+    pub fn invalidate(&self, path: &Path) {
+        if let Ok(key) = path.canonicalize() {
+            self.state.lock().unwrap().entries.remove(&key);
+        }
+    }
+
+    /// Returns the cached parse of the worksheet, or parses it and keeps it if it fits
+    /// into the capacity.
+    fn load<T>(
+        &self,
+        path: &Path,
+        worksheet: &str,
+        cached: fn(&mut CacheEntry) -> &mut HashMap<String, Arc<T>>,
+        parse: impl FnOnce(&mut Sheets<BufReader<File>>) -> Option<Result<T, calamine::Error>>,
+        estimate: fn(&T) -> usize,
+    ) -> Result<Option<Arc<T>>, calamine::Error> {
+        let (key, stamp, sheets) = self.prepare(path)?;
+        if let Some(entry) = self.state.lock().unwrap().entries.get_mut(&key) {
+            if let Some(value) = cached(entry).get(worksheet) {
+                return Ok(Some(Arc::clone(value)));
+            }
+        }
+
+        let value = match parse(&mut sheets.lock().unwrap()) {
+            Some(Ok(value)) => Arc::new(value),
+            Some(Err(err)) => return Err(err),
+            None => return Ok(None),
+        };
+
+        let size = estimate(&value);
+        let mut state = self.state.lock().unwrap();
+        // the file may have changed or the workbook been evicted while parsing
+        if let Some(entry) = state.entries.get_mut(&key) {
+            if size <= self.capacity && entry.stamp == stamp && Arc::ptr_eq(&entry.sheets, &sheets) {
+                entry.size += size;
+                cached(entry).insert(worksheet.to_string(), Arc::clone(&value));
+            }
+        }
+        self.evict(&mut state);
+        Ok(Some(value))
+    }
+
+    /// Returns the key, the version and the opened workbook of the file.
+    #[allow(clippy::type_complexity)]
+    fn prepare(
+        &self,
+        path: &Path,
+    ) -> Result<(PathBuf, FileStamp, Arc<Mutex<Sheets<BufReader<File>>>>), calamine::Error> {
+        let key = path.canonicalize()?;
+        let stamp = FileStamp::read(&key)?;
+
+        {
+            let mut state = self.state.lock().unwrap();
+            state.clock += 1;
+            let clock = state.clock;
+            if let Some(entry) = state.entries.get_mut(&key).filter(|e| e.stamp == stamp) {
+                entry.last_used = clock;
+                return Ok((key, stamp, Arc::clone(&entry.sheets)));
+            }
+        }
+
+        let opened = Arc::new(Mutex::new(open_workbook_auto(&key)?));
+
+        let mut state = self.state.lock().unwrap();
+        let clock = state.clock;
+        let sheets = match state.entries.get_mut(&key) {
+            // opened by another connection meanwhile
+            Some(entry) if entry.stamp == stamp => {
+                entry.last_used = clock;
+                Arc::clone(&entry.sheets)
+            }
+            _ => {
+                state.entries.insert(
+                    key.clone(),
+                    CacheEntry {
+                        stamp,
+                        sheets: Arc::clone(&opened),
+                        ranges: HashMap::new(),
+                        formulas: HashMap::new(),
+                        size: WORKBOOK_COST,
+                        last_used: clock,
+                    },
+                );
+                opened
+            }
+        };
+        self.evict(&mut state);
+        Ok((key, stamp, sheets))
+    }
+
+    /// Drops least recently used workbooks until they fit into the capacity and their
+    /// number into `MAX_WORKBOOKS`. The most recently used workbook is always kept.
+    fn evict(&self, state: &mut CacheState) {
+        loop {
+            let total: usize = state.entries.values().map(|e| e.size).sum();
+            let fits = total <= self.capacity && state.entries.len() <= MAX_WORKBOOKS;
+            if fits || state.entries.len() <= 1 {
+                break;
+            }
+
+            let oldest = state
+                .entries
+                .iter()
+                .min_by_key(|(_, e)| e.last_used)
+                .map(|(k, _)| k.clone());
+
+            match oldest {
+                Some(key) => {
+                    state.entries.remove(&key);
+                }
+                None => break,
+            }
+        }
+
+        if self.capacity == 0 {
+            state.entries.clear();
+        }
+    }
+}
+
+pub fn get_cache() -> &'static WorkbookCache {
+    CACHE.get_or_init(|| {
+        let megabytes = std::env::var(CACHE_SIZE_ENV)
+            .ok()
+            .and_then(|v| v.trim().parse::<usize>().ok())
+            .unwrap_or(DEFAULT_CACHE_SIZE_MB);
+        WorkbookCache::new(megabytes.saturating_mul(1024 * 1024))
+    })
+}
+
+fn estimate_size(range: &Range<DataType>) -> usize {
+    let (height, width) = range.get_size();
+    let strings: usize = range
+        .used_cells()
+        .map(|(_, _, value)| match value {
+            DataType::String(s) => s.capacity(),
+            _ => 0,
+        })
+        .sum();
+    height * width * size_of::<DataType>() + strings
+}
+
+/// Adds the formulas of the cells of shared formula blocks, which calamine leaves out.
+fn expand_shared_formulas(path: &Path, worksheet: &str, formulas: Range<String>) -> Result<Range<String>, calamine::Error> {
+    let mut archive = ZipArchive::new(BufReader::new(File::open(path)?)).map_err(XlsxError::Zip)?;
+    let shared = read_shared_formulas(&mut archive, worksheet).map_err(|err| match err {
+        PackageError::Io(e) => calamine::Error::Io(e),
+        PackageError::Zip(e) => XlsxError::Zip(e).into(),
+        PackageError::Xml(e) => XlsxError::Xml(e).into(),
+        PackageError::Unsupported(msg) | PackageError::Invalid(msg) => XlsxError::FileNotFound(msg).into(),
+    })?;
+    if shared.is_empty() {
+        return Ok(formulas);
+    }
+
+    // used cells are positioned relative to the first cell of the range
+    let start = formulas.start().unwrap_or((0, 0));
+    let mut cells: Vec<Cell<String>> = formulas
+        .used_cells()
+        .map(|(row, column, formula)| Cell::new((start.0 + row as u32, start.1 + column as u32), formula.clone()))
+        .chain(shared.into_iter().map(|(position, formula)| Cell::new(position, formula)))
+        .collect();
+    cells.sort_by_key(|cell| cell.get_position());
+    Ok(Range::from_sparse(cells))
+}
+
+fn estimate_formulas_size(range: &Range<String>) -> usize {
+    let (height, width) = range.get_size();
+    let strings: usize = range.used_cells().map(|(_, _, formula)| formula.capacity()).sum();
+    height * width * size_of::<String>() + strings
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn worksheet_range_is_shared_between_calls() {
+        let cache = WorkbookCache::new(1024 * 1024);
+        let path = Path::new("./tests/abcdef.xlsx");
+
+        let first = cache.worksheet_range(path, "Sheet1").unwrap().unwrap();
+        let second = cache.worksheet_range(path, "Sheet1").unwrap().unwrap();
+
+        assert!(Arc::ptr_eq(&first, &second));
+    }
+
+    #[test]
+    fn worksheet_range_is_not_retained_over_capacity() {
+        let cache = WorkbookCache::new(0);
+        let path = Path::new("./tests/abcdef.xlsx");
+
+        let first = cache.worksheet_range(path, "Sheet1").unwrap().unwrap();
+        let second = cache.worksheet_range(path, "Sheet1").unwrap().unwrap();
+
+        assert!(!Arc::ptr_eq(&first, &second));
+        assert_eq!(first.get_size(), second.get_size());
+    }
+
+    #[test]
+    fn worksheet_range_is_none_for_missing_sheet() {
+        let cache = WorkbookCache::new(1024 * 1024);
+        let path = Path::new("./tests/abcdef.xlsx");
+
+        assert!(cache.worksheet_range(path, "Missing").unwrap().is_none());
+    }
+
+    #[test]
+    fn opened_workbooks_are_evicted() {
+        // room for one opened workbook only
+        let cache = WorkbookCache::new(WORKBOOK_COST + 1024);
+
+        cache.with_sheets(Path::new("./tests/abcdef.xlsx"), |_| ()).unwrap();
+        cache.with_sheets(Path::new("./tests/months.xlsx"), |_| ()).unwrap();
+
+        let state = cache.state.lock().unwrap();
+        assert_eq!(state.entries.len(), 1);
+        assert!(state.entries.keys().all(|key| key.ends_with("months.xlsx")));
+    }
+}
@@ -10,35 +10,25 @@ impl CellIndex {
         Self { x, y }
     }
 
+    /// Parses a cell reference such as `A1`, `$A$1`, `a1`, a column `A` or a row `1`.
+    /// The omitted column or row is 0.
     pub fn try_parse(s: &str) -> Option<Self> {
-        let chars = s.chars();
-
-        let mut alpha = String::new();
-        let mut num = String::new();
-
-        for ch in chars {
-            if ch.is_ascii_alphabetic() {
-                if num.len() == 0 {
-                    alpha.push(ch);
-                } else {
-                    return None;
-                }
-            } else if ch.is_ascii_digit() {
-                num.push(ch);
-            }
+        let s = s.strip_prefix('$').unwrap_or(s);
+        let letters = s.chars().take_while(|ch| ch.is_ascii_alphabetic()).count();
+        let (alpha, num) = s.split_at(letters);
+        let num = if alpha.is_empty() { num } else { num.strip_prefix('$').unwrap_or(num) };
+
+        // XFD is the last column of a worksheet
+        if alpha.len() > 3 || (alpha.is_empty() && num.is_empty()) {
+            return None;
         }
-
-        if alpha.len() > 0 {
-            let x = column_to_index(alpha.to_uppercase().as_str());
-            if num.len() > 0 {
-                let y = row_to_index(num.as_str());
-                Some(CellIndex::new(x, y))
-            } else {
-                Some(CellIndex::new(x, 0))
-            }
-        } else {
-            None
+        if !num.chars().all(|ch| ch.is_ascii_digit()) {
+            return None;
         }
+
+        let x = if alpha.is_empty() { 0 } else { column_to_index(alpha.to_uppercase().as_str()) };
+        let y = if num.is_empty() { 0 } else { row_to_index(num)? };
+        Some(CellIndex::new(x, y))
     }
 
     pub fn get_x(&self) -> u32 {
@@ -57,7 +47,7 @@ impl CellIndex {
         index_to_row(self.y)
     }
 
-    pub fn to_zero_indexed(&self) -> (u32, u32) {
+    pub fn to_zero_indexed(self) -> (u32, u32) {
         (if self.y > 0 { self.y - 1 } else { 0 },
             if self.x > 0 { self.x - 1 } else { 0 })
     }
@@ -76,27 +66,98 @@ impl CellRange {
         Self { start, end }
     }
 
+    /// Parses a range such as `A1:F10`, `$A$1:$F$100`, `A2:F` (to the last row),
+    /// `A5:` (to the last column and row), `B:D`, `2:10` or a single cell `A1`.
+    /// The omitted columns and rows are 0 and resolved against the used range by `resolve`.
     pub fn try_parse(s: &str) -> Option<Self> {
-        let parts: Vec<&str> = s.split(":").collect();
-        if parts.len() == 2 {
-            let start = CellIndex::try_parse(parts[0])?;
-            let end = CellIndex::try_parse(parts[1])?;
-            Some(CellRange::new(start, end))
-        } else {
-            None
+        match s.split_once(':') {
+            Some((start, "")) => Some(CellRange::new(CellIndex::try_parse(start)?, CellIndex::new(0, 0))),
+            Some((start, end)) => {
+                let start = CellIndex::try_parse(start)?;
+                let end = CellIndex::try_parse(end)?;
+                // a column can't be paired with a row, as in A:5
+                if (start.x == 0) != (end.x == 0) && (start.y == 0) != (end.y == 0) {
+                    return None;
+                }
+                Some(CellRange::new(start, end))
+            }
+            None => {
+                let cell = CellIndex::try_parse(s)?;
+                if cell.x == 0 || cell.y == 0 {
+                    return None;
+                }
+                Some(CellRange::new(cell, cell))
+            }
         }
     }
 
+    /// Returns the zero-indexed (row, column) positions of the first and the last cell,
+    /// taking the omitted columns and rows from the used range of the worksheet.
+    pub fn resolve(&self, used_start: (u32, u32), used_end: (u32, u32)) -> ((u32, u32), (u32, u32)) {
+        let pick = |index: u32, used: u32| if index > 0 { index - 1 } else { used };
+        (
+            (pick(self.start.y, used_start.0), pick(self.start.x, used_start.1)),
+            (pick(self.end.y, used_end.0), pick(self.end.x, used_end.1)),
+        )
+    }
+
     pub fn get_start(&self) -> CellIndex {
         self.start
     }
 
+    /// The reference parsed by `try_parse`, e.g. `A2:F`, the omitted columns and rows left out.
+    pub fn get_reference(&self) -> String {
+        let cell = |index: CellIndex| match index.y {
+            0 => index_to_column(index.x),
+            row => format!("{}{}", index_to_column(index.x), row),
+        };
+        format!("{}:{}", cell(self.start), cell(self.end))
+    }
+
     pub fn get_end(&self) -> CellIndex {
         self.end
     }
 
 }
 
+/// Splits the worksheet from a reference such as `'Sheet 1'!A1:F` or `Sheet1!A1:F`.
+/// Quotes doubled within a quoted name are unescaped.
+pub fn split_sheet(reference: &str) -> (Option<String>, &str) {
+    match reference.rsplit_once('!') {
+        Some((sheet, cells)) => {
+            let sheet = match sheet.strip_prefix('\'').and_then(|s| s.strip_suffix('\'')) {
+                Some(quoted) => quoted.replace("''", "'"),
+                None => sheet.to_string(),
+            };
+            (Some(sheet), cells)
+        }
+        None => (None, reference),
+    }
+}
+
+/// Splits the areas of a reference such as `Sheet1!$A$1:$B$2,Sheet1!$D$1:$D$2`
+/// on the commas outside of quoted sheet names and function arguments.
+pub fn split_areas(reference: &str) -> Vec<&str> {
+    let mut areas = Vec::new();
+    let mut quoted = false;
+    let mut depth = 0;
+    let mut start = 0;
+    for (i, ch) in reference.char_indices() {
+        match ch {
+            '\'' => quoted = !quoted,
+            '(' if !quoted => depth += 1,
+            ')' if !quoted => depth -= 1,
+            ',' if !quoted && depth == 0 => {
+                areas.push(&reference[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    areas.push(&reference[start..]);
+    areas
+}
+
 fn column_to_index(column: &str) -> u32 {
     let column = column.as_bytes();
     let mut sum = 0;
@@ -119,8 +180,8 @@ fn index_to_column(index: u32) -> String {
     out
 }
 
-fn row_to_index(row: &str) -> u32 {
-    row.parse::<u32>().unwrap()
+fn row_to_index(row: &str) -> Option<u32> {
+    row.parse::<u32>().ok().filter(|row| *row > 0)
 }
 
 fn index_to_row(index: u32) -> String {
@@ -196,6 +257,59 @@ mod tests {
         assert_eq!(range.end.y, 9);
     }
 
+    #[test]
+    fn try_parse_cell_index_accepts_absolute_and_lowercase_references() {
+        let index = CellIndex::try_parse("$b$12").unwrap();
+        assert_eq!((index.x, index.y), (2, 12));
+        let row = CellIndex::try_parse("5").unwrap();
+        assert_eq!((row.x, row.y), (0, 5));
+        assert!(CellIndex::try_parse("A0").is_none());
+        assert!(CellIndex::try_parse("ABCD1").is_none());
+        assert!(CellIndex::try_parse("A1B").is_none());
+    }
+
+    #[test]
+    fn try_parse_cell_range_from_open_references() {
+        let range = CellRange::try_parse("A5:").unwrap();
+        assert_eq!(range.resolve((0, 0), (99, 9)), ((4, 0), (99, 9)));
+
+        let range = CellRange::try_parse("B:D").unwrap();
+        assert_eq!(range.resolve((2, 0), (99, 9)), ((2, 1), (99, 3)));
+
+        let range = CellRange::try_parse("2:10").unwrap();
+        assert_eq!(range.resolve((0, 1), (99, 9)), ((1, 1), (9, 9)));
+
+        let range = CellRange::try_parse("$A$1:$F$100").unwrap();
+        assert_eq!(range.resolve((0, 0), (9, 9)), ((0, 0), (99, 5)));
+
+        assert!(CellRange::try_parse("B2").is_some());
+        assert!(CellRange::try_parse("A:5").is_none());
+        assert!(CellRange::try_parse("A1:F:G").is_none());
+    }
+
+    #[test]
+    fn get_reference_gives_the_parsed_reference() {
+        for reference in ["A1:Z9", "A5:", "B:D", "2:10", "B3:D"] {
+            assert_eq!(CellRange::try_parse(reference).unwrap().get_reference(), reference);
+        }
+        assert_eq!(CellRange::try_parse("$A$1:$F$100").unwrap().get_reference(), "A1:F100");
+        assert!(CellRange::try_parse("A1:F:G").is_none());
+    }
+
+    #[test]
+    fn split_sheet_unquotes_sheet_names() {
+        assert_eq!(split_sheet("'Bob''s Sheet'!A1:F"), (Some("Bob's Sheet".to_string()), "A1:F"));
+        assert_eq!(split_sheet("Sheet1!A1"), (Some("Sheet1".to_string()), "A1"));
+        assert_eq!(split_sheet("A1:F"), (None, "A1:F"));
+    }
+
+    #[test]
+    fn split_areas_ignores_commas_in_sheet_names() {
+        assert_eq!(split_areas("Sheet1!$A$1:$B$2,Sheet1!$D$1"), vec!["Sheet1!$A$1:$B$2", "Sheet1!$D$1"]);
+        assert_eq!(split_areas("'Q3, actual'!$A$1:$B$2"), vec!["'Q3, actual'!$A$1:$B$2"]);
+        assert_eq!(split_areas("OFFSET(Sheet1!$A$1,1,0)").len(), 1);
+    }
+
     #[test]
     fn to_zero_indexed_gives_0_indexed_tuple_in_y_x_format() {
         let index = CellIndex::new(1, 9);
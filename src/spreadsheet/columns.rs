@@ -0,0 +1,423 @@
+use calamine::DataType;
+use std::fmt;
+
+/// Number of rows sampled to infer the column types, unless SAMPLE is given.
+pub const DEFAULT_SAMPLE_ROWS: usize = 100;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum ColumnType {
+    Integer,
+    Real,
+    Text,
+    Boolean,
+    DateTime,
+}
+
+impl ColumnType {
+    /// The type declared in the table schema.
+    pub fn as_sql(&self) -> &'static str {
+        match self {
+            ColumnType::Integer => "INTEGER",
+            ColumnType::Real => "REAL",
+            ColumnType::Text => "TEXT",
+            ColumnType::Boolean => "BOOLEAN",
+            ColumnType::DateTime => "DATETIME",
+        }
+    }
+
+    /// Maps a declared SQL type to a column type, following SQLite's affinity rules
+    /// with additional BOOLEAN and DATE/TIME types. `None` for types without a
+    /// conversion, e.g. BLOB.
+    pub fn from_declared(declared: &str) -> Option<Self> {
+        let declared = declared.to_ascii_uppercase();
+        if declared.contains("INT") {
+            Some(ColumnType::Integer)
+        } else if declared.contains("CHAR") || declared.contains("CLOB") || declared.contains("TEXT") {
+            Some(ColumnType::Text)
+        } else if declared.contains("BOOL") {
+            Some(ColumnType::Boolean)
+        } else if declared.contains("DATE") || declared.contains("TIME") {
+            Some(ColumnType::DateTime)
+        } else if declared.contains("REAL")
+            || declared.contains("FLOA")
+            || declared.contains("DOUB")
+            || declared.contains("NUM")
+            || declared.contains("DEC")
+        {
+            Some(ColumnType::Real)
+        } else {
+            None
+        }
+    }
+
+    /// The type of a single cell, `None` for empty and error cells.
+    fn of_value(value: &DataType) -> Option<Self> {
+        match value {
+            DataType::Int(_) => Some(ColumnType::Integer),
+            // spreadsheets store all numbers as doubles, whole numbers count as integers
+            DataType::Float(f) if f.fract() == 0.0 && f.abs() < i64::MAX as f64 => {
+                Some(ColumnType::Integer)
+            }
+            DataType::Float(_) => Some(ColumnType::Real),
+            DataType::String(_) => Some(ColumnType::Text),
+            DataType::Bool(_) => Some(ColumnType::Boolean),
+            DataType::DateTime(_) => Some(ColumnType::DateTime),
+            DataType::Empty | DataType::Error(_) => None,
+        }
+    }
+
+    /// The narrowest type holding values of both types.
+    fn merge(self, other: Self) -> Self {
+        match (self, other) {
+            (a, b) if a == b => a,
+            (ColumnType::Integer, ColumnType::Real) | (ColumnType::Real, ColumnType::Integer) => {
+                ColumnType::Real
+            }
+            _ => ColumnType::Text,
+        }
+    }
+}
+
+impl fmt::Display for ColumnType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.as_sql())
+    }
+}
+
+/// A column of the declared table.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Column {
+    name: String,
+    declared_type: Option<String>,
+}
+
+impl Column {
+    pub fn new(name: String) -> Self {
+        Self { name, declared_type: None }
+    }
+
+    pub fn with_type(name: String, column_type: Option<ColumnType>) -> Self {
+        Self::with_declared_type(name, column_type.map(|t| t.as_sql().to_string()))
+    }
+
+    pub fn with_declared_type(name: String, declared_type: Option<String>) -> Self {
+        Self { name, declared_type }
+    }
+
+    pub fn get_name(&self) -> &str {
+        self.name.as_str()
+    }
+
+    pub fn get_declared_type(&self) -> Option<&str> {
+        self.declared_type.as_deref()
+    }
+}
+
+/// How the column names read from the COLNAMES row are normalized.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum NameStyle {
+    /// Keeps the names as written.
+    Raw,
+    /// Removes leading and trailing whitespace.
+    Trim,
+    /// Trims and lowercases.
+    Lower,
+    /// Lowercases and joins the words with underscores, e.g. `Unit Price` as `unit_price`.
+    Snake,
+}
+
+impl NameStyle {
+    pub fn try_parse(s: &str) -> Option<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "raw" => Some(NameStyle::Raw),
+            "trim" => Some(NameStyle::Trim),
+            "lower" => Some(NameStyle::Lower),
+            "snake" => Some(NameStyle::Snake),
+            _ => None,
+        }
+    }
+
+    fn apply(&self, name: &str) -> String {
+        match self {
+            NameStyle::Raw => name.to_string(),
+            NameStyle::Trim => name.trim().to_string(),
+            NameStyle::Lower => name.trim().to_lowercase(),
+            NameStyle::Snake => name
+                .split(|c: char| !c.is_alphanumeric())
+                .filter(|word| !word.is_empty())
+                .map(str::to_lowercase)
+                .collect::<Vec<_>>()
+                .join("_"),
+        }
+    }
+}
+
+/// Turns the header cells into valid and unique column names. Each column is given
+/// as its header text, if any, and its column letter. Blank names fall back to the
+/// column letter, duplicates (compared case-insensitively, as SQLite does) get a
+/// `_2`, `_3`, ... suffix. The `reserved` names of the hidden columns are never given
+/// to a column, nor with `formulas` the name of a `<name>_formula` column.
+pub fn sanitize_names(
+    columns: Vec<(Option<String>, String)>,
+    style: NameStyle,
+    reserved: &[String],
+    formulas: bool,
+) -> Vec<String> {
+    let names: Vec<String> = columns
+        .into_iter()
+        .map(|(header, letter)| {
+            let name = header.map(|h| style.apply(&h.replace('\0', ""))).unwrap_or_default();
+            if name.trim().is_empty() {
+                letter
+            } else {
+                name
+            }
+        })
+        .collect();
+
+    let mut used: std::collections::HashSet<String> = reserved.iter().map(|name| name.to_lowercase()).collect();
+    let mut unique = Vec::with_capacity(names.len());
+    for name in names {
+        let mut candidate = name.clone();
+        let mut suffix = 2;
+        loop {
+            let formula = format!("{}_formula", candidate).to_lowercase();
+            let taken = used.contains(&candidate.to_lowercase()) || (formulas && used.contains(&formula));
+            if !taken {
+                used.insert(candidate.to_lowercase());
+                if formulas {
+                    used.insert(formula);
+                }
+                break;
+            }
+            candidate = format!("{}_{}", name, suffix);
+            suffix += 1;
+        }
+        unique.push(candidate);
+    }
+
+    unique
+}
+
+/// Parses the column definitions of `CREATE TABLE x(id INTEGER, name TEXT, ...)`
+/// into column names and declared types. Table constraints are ignored.
+pub fn parse_schema(sql: &str) -> Option<Vec<(String, Option<String>)>> {
+    let start = sql.find('(')?;
+    let end = sql.rfind(')')?;
+    if end <= start {
+        return None;
+    }
+
+    // split on the commas outside of parentheses, e.g. not in DECIMAL(10,2)
+    let mut definitions = Vec::new();
+    let mut depth = 0;
+    let mut current = String::new();
+    for ch in sql[start + 1..end].chars() {
+        match ch {
+            '(' => depth += 1,
+            ')' => depth -= 1,
+            ',' if depth == 0 => {
+                definitions.push(std::mem::take(&mut current));
+                continue;
+            }
+            _ => {}
+        }
+        current.push(ch);
+    }
+    definitions.push(current);
+
+    let mut columns = Vec::new();
+    for definition in definitions {
+        let definition = definition.trim();
+        let (name, rest) = split_name(definition)?;
+        if name.is_empty() {
+            return None;
+        }
+
+        let keyword = name.to_ascii_uppercase();
+        if ["CONSTRAINT", "PRIMARY", "UNIQUE", "CHECK", "FOREIGN"].contains(&keyword.as_str())
+            && !definition.starts_with(['"', '`', '['])
+        {
+            continue;
+        }
+
+        // the type ends where the column constraints start
+        let mut declared = Vec::new();
+        for word in rest.split_whitespace() {
+            let upper = word.to_ascii_uppercase();
+            if [
+                "CONSTRAINT", "PRIMARY", "NOT", "NULL", "UNIQUE", "CHECK", "DEFAULT", "COLLATE",
+                "REFERENCES", "GENERATED", "AS",
+            ]
+            .contains(&upper.as_str())
+            {
+                break;
+            }
+            declared.push(word);
+        }
+
+        let declared = declared.join(" ");
+        columns.push((name, if declared.is_empty() { None } else { Some(declared) }));
+    }
+
+    if columns.is_empty() {
+        None
+    } else {
+        Some(columns)
+    }
+}
+
+/// Splits a column definition into the (unquoted) column name and the rest.
+fn split_name(definition: &str) -> Option<(String, &str)> {
+    let close = match definition.chars().next()? {
+        '"' => '"',
+        '`' => '`',
+        '[' => ']',
+        '\'' => '\'',
+        _ => {
+            let end = definition.find(char::is_whitespace).unwrap_or(definition.len());
+            return Some((definition[..end].to_string(), definition[end..].trim()));
+        }
+    };
+
+    let end = definition[1..].find(close)? + 1;
+    Some((definition[1..end].to_string(), definition[end + 1..].trim()))
+}
+
+/// Infers the type of each of the `width` columns from the first `sample` rows, given
+/// with their rowid. The row `skip` (e.g. the column names row) is not sampled.
+/// Columns without values in the sample have no type.
+pub fn infer_column_types<R: AsRef<[DataType]>>(
+    width: usize,
+    rows: impl Iterator<Item = (u32, R)>,
+    sample: usize,
+    skip: Option<u32>,
+) -> Vec<Option<ColumnType>> {
+    let mut types = vec![None; width];
+
+    let rows = rows.filter(|(rowid, _)| Some(*rowid) != skip).take(sample);
+
+    for (_, row) in rows {
+        for (column, value) in row.as_ref().iter().enumerate().take(width) {
+            if let Some(value_type) = ColumnType::of_value(value) {
+                types[column] = Some(match types[column] {
+                    Some(column_type) => ColumnType::merge(column_type, value_type),
+                    None => value_type,
+                });
+            }
+        }
+    }
+
+    types
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use calamine::Range;
+
+    fn rows_of(range: &Range<DataType>) -> impl Iterator<Item = (u32, &[DataType])> {
+        let start = range.start().unwrap_or((0, 0)).0;
+        range.rows().enumerate().map(move |(i, row)| (start + i as u32, row))
+    }
+
+    #[test]
+    fn infer_column_types_merges_values() {
+        let mut range = Range::new((1, 0), (4, 3));
+        range.set_value((1, 0), DataType::String("header".to_string()));
+        range.set_value((2, 0), DataType::Float(1.0));
+        range.set_value((3, 0), DataType::Float(1.5));
+        range.set_value((2, 1), DataType::Float(2.0));
+        range.set_value((3, 1), DataType::String("two".to_string()));
+        range.set_value((2, 2), DataType::Bool(true));
+        range.set_value((4, 2), DataType::Error(calamine::CellErrorType::NA));
+
+        let types = infer_column_types(range.width(), rows_of(&range), 10, Some(1));
+
+        assert_eq!(
+            types,
+            vec![Some(ColumnType::Real), Some(ColumnType::Text), Some(ColumnType::Boolean), None]
+        );
+    }
+
+    #[test]
+    fn from_declared_follows_affinity_rules() {
+        assert_eq!(ColumnType::from_declared("BIGINT"), Some(ColumnType::Integer));
+        assert_eq!(ColumnType::from_declared("varchar(20)"), Some(ColumnType::Text));
+        assert_eq!(ColumnType::from_declared("DECIMAL(10,2)"), Some(ColumnType::Real));
+        assert_eq!(ColumnType::from_declared("DATETIME"), Some(ColumnType::DateTime));
+        assert_eq!(ColumnType::from_declared("BLOB"), None);
+    }
+
+    #[test]
+    fn parse_schema_reads_names_and_types() {
+        let columns = parse_schema(
+            "CREATE TABLE x(id INTEGER PRIMARY KEY, \"full name\" TEXT NOT NULL, amount DECIMAL(10,2), note, PRIMARY KEY(id))",
+        )
+        .unwrap();
+
+        assert_eq!(
+            columns,
+            vec![
+                ("id".to_string(), Some("INTEGER".to_string())),
+                ("full name".to_string(), Some("TEXT".to_string())),
+                ("amount".to_string(), Some("DECIMAL(10,2)".to_string())),
+                ("note".to_string(), None),
+            ]
+        );
+        assert!(parse_schema("CREATE TABLE x").is_none());
+    }
+
+    #[test]
+    fn sanitize_names_fills_blanks_and_deduplicates() {
+        let names = sanitize_names(
+            vec![
+                (Some("Total".to_string()), "A".to_string()),
+                (Some("  ".to_string()), "B".to_string()),
+                (None, "C".to_string()),
+                (Some("total".to_string()), "D".to_string()),
+                (Some("Total_2".to_string()), "E".to_string()),
+                (Some("Unit `Price`".to_string()), "F".to_string()),
+            ],
+            NameStyle::Raw,
+            &[],
+            false,
+        );
+
+        assert_eq!(names, vec!["Total", "B", "C", "total_2", "Total_2_2", "Unit `Price`"]);
+    }
+
+    #[test]
+    fn sanitize_names_keeps_hidden_names_free() {
+        let columns = vec![
+            (Some("_row".to_string()), "A".to_string()),
+            (Some("price".to_string()), "B".to_string()),
+            (Some("price_formula".to_string()), "C".to_string()),
+        ];
+
+        let names = sanitize_names(columns.clone(), NameStyle::Raw, &["_ROW".to_string()], false);
+        assert_eq!(names, vec!["_row_2", "price", "price_formula"]);
+
+        let names = sanitize_names(columns, NameStyle::Raw, &["_row".to_string()], true);
+        assert_eq!(names, vec!["_row_2", "price", "price_formula_2"]);
+    }
+
+    #[test]
+    fn name_styles_normalize_names() {
+        assert_eq!(NameStyle::Trim.apply(" Unit Price "), "Unit Price");
+        assert_eq!(NameStyle::Lower.apply(" Unit Price "), "unit price");
+        assert_eq!(NameStyle::Snake.apply(" Unit Price (EUR) "), "unit_price_eur");
+        assert_eq!(NameStyle::Snake.apply("%"), "");
+    }
+
+    #[test]
+    fn infer_column_types_samples_limited_rows() {
+        let mut range = Range::new((0, 0), (2, 0));
+        range.set_value((0, 0), DataType::Int(1));
+        range.set_value((1, 0), DataType::Int(2));
+        range.set_value((2, 0), DataType::String("three".to_string()));
+
+        assert_eq!(infer_column_types(1, rows_of(&range), 2, None), vec![Some(ColumnType::Integer)]);
+        assert_eq!(infer_column_types(1, rows_of(&range), 0, None), vec![None]);
+    }
+}
@@ -0,0 +1,238 @@
+use calamine::DataType;
+use std::cmp::Ordering;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum ConstraintOp {
+    Eq,
+    Gt,
+    Ge,
+    Lt,
+    Le,
+}
+
+impl ConstraintOp {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ConstraintOp::Eq => "eq",
+            ConstraintOp::Gt => "gt",
+            ConstraintOp::Ge => "ge",
+            ConstraintOp::Lt => "lt",
+            ConstraintOp::Le => "le",
+        }
+    }
+
+    pub fn try_parse(s: &str) -> Option<Self> {
+        match s {
+            "eq" => Some(ConstraintOp::Eq),
+            "gt" => Some(ConstraintOp::Gt),
+            "ge" => Some(ConstraintOp::Ge),
+            "lt" => Some(ConstraintOp::Lt),
+            "le" => Some(ConstraintOp::Le),
+            _ => None,
+        }
+    }
+
+    fn accepts(&self, ordering: Ordering) -> bool {
+        match self {
+            ConstraintOp::Eq => ordering == Ordering::Equal,
+            ConstraintOp::Gt => ordering == Ordering::Greater,
+            ConstraintOp::Ge => ordering != Ordering::Less,
+            ConstraintOp::Lt => ordering == Ordering::Less,
+            ConstraintOp::Le => ordering != Ordering::Greater,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum ConstraintTarget {
+    RowId,
+    Column(usize),
+}
+
+/// A single `<target> <op> <value>` term that SQLite passed down to the module.
+#[derive(Debug, Clone)]
+pub struct Constraint {
+    target: ConstraintTarget,
+    op: ConstraintOp,
+    value: DataType,
+}
+
+impl Constraint {
+    pub fn new(target: ConstraintTarget, op: ConstraintOp, value: DataType) -> Self {
+        Self { target, op, value }
+    }
+
+    pub fn get_target(&self) -> ConstraintTarget {
+        self.target
+    }
+
+    pub fn get_op(&self) -> ConstraintOp {
+        self.op
+    }
+
+    pub fn get_value(&self) -> &DataType {
+        &self.value
+    }
+
+    /// Returns `false` only if the row certainly cannot satisfy the constraint.
+    /// SQLite double checks every row we return, so whenever the outcome depends on
+    /// affinity or collation rules the row is kept.
+    pub fn may_match(&self, rowid: u32, row: &[DataType]) -> bool {
+        match self.target {
+            ConstraintTarget::RowId => {
+                let rowid = DataType::Int(rowid as i64);
+                may_match_value(self.op, &rowid, &self.value)
+            }
+            ConstraintTarget::Column(i) => match row.get(i) {
+                Some(cell) => may_match_value(self.op, cell, &self.value),
+                None => false,
+            },
+        }
+    }
+}
+
+/// Collapses rowid constraints into an inclusive `(low, high)` range of rowids.
+/// Returns `None` when no rowid can possibly match.
+pub fn get_rowid_bounds(constraints: &[Constraint]) -> Option<(u32, u32)> {
+    let mut low = 0f64;
+    let mut high = u32::MAX as f64;
+
+    for constraint in constraints {
+        if constraint.target != ConstraintTarget::RowId {
+            continue;
+        }
+
+        let value = match constraint.value {
+            DataType::Int(n) => n as f64,
+            DataType::Float(f) => f,
+            DataType::Empty => return None,
+            _ => continue,
+        };
+
+        match constraint.op {
+            ConstraintOp::Eq => {
+                if value.fract() != 0.0 {
+                    return None;
+                }
+                low = low.max(value);
+                high = high.min(value);
+            }
+            ConstraintOp::Gt => low = low.max(value.floor() + 1.0),
+            ConstraintOp::Ge => low = low.max(value.ceil()),
+            ConstraintOp::Lt => high = high.min(value.ceil() - 1.0),
+            ConstraintOp::Le => high = high.min(value.floor()),
+        }
+    }
+
+    if low > high || high < 0.0 {
+        None
+    } else {
+        Some((low as u32, high as u32))
+    }
+}
+
+/// Encodes the targets and operators of the constraints used by a query plan,
+/// so that `x_filter` can pair them with the values in `argv`.
+pub fn encode_plan(plan: &[(ConstraintTarget, ConstraintOp)]) -> String {
+    plan.iter()
+        .map(|(target, op)| match target {
+            ConstraintTarget::RowId => format!("r:{}", op.as_str()),
+            ConstraintTarget::Column(i) => format!("{}:{}", i, op.as_str()),
+        })
+        .collect::<Vec<String>>()
+        .join(",")
+}
+
+pub fn decode_plan(s: &str) -> Vec<(ConstraintTarget, ConstraintOp)> {
+    s.split(',')
+        .filter_map(|part| {
+            let (target, op) = part.split_once(':')?;
+            let target = match target {
+                "r" => ConstraintTarget::RowId,
+                n => ConstraintTarget::Column(n.parse().ok()?),
+            };
+            Some((target, ConstraintOp::try_parse(op)?))
+        })
+        .collect()
+}
+
+fn may_match_value(op: ConstraintOp, cell: &DataType, value: &DataType) -> bool {
+    match (as_number(cell), as_number(value)) {
+        (Some(a), Some(b)) => match a.partial_cmp(&b) {
+            Some(ordering) => op.accepts(ordering),
+            None => true,
+        },
+        _ => match (cell, value) {
+            // NULL never compares as true
+            (DataType::Empty, _) | (_, DataType::Empty) => false,
+            (DataType::Error(_), _) => false,
+            (DataType::String(a), DataType::String(b)) => op.accepts(a.as_str().cmp(b.as_str())),
+            _ => true,
+        },
+    }
+}
+
+fn as_number(value: &DataType) -> Option<f64> {
+    match value {
+        DataType::Int(n) => Some(*n as f64),
+        DataType::Float(f) => Some(*f),
+        DataType::DateTime(f) => Some(*f),
+        DataType::Bool(b) => Some(if *b { 1.0 } else { 0.0 }),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encode_and_decode_plan_roundtrip() {
+        let plan = vec![
+            (ConstraintTarget::RowId, ConstraintOp::Ge),
+            (ConstraintTarget::Column(3), ConstraintOp::Eq),
+        ];
+        let encoded = encode_plan(&plan);
+        assert_eq!(encoded, "r:ge,3:eq");
+        assert_eq!(decode_plan(encoded.as_str()), plan);
+    }
+
+    #[test]
+    fn get_rowid_bounds_combines_range_constraints() {
+        let constraints = vec![
+            Constraint::new(ConstraintTarget::RowId, ConstraintOp::Gt, DataType::Int(2)),
+            Constraint::new(ConstraintTarget::RowId, ConstraintOp::Le, DataType::Float(7.5)),
+        ];
+        assert_eq!(get_rowid_bounds(&constraints), Some((3, 7)));
+    }
+
+    #[test]
+    fn get_rowid_bounds_is_none_for_contradicting_constraints() {
+        let constraints = vec![
+            Constraint::new(ConstraintTarget::RowId, ConstraintOp::Gt, DataType::Int(5)),
+            Constraint::new(ConstraintTarget::RowId, ConstraintOp::Lt, DataType::Int(5)),
+        ];
+        assert_eq!(get_rowid_bounds(&constraints), None);
+    }
+
+    #[test]
+    fn may_match_compares_numbers_and_strings() {
+        let row = vec![DataType::String("ten".to_string()), DataType::Float(10.0)];
+
+        let eq = Constraint::new(ConstraintTarget::Column(1), ConstraintOp::Eq, DataType::Int(10));
+        assert!(eq.may_match(0, &row));
+
+        let gt = Constraint::new(ConstraintTarget::Column(1), ConstraintOp::Gt, DataType::Int(10));
+        assert!(!gt.may_match(0, &row));
+
+        let text = Constraint::new(ConstraintTarget::Column(0), ConstraintOp::Eq, DataType::String("six".to_string()));
+        assert!(!text.may_match(0, &row));
+    }
+
+    #[test]
+    fn may_match_keeps_rows_with_mixed_types() {
+        let row = vec![DataType::Float(10.0)];
+        let eq = Constraint::new(ConstraintTarget::Column(0), ConstraintOp::Eq, DataType::String("10".to_string()));
+        assert!(eq.may_match(0, &row));
+    }
+}
@@ -0,0 +1,429 @@
+use crate::spreadsheet::columns::ColumnType;
+use crate::spreadsheet::writer::get_cell_reference;
+use calamine::DataType;
+use std::borrow::Cow;
+
+/// What to do with a value that can't be converted to the declared type of its column.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum TypeErrorPolicy {
+    /// Returns NULL.
+    Null,
+    /// Returns the value as it is in the worksheet.
+    Keep,
+    /// Aborts the query.
+    Fail,
+}
+
+impl TypeErrorPolicy {
+    pub fn try_parse(s: &str) -> Option<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "null" => Some(TypeErrorPolicy::Null),
+            "keep" => Some(TypeErrorPolicy::Keep),
+            "fail" => Some(TypeErrorPolicy::Fail),
+            _ => None,
+        }
+    }
+}
+
+/// How cells holding errors (`#N/A`, `#DIV/0!`, ...) are returned.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum ErrorPolicy {
+    /// Returns NULL.
+    Null,
+    /// Returns the error as text, e.g. `#N/A`.
+    Text,
+    /// Aborts the query.
+    Fail,
+}
+
+impl ErrorPolicy {
+    pub fn try_parse(s: &str) -> Option<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "null" => Some(ErrorPolicy::Null),
+            "text" => Some(ErrorPolicy::Text),
+            "fail" => Some(ErrorPolicy::Fail),
+            _ => None,
+        }
+    }
+}
+
+/// How date and time cells are returned.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum DateFormat {
+    /// The Excel serial number (days since 1899-12-30), as stored in the file.
+    Serial,
+    /// ISO-8601 text, e.g. `2023-07-17 12:00:00`, understood by SQLite's date functions.
+    Iso,
+    /// Julian day number, as returned by SQLite's `julianday()`.
+    Julian,
+    /// Seconds since 1970-01-01, as returned by SQLite's `unixepoch()`.
+    Unix,
+}
+
+impl DateFormat {
+    pub fn try_parse(s: &str) -> Option<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "serial" => Some(DateFormat::Serial),
+            "iso" => Some(DateFormat::Iso),
+            "julian" => Some(DateFormat::Julian),
+            "unix" => Some(DateFormat::Unix),
+            _ => None,
+        }
+    }
+}
+
+/// Converts the cell values of a table before they are filtered and returned.
+#[derive(Debug, Clone)]
+pub struct ValueConverter {
+    worksheet: String,
+    first_column: u32,
+    types: Vec<Option<ColumnType>>,
+    on_type_error: TypeErrorPolicy,
+    dates: DateFormat,
+    errors: ErrorPolicy,
+}
+
+impl ValueConverter {
+    /// Creates a converter for the columns starting at the absolute column
+    /// `first_column` of the worksheet, leaving all values unchanged.
+    pub fn new(worksheet: String, first_column: u32) -> Self {
+        Self {
+            worksheet,
+            first_column,
+            types: Vec::new(),
+            on_type_error: TypeErrorPolicy::Null,
+            dates: DateFormat::Serial,
+            errors: ErrorPolicy::Null,
+        }
+    }
+
+    /// Converts the values to the declared types of the columns.
+    pub fn with_types(mut self, types: Vec<Option<ColumnType>>, on_type_error: TypeErrorPolicy) -> Self {
+        self.types = types;
+        self.on_type_error = on_type_error;
+        self
+    }
+
+    pub fn with_dates(mut self, dates: DateFormat) -> Self {
+        self.dates = dates;
+        self
+    }
+
+    pub fn with_errors(mut self, errors: ErrorPolicy) -> Self {
+        self.errors = errors;
+        self
+    }
+
+    /// Checks whether the converter leaves all values unchanged.
+    pub fn is_identity(&self) -> bool {
+        self.types.iter().all(|t| t.is_none())
+            && self.dates == DateFormat::Serial
+            && self.errors == ErrorPolicy::Null
+    }
+
+    /// Converts the value of the i-th column of the row with the given rowid.
+    pub fn convert<'a>(
+        &self,
+        rowid: u32,
+        i: usize,
+        value: &'a DataType,
+    ) -> Result<Cow<'a, DataType>, String> {
+        let value = self.convert_type(rowid, i, value)?;
+        match value.as_ref() {
+            DataType::DateTime(serial) if self.dates != DateFormat::Serial => {
+                Ok(Cow::Owned(format_date(*serial, self.dates)))
+            }
+            DataType::Error(e) => match self.errors {
+                ErrorPolicy::Null => Ok(Cow::Owned(DataType::Empty)),
+                ErrorPolicy::Text => Ok(Cow::Owned(DataType::String(e.to_string()))),
+                ErrorPolicy::Fail => Err(format!(
+                    "Cell {}!{} contains the error {}",
+                    self.worksheet,
+                    self.get_address(rowid, i),
+                    e
+                )),
+            },
+            _ => Ok(value),
+        }
+    }
+
+    /// Returns the A1 address of the i-th column of the row with the given rowid.
+    fn get_address(&self, rowid: u32, i: usize) -> String {
+        get_cell_reference(rowid, self.first_column + i as u32)
+    }
+
+    fn convert_type<'a>(
+        &self,
+        rowid: u32,
+        i: usize,
+        value: &'a DataType,
+    ) -> Result<Cow<'a, DataType>, String> {
+        let column_type = match self.types.get(i) {
+            Some(Some(column_type)) => *column_type,
+            _ => return Ok(Cow::Borrowed(value)),
+        };
+
+        match coerce(value, column_type) {
+            Some(converted) => Ok(converted),
+            None => match self.on_type_error {
+                TypeErrorPolicy::Null => Ok(Cow::Owned(DataType::Empty)),
+                TypeErrorPolicy::Keep => Ok(Cow::Borrowed(value)),
+                TypeErrorPolicy::Fail => Err(format!(
+                    "Value '{}' in {}!{} can't be converted to {}",
+                    value,
+                    self.worksheet,
+                    self.get_address(rowid, i),
+                    column_type
+                )),
+            },
+        }
+    }
+
+    /// Converts all values of a row.
+    pub fn convert_row(&self, rowid: u32, row: &[DataType]) -> Result<Vec<DataType>, String> {
+        row.iter()
+            .enumerate()
+            .map(|(i, value)| self.convert(rowid, i, value).map(Cow::into_owned))
+            .collect()
+    }
+}
+
+/// Converts a value to the given type, `None` if the value can't be converted.
+/// Empty cells and cell errors are left as they are.
+pub fn coerce(value: &DataType, column_type: ColumnType) -> Option<Cow<'_, DataType>> {
+    let converted = match (column_type, value) {
+        (_, DataType::Empty) | (_, DataType::Error(_)) => return Some(Cow::Borrowed(value)),
+
+        (ColumnType::Integer, DataType::Int(_)) => return Some(Cow::Borrowed(value)),
+        (ColumnType::Integer, DataType::Float(f)) => DataType::Int(to_integer(*f)?),
+        (ColumnType::Integer, DataType::Bool(b)) => DataType::Int(*b as i64),
+        (ColumnType::Integer, DataType::String(s)) => match s.trim().parse::<i64>() {
+            Ok(n) => DataType::Int(n),
+            Err(_) => DataType::Int(to_integer(s.trim().parse::<f64>().ok()?)?),
+        },
+        (ColumnType::Integer, DataType::DateTime(_)) => return None,
+
+        (ColumnType::Real, DataType::Float(_)) => return Some(Cow::Borrowed(value)),
+        (ColumnType::Real, DataType::Int(n)) => DataType::Float(*n as f64),
+        (ColumnType::Real, DataType::DateTime(f)) => DataType::Float(*f),
+        (ColumnType::Real, DataType::Bool(b)) => DataType::Float(*b as i64 as f64),
+        (ColumnType::Real, DataType::String(s)) => DataType::Float(s.trim().parse::<f64>().ok()?),
+
+        (ColumnType::Text, DataType::String(_)) => return Some(Cow::Borrowed(value)),
+        (ColumnType::Text, value) => DataType::String(value.to_string()),
+
+        (ColumnType::Boolean, DataType::Bool(_)) => return Some(Cow::Borrowed(value)),
+        (ColumnType::Boolean, DataType::Int(n)) => DataType::Bool(to_bool(*n as f64)?),
+        (ColumnType::Boolean, DataType::Float(f)) => DataType::Bool(to_bool(*f)?),
+        (ColumnType::Boolean, DataType::String(s)) => {
+            match s.trim().to_ascii_lowercase().as_str() {
+                "true" | "yes" | "on" | "1" => DataType::Bool(true),
+                "false" | "no" | "off" | "0" => DataType::Bool(false),
+                _ => return None,
+            }
+        }
+        (ColumnType::Boolean, DataType::DateTime(_)) => return None,
+
+        (ColumnType::DateTime, DataType::DateTime(_)) => return Some(Cow::Borrowed(value)),
+        // numbers are taken as serial dates, as Excel does
+        (ColumnType::DateTime, DataType::Float(f)) => DataType::DateTime(*f),
+        (ColumnType::DateTime, DataType::Int(n)) => DataType::DateTime(*n as f64),
+        (ColumnType::DateTime, DataType::Bool(_)) | (ColumnType::DateTime, DataType::String(_)) => {
+            return None
+        }
+    };
+
+    Some(Cow::Owned(converted))
+}
+
+/// Days from 1899-12-30 (serial 0) to 1970-01-01.
+const UNIX_EPOCH_SERIAL: f64 = 25569.0;
+/// Julian day number of 1899-12-30 00:00.
+const JULIAN_DAY_SERIAL: f64 = 2415018.5;
+/// SQLite reads times without a date as times on 2000-01-01, which is serial 36526.
+const TIME_ONLY_SERIAL: f64 = 36526.0;
+
+/// Formats an Excel serial date.
+pub fn format_date(serial: f64, format: DateFormat) -> DataType {
+    if !serial.is_finite() || serial < 0.0 {
+        return DataType::Float(serial);
+    }
+
+    let time_only = serial < 1.0;
+    let days = if time_only {
+        TIME_ONLY_SERIAL + serial
+    } else {
+        to_actual_days(serial)
+    };
+
+    match format {
+        DateFormat::Serial => DataType::Float(serial),
+        DateFormat::Julian => DataType::Float(days + JULIAN_DAY_SERIAL),
+        DateFormat::Unix => {
+            let seconds = ((days - UNIX_EPOCH_SERIAL) * 86400.0 * 1000.0).round() / 1000.0;
+            if seconds.fract() == 0.0 {
+                DataType::Int(seconds as i64)
+            } else {
+                DataType::Float(seconds)
+            }
+        }
+        DateFormat::Iso => {
+            let millis = (days.fract() * 86_400_000.0).round() as i64;
+            // rounding may carry the time over to the next day
+            let (day, millis) = (days.trunc() as i64 + millis / 86_400_000, millis % 86_400_000);
+
+            let (hours, minutes) = (millis / 3_600_000, millis / 60_000 % 60);
+            let (seconds, millis) = (millis / 1000 % 60, millis % 1000);
+            let time = if millis > 0 {
+                format!("{:02}:{:02}:{:02}.{:03}", hours, minutes, seconds, millis)
+            } else {
+                format!("{:02}:{:02}:{:02}", hours, minutes, seconds)
+            };
+
+            if time_only {
+                return DataType::String(time);
+            }
+
+            let (year, month, day) = civil_from_days(day - UNIX_EPOCH_SERIAL as i64);
+            let date = format!("{:04}-{:02}-{:02}", year, month, day);
+            if hours == 0 && minutes == 0 && seconds == 0 && millis == 0 {
+                DataType::String(date)
+            } else {
+                DataType::String(format!("{} {}", date, time))
+            }
+        }
+    }
+}
+
+/// Converts a serial date to the number of actual days since 1899-12-30.
+/// Excel treats 1900 as a leap year: serials before the fictitious
+/// 1900-02-29 (serial 60) are one day off, serial 60 itself becomes 1900-03-01.
+fn to_actual_days(serial: f64) -> f64 {
+    if serial < 60.0 {
+        serial + 1.0
+    } else if serial < 61.0 {
+        61.0 + serial.fract()
+    } else {
+        serial
+    }
+}
+
+/// Converts days since 1970-01-01 to a (year, month, day) date of the proleptic Gregorian calendar.
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+fn to_integer(f: f64) -> Option<i64> {
+    if f.fract() == 0.0 && f.abs() < i64::MAX as f64 {
+        Some(f as i64)
+    } else {
+        None
+    }
+}
+
+fn to_bool(f: f64) -> Option<bool> {
+    if f == 0.0 {
+        Some(false)
+    } else if f == 1.0 {
+        Some(true)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn coerce_converts_compatible_values() {
+        let int = |s: &str| coerce(&DataType::String(s.to_string()), ColumnType::Integer).map(Cow::into_owned);
+
+        assert_eq!(int(" 42 "), Some(DataType::Int(42)));
+        assert_eq!(int("42.0"), Some(DataType::Int(42)));
+        assert_eq!(int("4.2"), None);
+        assert_eq!(
+            coerce(&DataType::Float(10.0), ColumnType::Text).map(Cow::into_owned),
+            Some(DataType::String("10".to_string()))
+        );
+        assert_eq!(
+            coerce(&DataType::String("Yes".to_string()), ColumnType::Boolean).map(Cow::into_owned),
+            Some(DataType::Bool(true))
+        );
+        assert_eq!(coerce(&DataType::String("x".to_string()), ColumnType::Real), None);
+    }
+
+    #[test]
+    fn convert_applies_type_error_policy() {
+        let value = DataType::String("abc".to_string());
+        let converter = |policy| {
+            ValueConverter::new("Sheet1".to_string(), 1)
+                .with_types(vec![None, Some(ColumnType::Integer)], policy)
+        };
+
+        assert_eq!(
+            converter(TypeErrorPolicy::Null).convert(2, 1, &value).unwrap().into_owned(),
+            DataType::Empty
+        );
+        assert_eq!(
+            converter(TypeErrorPolicy::Keep).convert(2, 1, &value).unwrap().into_owned(),
+            value
+        );
+        assert_eq!(
+            converter(TypeErrorPolicy::Fail).convert(2, 1, &value).unwrap_err(),
+            "Value 'abc' in Sheet1!C3 can't be converted to INTEGER"
+        );
+        assert_eq!(
+            converter(TypeErrorPolicy::Fail).convert(2, 0, &value).unwrap().into_owned(),
+            value
+        );
+    }
+
+    #[test]
+    fn convert_applies_error_policy() {
+        let value = DataType::Error(calamine::CellErrorType::Div0);
+        let converter = |errors| ValueConverter::new("Sheet1".to_string(), 0).with_errors(errors);
+
+        assert_eq!(
+            converter(ErrorPolicy::Null).convert(4, 1, &value).unwrap().into_owned(),
+            DataType::Empty
+        );
+        assert_eq!(
+            converter(ErrorPolicy::Text).convert(4, 1, &value).unwrap().into_owned(),
+            DataType::String("#DIV/0!".to_string())
+        );
+        assert_eq!(
+            converter(ErrorPolicy::Fail).convert(4, 1, &value).unwrap_err(),
+            "Cell Sheet1!B5 contains the error #DIV/0!"
+        );
+    }
+
+    #[test]
+    fn format_date_produces_iso_text() {
+        let iso = |serial| format_date(serial, DateFormat::Iso);
+
+        assert_eq!(iso(45124.5), DataType::String("2023-07-17 12:00:00".to_string()));
+        assert_eq!(iso(45124.0), DataType::String("2023-07-17".to_string()));
+        assert_eq!(iso(0.75), DataType::String("18:00:00".to_string()));
+        assert_eq!(iso(1.0), DataType::String("1900-01-01".to_string()));
+        assert_eq!(iso(59.0), DataType::String("1900-02-28".to_string()));
+        assert_eq!(iso(61.0), DataType::String("1900-03-01".to_string()));
+        assert_eq!(iso(45124.999999999), DataType::String("2023-07-18".to_string()));
+    }
+
+    #[test]
+    fn format_date_produces_julian_and_unix_numbers() {
+        assert_eq!(format_date(25569.0, DateFormat::Unix), DataType::Int(0));
+        assert_eq!(format_date(25569.5, DateFormat::Julian), DataType::Float(2440588.0));
+        assert_eq!(format_date(0.5, DateFormat::Julian), DataType::Float(2451545.0));
+    }
+}
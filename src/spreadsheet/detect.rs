@@ -0,0 +1,312 @@
+use crate::spreadsheet::cells::CellIndex;
+use crate::spreadsheet::reader::is_blank;
+use crate::spreadsheet::writer::get_cell_reference;
+use calamine::{DataType, Range};
+use std::collections::HashSet;
+
+/// The block of data picked by AUTO and `xlite_detect`.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct DetectedTable {
+    /// Absolute (row, column) position of the first cell, the header included.
+    pub start: (u32, u32),
+    /// Absolute (row, column) position of the last cell.
+    pub end: (u32, u32),
+    /// Number of header rows at the top of the block, 0 or 1.
+    pub header_rows: u32,
+}
+
+impl DetectedTable {
+    /// The RANGE of the block, e.g. `B3:D6`.
+    pub fn get_reference(&self) -> String {
+        format!(
+            "{}:{}",
+            get_cell_reference(self.start.0, self.start.1),
+            get_cell_reference(self.end.0, self.end.1)
+        )
+    }
+
+    /// The RANGE of the block without its end row, e.g. `B3:D`, so that rows added
+    /// below the table are read.
+    pub fn get_open_reference(&self) -> String {
+        format!(
+            "{}:{}",
+            get_cell_reference(self.start.0, self.start.1),
+            CellIndex::new(self.end.1 + 1, 1).get_x_as_string()
+        )
+    }
+
+    /// Number of rows of data, the header excluded.
+    pub fn get_height(&self) -> u32 {
+        self.end.0 + 1 - self.start.0 - self.header_rows
+    }
+
+    pub fn get_width(&self) -> u32 {
+        self.end.1 + 1 - self.start.1
+    }
+}
+
+/// A rectangle of the worksheet and its number of non-blank cells.
+#[derive(Debug, Copy, Clone)]
+struct Block {
+    start: (u32, u32),
+    end: (u32, u32),
+    cells: usize,
+}
+
+impl Block {
+    fn overlaps(&self, other: &Block) -> bool {
+        self.start.0 <= other.end.0
+            && other.start.0 <= self.end.0
+            && self.start.1 <= other.end.1
+            && other.start.1 <= self.end.1
+    }
+
+    fn contains(&self, position: (u32, u32)) -> bool {
+        (self.start.0..=self.end.0).contains(&position.0) && (self.start.1..=self.end.1).contains(&position.1)
+    }
+
+    fn merge(&mut self, other: &Block) {
+        self.start = (self.start.0.min(other.start.0), self.start.1.min(other.start.1));
+        self.end = (self.end.0.max(other.end.0), self.end.1.max(other.end.1));
+        self.cells += other.cells;
+    }
+}
+
+/// Picks the block of adjacent non-blank cells holding the most values, and its header row:
+/// the text-only row right above the first row holding numbers, dates or booleans.
+/// Rows above the header, e.g. a title, are left out. `None` if the worksheet is blank.
+pub fn detect_table(sheet: &Range<DataType>) -> Option<DetectedTable> {
+    let block = find_blocks(sheet)
+        .into_iter()
+        .fold(None, |best: Option<Block>, block| match best {
+            Some(best) if best.cells >= block.cells => Some(best),
+            _ => Some(block),
+        })?;
+
+    let (first_row, header_rows) = detect_header(sheet, &block);
+    Some(DetectedTable {
+        start: (first_row, block.start.1),
+        end: block.end,
+        header_rows,
+    })
+}
+
+/// Returns the last row with values in the columns of a detected table, up to the end of the
+/// block holding its first cell, the table having grown or shrunk since. `None` if no block
+/// holds the cell anymore.
+pub fn find_last_row(sheet: &Range<DataType>, start: (u32, u32), last_column: u32) -> Option<u32> {
+    let block = find_blocks(sheet).into_iter().find(|block| block.contains(start))?;
+    (start.0..=block.end.0).rev().find(|row| {
+        (start.1..=last_column).any(|column| sheet.get_value((*row, column)).is_some_and(|v| !is_blank(v)))
+    })
+}
+
+/// Returns the bounding rectangles of the groups of non-blank cells touching each other,
+/// diagonals included. Overlapping rectangles are merged, so that gaps within a table
+/// don't split it.
+fn find_blocks(sheet: &Range<DataType>) -> Vec<Block> {
+    let (origin, end) = match (sheet.start(), sheet.end()) {
+        (Some(start), Some(end)) => (start, end),
+        _ => return Vec::new(),
+    };
+    let filled = |(row, column): (u32, u32)| sheet.get_value((row, column)).is_some_and(|v| !is_blank(v));
+
+    let mut visited = HashSet::new();
+    let mut blocks: Vec<Block> = Vec::new();
+    for (row, column, value) in sheet.used_cells() {
+        let position = (origin.0 + row as u32, origin.1 + column as u32);
+        if is_blank(value) || !visited.insert(position) {
+            continue;
+        }
+
+        let mut block = Block { start: position, end: position, cells: 0 };
+        let mut pending = vec![position];
+        while let Some((row, column)) = pending.pop() {
+            block.merge(&Block { start: (row, column), end: (row, column), cells: 1 });
+            for r in row.saturating_sub(1)..=(row + 1).min(end.0) {
+                for c in column.saturating_sub(1)..=(column + 1).min(end.1) {
+                    if filled((r, c)) && visited.insert((r, c)) {
+                        pending.push((r, c));
+                    }
+                }
+            }
+        }
+        blocks.push(block);
+    }
+
+    merge_overlapping(blocks)
+}
+
+/// Merges the overlapping rectangles. Each round sweeps the rectangles by their first row,
+/// comparing each one with the rectangles still open at that row, and joins the overlapping
+/// ones with a union-find. Merging two rectangles can make the result overlap a third one,
+/// so the rounds go on until no rectangles were joined.
+fn merge_overlapping(mut blocks: Vec<Block>) -> Vec<Block> {
+    fn find(parents: &mut [usize], mut i: usize) -> usize {
+        while parents[i] != i {
+            parents[i] = parents[parents[i]];
+            i = parents[i];
+        }
+        i
+    }
+
+    loop {
+        blocks.sort_by_key(|block| block.start);
+        let mut parents: Vec<usize> = (0..blocks.len()).collect();
+        let mut joined = false;
+        let mut open: Vec<usize> = Vec::new();
+        for i in 0..blocks.len() {
+            open.retain(|j| blocks[*j].end.0 >= blocks[i].start.0);
+            for j in open.iter().copied() {
+                if blocks[i].overlaps(&blocks[j]) {
+                    let (a, b) = (find(&mut parents, i), find(&mut parents, j));
+                    if a != b {
+                        parents[a] = b;
+                        joined = true;
+                    }
+                }
+            }
+            open.push(i);
+        }
+        if !joined {
+            return blocks;
+        }
+
+        let mut groups: Vec<Option<Block>> = vec![None; blocks.len()];
+        for (i, block) in blocks.iter().enumerate() {
+            match &mut groups[find(&mut parents, i)] {
+                Some(group) => group.merge(block),
+                group => *group = Some(*block),
+            }
+        }
+        blocks = groups.into_iter().flatten().collect();
+    }
+}
+
+/// Returns the first row of the table within the block and its number of header rows.
+fn detect_header(sheet: &Range<DataType>, block: &Block) -> (u32, u32) {
+    let cells = |row: u32| -> Vec<&DataType> {
+        (block.start.1..=block.end.1)
+            .filter_map(|column| sheet.get_value((row, column)))
+            .filter(|value| !is_blank(value))
+            .collect()
+    };
+    let width = (block.end.1 + 1 - block.start.1) as usize;
+    // a header names at least half of the columns, with text only
+    let is_header = |row: u32| {
+        let cells = cells(row);
+        cells.len() * 2 >= width && cells.iter().all(|value| matches!(value, DataType::String(_)))
+    };
+
+    let first_data_row = (block.start.0..=block.end.0)
+        .find(|row| cells(*row).iter().any(|value| !matches!(value, DataType::String(_))));
+    match first_data_row {
+        Some(row) if row > block.start.0 && is_header(row - 1) => (row - 1, 1),
+        Some(_) => (block.start.0, 0),
+        // a table of text has a header when its first row names every column once
+        None => {
+            let names: Vec<String> = cells(block.start.0).iter().map(|value| value.to_string()).collect();
+            let unique: HashSet<&String> = names.iter().collect();
+            if block.start.0 < block.end.0 && names.len() == width && unique.len() == width {
+                (block.start.0, 1)
+            } else {
+                (block.start.0, 0)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sheet(cells: &[((u32, u32), DataType)]) -> Range<DataType> {
+        let mut range = Range::new((0, 0), (9, 7));
+        for (position, value) in cells {
+            range.set_value(*position, value.clone());
+        }
+        range
+    }
+
+    fn text(s: &str) -> DataType {
+        DataType::String(s.to_string())
+    }
+
+    #[test]
+    fn detect_table_skips_title_and_notes() {
+        let mut cells = vec![
+            ((0, 0), text("Sales report")),
+            ((2, 1), text("store")),
+            ((2, 2), text("month")),
+            ((2, 3), text("sales")),
+        ];
+        for row in 3..7 {
+            cells.push(((row, 1), text("north")));
+            cells.push(((row, 2), text("Jan")));
+            cells.push(((row, 3), DataType::Float(row as f64)));
+        }
+        // a gap in the table doesn't split it
+        cells.remove(cells.len() - 2);
+        cells.push(((9, 6), text("checked by Ann")));
+
+        let detected = detect_table(&sheet(&cells)).unwrap();
+
+        assert_eq!(detected, DetectedTable { start: (2, 1), end: (6, 3), header_rows: 1 });
+        assert_eq!(detected.get_reference(), "B3:D7");
+        assert_eq!(detected.get_open_reference(), "B3:D");
+        assert_eq!((detected.get_height(), detected.get_width()), (4, 3));
+    }
+
+    #[test]
+    fn detect_table_handles_text_tables() {
+        let cells = vec![
+            ((1, 1), text("name")),
+            ((1, 2), text("city")),
+            ((2, 1), text("Ann")),
+            ((2, 2), text("Oslo")),
+        ];
+
+        let detected = detect_table(&sheet(&cells)).unwrap();
+
+        assert_eq!(detected, DetectedTable { start: (1, 1), end: (2, 2), header_rows: 1 });
+    }
+
+    #[test]
+    fn detect_table_without_header() {
+        let cells = vec![((0, 0), DataType::Float(1.0)), ((1, 0), DataType::Float(2.0))];
+
+        let detected = detect_table(&sheet(&cells)).unwrap();
+
+        assert_eq!(detected, DetectedTable { start: (0, 0), end: (1, 0), header_rows: 0 });
+        assert!(detect_table(&sheet(&[])).is_none());
+    }
+
+    #[test]
+    fn find_blocks_merges_chains_of_overlaps() {
+        // a corner, a line crossing its rectangle and a cell in the union of both rectangles only
+        let mut cells: Vec<((u32, u32), DataType)> = (0..5).map(|column| ((0, column), text("top"))).collect();
+        cells.extend((1..5).map(|row| ((row, 0), text("left"))));
+        cells.extend((2..7).map(|column| ((2, column), text("line"))));
+        cells.push(((4, 6), text("corner")));
+        cells.push(((8, 7), text("far")));
+
+        let blocks = find_blocks(&sheet(&cells));
+
+        assert_eq!(blocks.len(), 2);
+        assert_eq!((blocks[0].start, blocks[0].end, blocks[0].cells), ((0, 0), (4, 6), 15));
+        assert_eq!((blocks[1].start, blocks[1].end), ((8, 7), (8, 7)));
+    }
+
+    #[test]
+    fn find_last_row_follows_the_table() {
+        let mut cells = vec![((2, 1), text("store")), ((3, 1), text("north")), ((4, 1), text("south"))];
+        assert_eq!(find_last_row(&sheet(&cells), (2, 1), 1), Some(4));
+
+        // a note touching the last row is left out, being outside of the columns
+        cells.push(((5, 1), text("east")));
+        cells.push(((6, 0), text("source")));
+        cells.push(((8, 1), text("checked by Ann")));
+        assert_eq!(find_last_row(&sheet(&cells), (2, 1), 1), Some(5));
+        assert_eq!(find_last_row(&sheet(&cells), (0, 3), 3), None);
+    }
+}
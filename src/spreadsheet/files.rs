@@ -0,0 +1,108 @@
+use crate::spreadsheet::worksheets::{is_pattern, matches_pattern};
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Extensions of the files read from a FILENAME directory.
+const SPREADSHEET_EXTENSIONS: [&str; 5] = ["xlsx", "xlsm", "xlsb", "xls", "ods"];
+
+/// What happens when one of the files of a FILENAME pattern can't be read.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum FileErrorPolicy {
+    /// Aborts the query.
+    Fail,
+    /// Leaves the file out and logs a warning.
+    Skip,
+}
+
+impl FileErrorPolicy {
+    pub fn try_parse(s: &str) -> Option<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "fail" => Some(FileErrorPolicy::Fail),
+            "skip" => Some(FileErrorPolicy::Skip),
+            _ => None,
+        }
+    }
+}
+
+/// True if FILENAME stands for several files: a directory, or a file name with
+/// `*` and `?` wildcards.
+pub fn is_file_pattern(path: &Path) -> bool {
+    path.is_dir()
+        || path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .is_some_and(is_pattern)
+}
+
+/// Lists the files of a directory having a spreadsheet extension, or the files matching
+/// the wildcards of the file name (e.g. `./inbox/*.xlsx`), sorted by name.
+/// Lock files left by Excel (`~$report.xlsx`) are ignored.
+pub fn list_files(pattern: &Path) -> io::Result<Vec<PathBuf>> {
+    let (folder, wanted) = if pattern.is_dir() {
+        (pattern, None)
+    } else {
+        let folder = match pattern.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent,
+            _ => Path::new("."),
+        };
+        (folder, pattern.file_name().and_then(|name| name.to_str()))
+    };
+
+    let mut files = Vec::new();
+    for entry in std::fs::read_dir(folder)? {
+        let path = entry?.path();
+        let name = match path.file_name().and_then(|name| name.to_str()) {
+            Some(name) => name,
+            None => continue,
+        };
+        if !path.is_file() || name.starts_with("~$") {
+            continue;
+        }
+        let selected = match wanted {
+            Some(wanted) => matches_pattern(wanted, name),
+            None => path
+                .extension()
+                .and_then(|ext| ext.to_str())
+                .is_some_and(|ext| SPREADSHEET_EXTENSIONS.iter().any(|e| e.eq_ignore_ascii_case(ext))),
+        };
+        if selected {
+            files.push(path);
+        }
+    }
+
+    files.sort();
+    Ok(files)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn is_file_pattern_detects_directories_and_wildcards() {
+        assert!(is_file_pattern(Path::new("./tests")));
+        assert!(is_file_pattern(Path::new("./tests/*.xlsx")));
+        assert!(!is_file_pattern(Path::new("./tests/abcdef.xlsx")));
+    }
+
+    #[test]
+    fn list_files_matches_wildcards_in_order() {
+        let files = list_files(Path::new("./tests/abcdef*.xlsx")).unwrap();
+
+        assert_eq!(
+            files,
+            vec![
+                PathBuf::from("./tests/abcdef.xlsx"),
+                PathBuf::from("./tests/abcdef_colnames.xlsx"),
+            ]
+        );
+    }
+
+    #[test]
+    fn list_files_reads_spreadsheets_of_directory() {
+        let files = list_files(Path::new("./tests")).unwrap();
+
+        assert!(files.contains(&PathBuf::from("./tests/abcdef.xlsx")));
+        assert!(!files.contains(&PathBuf::from("./tests/lib.rs")));
+    }
+}
@@ -0,0 +1,227 @@
+use crate::spreadsheet::cells::CellIndex;
+
+/// The last column of a worksheet, `XFD`.
+const MAX_COLUMN: i64 = 16384;
+/// The last row of a worksheet.
+const MAX_ROW: i64 = 1048576;
+
+/// Moves the relative references of a formula by `rows` and `columns`, as Excel does when
+/// a formula is copied, e.g. `B2*C2` moved one row down is `B3*C3`. References anchored
+/// with `$` are kept, references moved out of the worksheet become `#REF!`.
+pub fn translate_formula(formula: &str, rows: i64, columns: i64) -> String {
+    if rows == 0 && columns == 0 {
+        return formula.to_string();
+    }
+
+    let chars: Vec<char> = formula.chars().collect();
+    let mut out = String::with_capacity(formula.len());
+    let mut i = 0;
+    while i < chars.len() {
+        let ch = chars[i];
+        match ch {
+            // text literals and quoted sheet names
+            '"' | '\'' => {
+                let end = find_quote_end(&chars, i, ch);
+                out.extend(&chars[i..end]);
+                i = end;
+            }
+            // structured references, e.g. `Table1[Amount]`
+            '[' => {
+                let end = find_bracket_end(&chars, i);
+                out.extend(&chars[i..end]);
+                i = end;
+            }
+            _ if is_name_char(ch) || ch == '$' => {
+                let follows_name = i > 0 && (is_name_char(chars[i - 1]) || chars[i - 1] == '\\');
+                let reference = if follows_name { None } else { parse_reference(&chars, i) };
+                match reference {
+                    Some((reference, end)) => {
+                        out.push_str(reference.translate(rows, columns).as_str());
+                        i = end;
+                    }
+                    None => {
+                        // the rest of a name or a number, e.g. `LOG10` or `Sheet1`
+                        let end = (i + 1..chars.len()).find(|j| !is_name_char(chars[*j])).unwrap_or(chars.len());
+                        out.extend(&chars[i..end]);
+                        i = end;
+                    }
+                }
+            }
+            _ => {
+                out.push(ch);
+                i += 1;
+            }
+        }
+    }
+    out
+}
+
+fn is_name_char(ch: char) -> bool {
+    ch.is_alphanumeric() || ch == '_' || ch == '.'
+}
+
+/// Returns the position after the closing quote, `""` and `''` being escaped quotes.
+fn find_quote_end(chars: &[char], start: usize, quote: char) -> usize {
+    let mut i = start + 1;
+    while i < chars.len() {
+        if chars[i] == quote {
+            if chars.get(i + 1) == Some(&quote) {
+                i += 2;
+                continue;
+            }
+            return i + 1;
+        }
+        i += 1;
+    }
+    chars.len()
+}
+
+fn find_bracket_end(chars: &[char], start: usize) -> usize {
+    let mut depth = 0;
+    for (i, ch) in chars.iter().enumerate().skip(start) {
+        match ch {
+            '[' => depth += 1,
+            ']' => {
+                depth -= 1;
+                if depth == 0 {
+                    return i + 1;
+                }
+            }
+            _ => {}
+        }
+    }
+    chars.len()
+}
+
+/// A part of a reference: a column, a row, or both for a cell.
+enum Reference {
+    Cell(Part, Part),
+    Columns(Part, Part),
+    Rows(Part, Part),
+}
+
+/// A 1-based column or row number and whether it is anchored with `$`.
+#[derive(Copy, Clone)]
+struct Part {
+    value: i64,
+    absolute: bool,
+}
+
+impl Reference {
+    fn translate(&self, rows: i64, columns: i64) -> String {
+        let column = |part: &Part| move_part(part, columns, MAX_COLUMN).map(|p| format_column(&p));
+        let row = |part: &Part| move_part(part, rows, MAX_ROW).map(|p| format_row(&p));
+        let translated = match self {
+            Reference::Cell(c, r) => column(c).zip(row(r)).map(|(c, r)| format!("{}{}", c, r)),
+            Reference::Columns(a, b) => column(a).zip(column(b)).map(|(a, b)| format!("{}:{}", a, b)),
+            Reference::Rows(a, b) => row(a).zip(row(b)).map(|(a, b)| format!("{}:{}", a, b)),
+        };
+        translated.unwrap_or_else(|| "#REF!".to_string())
+    }
+}
+
+fn move_part(part: &Part, offset: i64, max: i64) -> Option<Part> {
+    if part.absolute {
+        return Some(*part);
+    }
+    let value = part.value + offset;
+    if (1..=max).contains(&value) {
+        Some(Part { value, absolute: false })
+    } else {
+        None
+    }
+}
+
+fn format_column(part: &Part) -> String {
+    let letters = CellIndex::new(part.value as u32, 0).get_x_as_string();
+    if part.absolute {
+        format!("${}", letters)
+    } else {
+        letters
+    }
+}
+
+fn format_row(part: &Part) -> String {
+    if part.absolute {
+        format!("${}", part.value)
+    } else {
+        part.value.to_string()
+    }
+}
+
+/// Parses a cell reference (`B2`, `$B$2`), a column range (`B:C`) or a row range (`2:3`)
+/// at `start`, returning it with the position after it.
+fn parse_reference(chars: &[char], start: usize) -> Option<(Reference, usize)> {
+    let is_end = |i: usize| chars.get(i).is_none_or(|ch| !is_name_char(*ch) && *ch != '(');
+
+    if let Some((column, i)) = parse_column(chars, start) {
+        if let Some((row, end)) = parse_row(chars, i) {
+            if is_end(end) {
+                return Some((Reference::Cell(column, row), end));
+            }
+        }
+        if chars.get(i) == Some(&':') {
+            if let Some((last, end)) = parse_column(chars, i + 1) {
+                if is_end(end) {
+                    return Some((Reference::Columns(column, last), end));
+                }
+            }
+        }
+        return None;
+    }
+
+    let (row, i) = parse_row(chars, start)?;
+    if chars.get(i) == Some(&':') {
+        if let Some((last, end)) = parse_row(chars, i + 1) {
+            if is_end(end) {
+                return Some((Reference::Rows(row, last), end));
+            }
+        }
+    }
+    None
+}
+
+fn parse_column(chars: &[char], start: usize) -> Option<(Part, usize)> {
+    let absolute = chars.get(start) == Some(&'$');
+    let first = if absolute { start + 1 } else { start };
+    let end = (first..chars.len()).find(|i| !chars[*i].is_ascii_alphabetic()).unwrap_or(chars.len());
+    if end == first || end - first > 3 {
+        return None;
+    }
+    let value = chars[first..end]
+        .iter()
+        .fold(0, |n, ch| n * 26 + (ch.to_ascii_uppercase() as i64 - 'A' as i64 + 1));
+    (value <= MAX_COLUMN).then_some((Part { value, absolute }, end))
+}
+
+fn parse_row(chars: &[char], start: usize) -> Option<(Part, usize)> {
+    let absolute = chars.get(start) == Some(&'$');
+    let first = if absolute { start + 1 } else { start };
+    let end = (first..chars.len()).find(|i| !chars[*i].is_ascii_digit()).unwrap_or(chars.len());
+    if end == first || end - first > 7 {
+        return None;
+    }
+    let value: i64 = chars[first..end].iter().collect::<String>().parse().ok()?;
+    (1..=MAX_ROW).contains(&value).then_some((Part { value, absolute }, end))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn translate_formula_moves_relative_references() {
+        assert_eq!(translate_formula("B2*C2", 1, 0), "B3*C3");
+        assert_eq!(translate_formula("SUM($B$2:B2)+C$1", 2, 1), "SUM($B$2:C4)+D$1");
+        assert_eq!(translate_formula("SUM(B:B)+SUM(3:4)", 1, 1), "SUM(C:C)+SUM(4:5)");
+        assert_eq!(translate_formula("'Sheet 1'!A1+Sheet2!A1", 1, 0), "'Sheet 1'!A2+Sheet2!A2");
+    }
+
+    #[test]
+    fn translate_formula_keeps_names_and_text() {
+        assert_eq!(translate_formula("LOG10(A1)&\"B2\"", 1, 0), "LOG10(A2)&\"B2\"");
+        assert_eq!(translate_formula("Sales_A1+Table1[B2]+2.5", 1, 0), "Sales_A1+Table1[B2]+2.5");
+        assert_eq!(translate_formula("A2-1", -1, 0), "A1-1");
+        assert_eq!(translate_formula("A1", -1, 0), "#REF!");
+    }
+}
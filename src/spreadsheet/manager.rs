@@ -1,86 +1,1050 @@
 use crate::options::UsingOption;
 use crate::spreadsheet::{
-    cells::{CellIndex, CellRange},
-    reader::DataReader,
+    cache::get_cache,
+    cells::{split_areas, split_sheet, CellIndex, CellRange},
+    files::{is_file_pattern, list_files, FileErrorPolicy},
+    provenance::{Origin, Provenance, RowProvenance},
+    columns::{infer_column_types, parse_schema, sanitize_names, Column, ColumnType, NameStyle, DEFAULT_SAMPLE_ROWS},
+    convert::{DateFormat, ErrorPolicy, TypeErrorPolicy, ValueConverter},
+    constraints::{Constraint, ConstraintTarget},
+    detect::{detect_table, find_last_row},
+    reader::{is_blank, DataReader, Segment},
+    writer::{write_worksheet, SheetChanges},
+    worksheets::{find_worksheet, find_worksheet_by_index, select_worksheets},
+    xlsx::{read_merged_cells, read_table_entries, PackageError},
 };
-use calamine::{open_workbook_auto, DataType, Range, Reader, Sheets};
+use calamine::{DataType, Range, Reader};
+use std::collections::BTreeSet;
+use std::fmt;
 use std::fs::File;
 use std::io::BufReader;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::str::FromStr;
+use std::sync::Arc;
+use zip::ZipArchive;
 
 pub struct DataManager {
-    sheets: Sheets<BufReader<File>>,
+    file: PathBuf,
     worksheet: String,
+    // the worksheets of WORKSHEETS read one after another, empty for a single worksheet
+    worksheets: Vec<String>,
+    // the directory or the file name with wildcards of FILENAME, `file` is its first readable file
+    file_pattern: Option<PathBuf>,
+    on_file_error: FileErrorPolicy,
+    // the files skipped by the last reads, reported by `take_warnings`
+    warnings: Vec<String>,
+    union: Option<UnionRange>,
+    // adds the PROVENANCE hidden columns `_file`, `_sheet`, `_row` and `_address`
+    provenance: bool,
+    // adds a hidden `<column>_formula` column for each column with FORMULAS
+    formulas: bool,
+    formula_names: Vec<String>,
+    // number of columns returned by `get_columns`, the computed hidden columns follow them
+    column_count: usize,
+    // copies the value of merged cells into every cell of their region with MERGED 'fill'
+    merged: bool,
+    // 0-based columns of FILLDOWN, whose blank cells take the value above
+    filldown: Vec<u32>,
+    filled: Vec<FilledSheet>,
+    // leaves out the rows whose cells are all blank
+    skip_empty: bool,
+    // drops the blank rows and columns at the end of the table
+    trim: bool,
+    // ends the table before its first blank row
+    until_blank: bool,
+    // ends the table detected by AUTO at the last row of its block of cells, found again by every query
+    auto: bool,
     range: Option<CellRange>,
+    // NAME, resolved again by every query so that the table follows the range
+    name: Option<String>,
     colnames_row: Option<u32>,
+    // rows at the top of the range holding the column names, excluded from the data
+    header_rows: u32,
+    header_separator: String,
+    // names of the columns of a TABLE, taking precedence over the header rows
+    column_names: Option<Vec<String>>,
+    sample: usize,
+    // names and declared types from SCHEMA
+    schema: Option<Vec<(String, Option<String>)>>,
+    // declared types from TYPES, `None` keeps the inferred type
+    types: Option<Vec<Option<String>>>,
+    on_type_error: TypeErrorPolicy,
+    dates: DateFormat,
+    errors: ErrorPolicy,
+    names: NameStyle,
+    rows_hint: Option<(u32, usize)>,
+    pending: Option<PendingChanges>,
+    snapshot: Option<Arc<Range<DataType>>>,
 }
 
+/// The worksheet and the cells of the table, as resolved from WORKSHEET, SHEET_INDEX or TABLE.
+/// Kept with a snapshot, so that the table opens once the file is gone.
+#[derive(Debug, Clone, Default)]
+pub struct TableSource {
+    pub worksheet: String,
+    pub range: Option<String>,
+    pub header_rows: u32,
+    pub column_names: Option<Vec<String>>,
+}
+
+/// Absolute (row, column) positions of the first and the last cell of a table.
+type Bounds = ((u32, u32), (u32, u32));
+
+/// A worksheet read by a table over several worksheets or files.
+struct Source {
+    file: PathBuf,
+    worksheet: String,
+    range: Arc<Range<DataType>>,
+}
+
+impl Source {
+    fn is_same(&self, other: &Source) -> bool {
+        self.file == other.file && self.worksheet == other.worksheet && Arc::ptr_eq(&self.range, &other.range)
+    }
+}
+
+/// The tables of the worksheets of WORKSHEETS or of a FILENAME pattern read one after
+/// another, and the sources they were read from.
+struct UnionRange {
+    sources: Vec<Source>,
+    rows: TableRows,
+    origins: Vec<Origin>,
+}
+
+/// The rows of a table: the effective range of the worksheet, or the tables of a union.
+#[derive(Clone)]
+struct TableRows {
+    segments: Vec<Segment>,
+    // the first and the last worksheet column of the table, `None` without rows
+    columns: Option<(u32, u32)>,
+}
+
+impl TableRows {
+    fn of_range(range: Arc<Range<DataType>>) -> Self {
+        let columns = range.start().zip(range.end()).map(|(start, end)| (start.1, end.1));
+        Self {
+            segments: vec![Segment::of_range(range)],
+            columns,
+        }
+    }
+
+    /// The first rowid and the number of rows.
+    fn get_hint(&self) -> (u32, usize) {
+        let first_rowid = self.segments.first().map(|s| s.first_rowid).unwrap_or(0);
+        (first_rowid, self.segments.iter().map(|s| s.range.height()).sum())
+    }
+}
+
+/// A worksheet with its MERGED and FILLDOWN cells filled, kept until the worksheet changes.
+struct FilledSheet {
+    file: PathBuf,
+    worksheet: String,
+    source: Arc<Range<DataType>>,
+    filled: Arc<Range<DataType>>,
+}
+
+/// Changes made in the current transaction, written to the file by `sync`.
+/// Deleted rows are only marked, so that rowids stay stable until the commit.
+#[derive(Clone)]
+struct PendingChanges {
+    sheet: Arc<Range<DataType>>,
+    deleted: Arc<BTreeSet<u32>>,
+}
+
+#[derive(Debug)]
 pub enum DataManagerError {
     NoFilename,
     NoWorksheet,
     Calamine(calamine::Error),
+    Package(PackageError),
+    Snapshot(String),
+    Unsupported(String),
+}
+
+impl fmt::Display for DataManagerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DataManagerError::NoFilename => write!(f, "Filename is not provided"),
+            DataManagerError::NoWorksheet => write!(f, "Worksheet is not provided"),
+            DataManagerError::Calamine(e) => write!(f, "{}", e),
+            DataManagerError::Package(e) => write!(f, "{}", e),
+            DataManagerError::Snapshot(msg) => write!(f, "Snapshot error: {}", msg),
+            DataManagerError::Unsupported(msg) => write!(f, "{}", msg),
+        }
+    }
 }
 
 impl DataManager {
-    pub fn get_sheets(&mut self) -> &mut Sheets<BufReader<File>> {
-        &mut self.sheets
+    pub fn get_file(&self) -> &Path {
+        self.file.as_path()
     }
 
-    pub fn get_effective_range(&mut self) -> Range<DataType> {
-        let range = self.sheets.worksheet_range(self.worksheet.as_str());
-        if let Some(Ok(r)) = range {
-            match self.range {
-                Some(sub) => {
-                    let start = sub.get_start();
-                    let mut end = sub.get_end();
+    pub fn get_worksheet(&self) -> &str {
+        self.worksheet.as_str()
+    }
 
-                    if end.get_y() == 0 {
-                        end = CellIndex::new(end.get_x(), r.height() as u32)
-                    }
+    pub fn get_source(&self) -> TableSource {
+        TableSource {
+            worksheet: self.worksheet.clone(),
+            range: self.range.map(|range| range.get_reference()),
+            header_rows: self.header_rows,
+            column_names: self.column_names.clone(),
+        }
+    }
+
+    pub fn get_colnames_row(&self) -> Option<u32> {
+        self.colnames_row
+    }
+
+    /// Names of the hidden columns of the table: `_sheet` for WORKSHEETS, `_file`
+    /// for a FILENAME pattern, the other PROVENANCE columns, then the FORMULAS columns.
+    pub fn get_hidden_columns(&self) -> Vec<String> {
+        self.get_reserved_names()
+            .into_iter()
+            .chain(self.formula_names.iter().cloned())
+            .collect()
+    }
+
+    /// The names of the label and PROVENANCE columns, which the data columns may not take.
+    fn get_reserved_names(&self) -> Vec<String> {
+        self.get_label_columns()
+            .into_iter()
+            .chain(self.get_provenance_columns())
+            .map(|column| column.get_name().to_string())
+            .collect()
+    }
+
+    /// The hidden columns telling the worksheet or the file of the rows of a union.
+    fn get_label_columns(&self) -> Vec<Provenance> {
+        let mut labels = Vec::new();
+        if !self.worksheets.is_empty() {
+            labels.push(Provenance::Sheet);
+        }
+        if self.file_pattern.is_some() {
+            labels.push(Provenance::File);
+        }
+        labels
+    }
+
+    /// The PROVENANCE columns computed from the rowids, following the label columns.
+    fn get_provenance_columns(&self) -> Vec<Provenance> {
+        if !self.provenance {
+            return Vec::new();
+        }
+        let labels = self.get_label_columns();
+        Provenance::ALL
+            .into_iter()
+            .filter(|column| !labels.contains(column))
+            .collect()
+    }
+
+    /// Tables created with SNAPSHOT read the cells stored in the database instead of the file.
+    pub fn is_snapshot(&self) -> bool {
+        self.snapshot.is_some()
+    }
+
+    pub fn set_snapshot(&mut self, range: Range<DataType>) {
+        self.snapshot = Some(Arc::new(range));
+    }
 
-                    r.range(start.to_zero_indexed(), end.to_zero_indexed())
+    /// Reads the whole worksheet from the file, ignoring the snapshot and uncommitted changes.
+    pub fn read_worksheet_range(&self) -> Result<Arc<Range<DataType>>, DataManagerError> {
+        match get_cache().worksheet_range(self.file.as_path(), self.worksheet.as_str()) {
+            Ok(Some(range)) => Ok(range),
+            Ok(None) => Err(DataManagerError::Unsupported(format!(
+                "Worksheet '{}' not found",
+                self.worksheet
+            ))),
+            Err(err) => Err(DataManagerError::Calamine(err)),
+        }
+    }
+
+    /// Returns the first and the last worksheet column of the table, `None` if it has no rows.
+    pub fn get_column_span(&mut self) -> Result<Option<(u32, u32)>, DataManagerError> {
+        Ok(self.load_rows()?.columns)
+    }
+
+    fn load_rows(&mut self) -> Result<TableRows, DataManagerError> {
+        self.refresh_name()?;
+        let rows = if self.is_union() {
+            self.load_union_rows()?
+        } else {
+            let sheet = self.load_filled_range()?;
+            TableRows::of_range(self.to_effective_range(sheet))
+        };
+        self.rows_hint = Some(rows.get_hint());
+        Ok(rows)
+    }
+
+    /// Takes the worksheet and the range of NAME from the workbook again, the name may have
+    /// been moved or resized since the table was created. Uncommitted changes and snapshots
+    /// keep the range they were made with.
+    fn refresh_name(&mut self) -> Result<(), DataManagerError> {
+        let name = match &self.name {
+            Some(name) if self.pending.is_none() && self.snapshot.is_none() => name,
+            _ => return Ok(()),
+        };
+        let (worksheet, cells) = find_defined_range(self.file.as_path(), name.as_str())?;
+        self.range = CellRange::try_parse(cells.as_str());
+        if !worksheet.eq_ignore_ascii_case(self.worksheet.as_str()) {
+            self.worksheet = worksheet;
+        }
+        Ok(())
+    }
+
+    /// Returns the first row index and the number of rows of the last loaded
+    /// effective range, without parsing the worksheet again.
+    pub fn get_rows_hint(&self) -> Option<(u32, usize)> {
+        self.rows_hint
+    }
+
+    fn load_worksheet_range(&self) -> Result<Arc<Range<DataType>>, DataManagerError> {
+        // uncommitted changes take precedence over the file
+        if let Some(pending) = &self.pending {
+            return Ok(Arc::clone(&pending.sheet));
+        }
+        if let Some(snapshot) = &self.snapshot {
+            return Ok(Arc::clone(snapshot));
+        }
+        self.read_worksheet_range()
+    }
+
+    /// The worksheet with its MERGED and FILLDOWN cells filled.
+    fn load_filled_range(&mut self) -> Result<Arc<Range<DataType>>, DataManagerError> {
+        let sheet = self.load_worksheet_range()?;
+        let (file, worksheet) = (self.file.clone(), self.worksheet.clone());
+        self.fill_sheet(file.as_path(), worksheet.as_str(), sheet)
+    }
+
+    /// Copies the value of the first cell of each merged region into the other cells of
+    /// the region with MERGED 'fill', then the last non-blank value of the FILLDOWN columns
+    /// into the blank cells below it, within the rows of the table.
+    fn fill_sheet(
+        &mut self,
+        file: &Path,
+        worksheet: &str,
+        sheet: Arc<Range<DataType>>,
+    ) -> Result<Arc<Range<DataType>>, DataManagerError> {
+        if !self.merged && self.filldown.is_empty() {
+            return Ok(sheet);
+        }
+        if let Some(entry) = self
+            .filled
+            .iter()
+            .find(|e| e.file == file && e.worksheet == worksheet && Arc::ptr_eq(&e.source, &sheet))
+        {
+            return Ok(Arc::clone(&entry.filled));
+        }
+
+        let mut filled = (*sheet).clone();
+        if let (true, Some(last)) = (self.merged, sheet.end()) {
+            for region in read_merged_regions(file, worksheet)? {
+                let (start, end) = match CellRange::try_parse(region.as_str()) {
+                    Some(range) => (range.get_start().to_zero_indexed(), range.get_end().to_zero_indexed()),
+                    None => continue,
+                };
+                let value = match sheet.get_value(start) {
+                    Some(value) if *value != DataType::Empty => value.clone(),
+                    _ => continue,
+                };
+                // regions spanning whole rows or columns are filled up to the last used cell
+                for row in start.0..=end.0.min(last.0) {
+                    for column in start.1..=end.1.min(last.1) {
+                        if (row, column) != start {
+                            set_cell_value(&mut filled, (row, column), value.clone());
+                        }
+                    }
+                }
+            }
+        }
+        if let Some((start, end)) = self.get_bounds(&filled) {
+            for &column in &self.filldown {
+                let mut above: Option<DataType> = None;
+                for row in start.0..=end.0 {
+                    match filled.get_value((row, column)) {
+                        Some(value) if !is_blank(value) => above = Some(value.clone()),
+                        _ => {
+                            if let Some(value) = &above {
+                                set_cell_value(&mut filled, (row, column), value.clone());
+                            }
+                        }
+                    }
                 }
-                None => r,
             }
+        }
+
+        let filled = Arc::new(filled);
+        self.filled.retain(|e| !(e.file == file && e.worksheet == worksheet));
+        self.filled.push(FilledSheet {
+            file: file.to_path_buf(),
+            worksheet: worksheet.to_string(),
+            source: sheet,
+            filled: Arc::clone(&filled),
+        });
+        Ok(filled)
+    }
+
+    /// True for tables reading several worksheets or files, stacked into one range.
+    fn is_union(&self) -> bool {
+        !self.worksheets.is_empty() || self.file_pattern.is_some()
+    }
+
+    /// Returns the worksheet ranges the table reads: the WORKSHEETS of every file of
+    /// the FILENAME pattern. Files that can't be read fail the query or are skipped
+    /// with a warning, depending on FILE_ERRORS.
+    fn load_sources(&mut self) -> Result<Vec<Source>, DataManagerError> {
+        let files = match &self.file_pattern {
+            Some(pattern) => list_files(pattern).map_err(|err| {
+                DataManagerError::Unsupported(format!("Can't list the files of '{}': {}", pattern.display(), err))
+            })?,
+            None => vec![self.file.clone()],
+        };
+        let worksheets = if self.worksheets.is_empty() {
+            vec![self.worksheet.clone()]
         } else {
-            Range::empty()
+            self.worksheets.clone()
+        };
+
+        let mut sources = Vec::new();
+        'files: for file in files {
+            let mut file_sources = Vec::with_capacity(worksheets.len());
+            for worksheet in &worksheets {
+                let range = match get_cache().worksheet_range(file.as_path(), worksheet.as_str()) {
+                    Ok(Some(range)) => Ok(range),
+                    Ok(None) if self.file_pattern.is_none() => Ok(Arc::new(Range::empty())),
+                    Ok(None) => Err(format!("worksheet '{}' not found", worksheet)),
+                    Err(err) => Err(err.to_string()),
+                };
+                let range = range.and_then(|range| {
+                    self.fill_sheet(file.as_path(), worksheet.as_str(), range)
+                        .map_err(|err| err.to_string())
+                });
+                match range {
+                    Ok(range) => file_sources.push(Source {
+                        file: file.clone(),
+                        worksheet: worksheet.clone(),
+                        range,
+                    }),
+                    Err(err) => {
+                        let message = format!("Can't read '{}': {}", file.display(), err);
+                        match self.on_file_error {
+                            FileErrorPolicy::Fail => return Err(DataManagerError::Unsupported(message)),
+                            FileErrorPolicy::Skip => {
+                                self.warnings.push(message);
+                                continue 'files;
+                            }
+                        }
+                    }
+                }
+            }
+            sources.append(&mut file_sources);
+        }
+        Ok(sources)
+    }
+
+    /// Reads the tables of the worksheets and files one after another, numbering their rows
+    /// from 0 so that rowids are unique. The tables are aligned on their first column and
+    /// kept until one of the worksheets changes.
+    fn load_union_rows(&mut self) -> Result<TableRows, DataManagerError> {
+        let sources = self.load_sources()?;
+        if let Some(union) = &self.union {
+            if union.sources.len() == sources.len() && union.sources.iter().zip(&sources).all(|(a, b)| a.is_same(b)) {
+                return Ok(union.rows.clone());
+            }
+        }
+
+        let tables: Vec<Arc<Range<DataType>>> = sources
+            .iter()
+            .map(|source| self.to_effective_range(Arc::clone(&source.range)))
+            .collect();
+        let mut origins = Vec::new();
+        let mut segments = Vec::new();
+        let columns = match tables.iter().filter_map(|t| t.start()).map(|start| start.1).min() {
+            Some(first_column) => {
+                let width = match &self.schema {
+                    Some(schema) => schema.len() as u32,
+                    None => tables
+                        .iter()
+                        .filter_map(|t| t.end())
+                        .map(|end| end.1 + 1 - first_column)
+                        .max()
+                        .unwrap_or(0),
+                };
+                let mut rowid = 0;
+                for (source, table) in sources.iter().zip(tables) {
+                    let (start, end) = match (table.start(), table.end()) {
+                        (Some(start), Some(end)) => (start, end),
+                        _ => continue,
+                    };
+                    origins.push(Origin {
+                        first_rowid: rowid,
+                        file: source.file.display().to_string(),
+                        worksheet: source.worksheet.clone(),
+                        first_row: start.0,
+                        columns: (start.1, end.1),
+                        formulas: None,
+                    });
+                    segments.push(Segment {
+                        first_rowid: rowid,
+                        offset: (start.1 - first_column) as usize,
+                        width: width as usize,
+                        range: Arc::clone(&table),
+                    });
+                    rowid += table.height() as u32;
+                }
+                (width > 0).then(|| (first_column, first_column + width - 1))
+            }
+            None => None,
+        };
+
+        let rows = TableRows { segments, columns };
+        self.union = Some(UnionRange {
+            sources,
+            rows: rows.clone(),
+            origins,
+        });
+        Ok(rows)
+    }
+
+    /// Returns the warnings about the files skipped since the last call.
+    pub fn take_warnings(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.warnings)
+    }
+
+    fn to_effective_range(&self, sheet: Arc<Range<DataType>>) -> Arc<Range<DataType>> {
+        if sheet.is_empty() {
+            return Arc::new(Range::empty());
+        }
+        match (self.range, self.get_bounds(&sheet)) {
+            (None, _) if self.header_rows == 0 && !self.trim && !self.until_blank => sheet,
+            (_, Some((start, end))) => Arc::new(sheet.range(start, end)),
+            (_, None) => Arc::new(Range::empty()),
         }
     }
 
-    pub fn get_columns(&mut self) -> Vec<String> {
-        let range = self.get_effective_range();
-        if range.get_size().1 > 0 {
-            let row_workspace_sheet = self.colnames_row
-                .and_then(|v| Some((v, self.sheets.worksheet_range(self.worksheet.as_str()))))
-                .and_then(|(row, sheet)| Some((row, sheet?.ok()?)));
-            (range.start().unwrap().1..=range.end().unwrap().1)
+    /// Returns the absolute (row, column) positions of the first and the last cell
+    /// of the table within the worksheet, `None` if the table has no rows.
+    /// The HEADER rows are not part of the table.
+    fn get_bounds(&self, sheet: &Range<DataType>) -> Option<Bounds> {
+        let (mut start, mut end) = match self.range {
+            Some(sub) => sub.resolve(sheet.start()?, sheet.end()?),
+            None => (sheet.start()?, sheet.end()?),
+        };
+        let first_row = start.0;
+        start.0 += self.header_rows;
+        if start.0 > end.0 || start.1 > end.1 {
+            return None;
+        }
+
+        let is_blank_row = |row: u32, (first, last): (u32, u32)| {
+            (first..=last).all(|column| sheet.get_value((row, column)).is_none_or(is_blank))
+        };
+        if self.until_blank {
+            match (start.0..=end.0).find(|row| is_blank_row(*row, (start.1, end.1))) {
+                Some(row) if row == start.0 => return None,
+                Some(row) => end.0 = row - 1,
+                None => {}
+            }
+        }
+        if self.auto {
+            // the rows added below the table belong to it, the notes after a gap don't
+            if let Some(last_row) = find_last_row(sheet, (first_row, start.1), end.1) {
+                end.0 = end.0.min(last_row);
+            }
+            if start.0 > end.0 {
+                return None;
+            }
+        }
+        if self.trim {
+            end.0 = (start.0..=end.0).rev().find(|row| !is_blank_row(*row, (start.1, end.1)))?;
+            // a column named by the header is kept
+            end.1 = (start.1..=end.1)
+                .rev()
+                .find(|column| (first_row..=end.0).any(|row| sheet.get_value((row, *column)).is_some_and(|v| !is_blank(v))))?;
+        }
+        Some((start, end))
+    }
+
+    /// Returns the columns of the table. The columns declared by SCHEMA are used as they are,
+    /// otherwise the types are taken from TYPES or inferred from the first SAMPLE rows.
+    pub fn get_columns(&mut self) -> Result<Vec<Column>, DataManagerError> {
+        let columns = self.read_columns()?;
+        self.column_count = columns.len();
+        if self.formulas {
+            self.formula_names = columns.iter().map(|c| format!("{}_formula", c.get_name())).collect();
+        }
+        Ok(columns)
+    }
+
+    fn read_columns(&mut self) -> Result<Vec<Column>, DataManagerError> {
+        // the names of WORKSHEETS are taken from the first worksheet, the types from all of them
+        let sheet = self.load_filled_range()?;
+        let range = self.to_effective_range(Arc::clone(&sheet));
+        let rows = self.load_rows()?;
+
+        if let Some(schema) = &self.schema {
+            return Ok(schema
+                .iter()
+                .map(|(name, declared)| Column::with_declared_type(name.clone(), declared.clone()))
+                .collect());
+        }
+
+        // a table holding only its HEADER rows takes the names from them, the columns are untyped
+        if rows.columns.is_none() && self.header_rows > 0 && !self.is_union() {
+            return Ok(self.read_header_columns(&sheet));
+        }
+
+        if let Some((first_column, last_column)) = rows.columns {
+            // the colnames row is a row of the first worksheet only, not a rowid of the union
+            let colnames_row = if self.is_union() { None } else { self.colnames_row };
+            let width = (last_column + 1 - first_column) as usize;
+            let sampled = DataReader::with_segments(rows.segments, Vec::new(), None).into_rows();
+            let types = infer_column_types(width, sampled, self.sample, colnames_row);
+            let declared = self.types.clone().unwrap_or_default();
+            let first_row = range.start().map(|start| start.0);
+            let names = sanitize_names(
+                (first_column..=last_column)
+                    .map(|n| {
+                        let header = if let Some(names) = &self.column_names {
+                            names.get((n - first_column) as usize).cloned()
+                        } else if self.header_rows > 0 {
+                            first_row.map(|first_row| self.get_header_name(&sheet, first_row, n))
+                        } else {
+                            self.colnames_row
+                                .and_then(|row| sheet.get_value((row, n)).map(|v| v.to_string()))
+                        };
+                        (header, CellIndex::new(n + 1, 1).get_x_as_string())
+                    })
+                    .collect(),
+                self.names,
+                &self.get_reserved_names(),
+                self.formulas,
+            );
+            Ok(names
                 .into_iter()
+                .zip(types)
+                .enumerate()
+                .map(|(i, (name, column_type))| match declared.get(i) {
+                    Some(Some(declared)) => Column::with_declared_type(name, Some(declared.clone())),
+                    _ => Column::with_type(name, column_type),
+                })
+                .collect())
+        } else {
+            Ok(Vec::new())
+        }
+    }
+
+    /// Returns the columns named by the HEADER rows of a table without data rows.
+    fn read_header_columns(&self, sheet: &Range<DataType>) -> Vec<Column> {
+        let (start, end) = match (self.range, sheet.start().zip(sheet.end())) {
+            (Some(sub), Some((used_start, used_end))) => sub.resolve(used_start, used_end),
+            (None, Some(used)) => used,
+            (_, None) => return Vec::new(),
+        };
+        if start.1 > end.1 {
+            return Vec::new();
+        }
+
+        let first_row = start.0 + self.header_rows;
+        let declared = self.types.clone().unwrap_or_default();
+        let names = sanitize_names(
+            (start.1..=end.1)
                 .map(|n| {
-                    row_workspace_sheet
-                        .as_ref()
-                        .and_then(|(row, sheet)| sheet.get_value((*row, n)).map(|v| v.to_string()))
-                        .unwrap_or_else(|| CellIndex::new(n + 1, 1).get_x_as_string())
+                    let header = self.get_header_name(sheet, first_row, n);
+                    (Some(header), CellIndex::new(n + 1, 1).get_x_as_string())
                 })
-                .collect()
+                .collect(),
+            self.names,
+            &self.get_reserved_names(),
+            self.formulas,
+        );
+        names
+            .into_iter()
+            .enumerate()
+            .map(|(i, name)| match declared.get(i) {
+                Some(Some(declared)) => Column::with_declared_type(name, Some(declared.clone())),
+                _ => Column::new(name),
+            })
+            .collect()
+    }
+
+    /// Joins the non-empty HEADER cells above the first row of the table in the column.
+    fn get_header_name(&self, sheet: &Range<DataType>, first_row: u32, column: u32) -> String {
+        (first_row.saturating_sub(self.header_rows)..first_row)
+            .filter_map(|row| sheet.get_value((row, column)))
+            .map(|value| value.to_string())
+            .filter(|value| !value.trim().is_empty())
+            .collect::<Vec<_>>()
+            .join(self.header_separator.as_str())
+    }
+
+    pub fn read(&mut self, mut constraints: Vec<Constraint>) -> Result<DataReader, DataManagerError> {
+        let rows = self.load_rows()?;
+        let converter = self.get_converter(rows.columns.map(|columns| columns.0).unwrap_or(0));
+
+        let provenance = self.get_row_provenance(&rows)?;
+        if let Some(provenance) = &provenance {
+            // the computed columns are not part of the rows, SQLite checks them
+            constraints.retain(|c| match c.get_target() {
+                ConstraintTarget::Column(i) => !provenance.is_provenance_column(i),
+                ConstraintTarget::RowId => true,
+            });
+        }
+
+        let mut reader = DataReader::with_segments(rows.segments, constraints, converter);
+        if let Some(provenance) = provenance {
+            reader = reader.with_provenance(provenance);
+        }
+        if self.skip_empty {
+            reader = reader.skipping_empty(self.column_count);
+        }
+        Ok(match &self.pending {
+            Some(pending) if !pending.deleted.is_empty() => {
+                reader.excluding(Arc::clone(&pending.deleted))
+            }
+            _ => reader,
+        })
+    }
+
+    /// Returns the union labels, the PROVENANCE and the FORMULAS columns of the rows,
+    /// `None` without these columns.
+    fn get_row_provenance(&self, rows: &TableRows) -> Result<Option<Arc<RowProvenance>>, DataManagerError> {
+        let columns: Vec<Provenance> = self
+            .get_label_columns()
+            .into_iter()
+            .chain(self.get_provenance_columns())
+            .collect();
+        if columns.is_empty() && !self.formulas {
+            return Ok(None);
+        }
+
+        let mut origins = match &self.union {
+            Some(union) if self.is_union() => union.origins.clone(),
+            _ => match (rows.segments.first(), rows.columns) {
+                // rowids are the rows of the worksheet
+                (Some(segment), Some(columns)) => vec![Origin {
+                    first_rowid: segment.first_rowid,
+                    file: self.file.display().to_string(),
+                    worksheet: self.worksheet.clone(),
+                    first_row: segment.first_rowid,
+                    columns,
+                    formulas: None,
+                }],
+                _ => Vec::new(),
+            },
+        };
+        let mut formulas = Vec::new();
+        if self.formulas {
+            for origin in origins.iter_mut() {
+                origin.formulas = get_cache()
+                    .worksheet_formula(Path::new(&origin.file), origin.worksheet.as_str())
+                    .map_err(DataManagerError::Calamine)?;
+            }
+            let data_column = rows.columns.map(|columns| columns.0).unwrap_or(0);
+            formulas = (0..self.column_count as u32).map(|i| data_column + i).collect();
+        }
+
+        Ok(Some(Arc::new(RowProvenance::new(self.column_count, columns, origins).with_formulas(formulas))))
+    }
+
+    /// Returns the converter to the types declared by SCHEMA or TYPES, the DATES
+    /// format and the ERRORS policy, `None` if values are returned unchanged. Inferred types don't convert values.
+    fn get_converter(&self, first_column: u32) -> Option<Arc<ValueConverter>> {
+        let declared: Vec<Option<ColumnType>> = match (&self.schema, &self.types) {
+            (Some(schema), _) => schema.iter().map(|(_, t)| t.as_deref().and_then(ColumnType::from_declared)).collect(),
+            (None, Some(types)) => types.iter().map(|t| t.as_deref().and_then(ColumnType::from_declared)).collect(),
+            (None, None) => Vec::new(),
+        };
+
+        let converter = ValueConverter::new(self.worksheet.clone(), first_column)
+            .with_types(declared, self.on_type_error)
+            .with_dates(self.dates)
+            .with_errors(self.errors);
+        if converter.is_identity() {
+            None
         } else {
-            Vec::new()
+            Some(Arc::new(converter))
+        }
+    }
+
+    /// Appends a row after the effective range and returns its rowid.
+    /// The change is kept in memory until `sync` writes it to the file.
+    pub fn insert(&mut self, mut values: Vec<DataType>) -> Result<u32, DataManagerError> {
+        // the PROVENANCE columns are not written
+        values.truncate(self.column_count);
+        if let Some(sub) = self.range {
+            if sub.get_end().get_y() != 0 {
+                return Err(DataManagerError::Unsupported(
+                    "INSERT requires a RANGE without an end row, e.g. 'A2:F'".to_string(),
+                ));
+            }
+        }
+
+        let mut pending = self.take_pending()?;
+        let mut sheet = Arc::try_unwrap(pending.sheet).unwrap_or_else(|p| (*p).clone());
+
+        let (row, first_col) = match (self.get_bounds(&sheet), self.range) {
+            (Some((_, end)), Some(sub)) => (end.0 + 1, sub.get_start().to_zero_indexed().1),
+            (Some((start, end)), None) => (end.0 + 1, start.1),
+            (None, Some(sub)) => {
+                let (row, col) = sub.get_start().to_zero_indexed();
+                (row + self.header_rows, col)
+            }
+            (None, None) => (self.header_rows, 0),
+        };
+
+        for (i, value) in values.into_iter().enumerate() {
+            set_cell_value(&mut sheet, (row, first_col + i as u32), value);
+        }
+
+        pending.sheet = Arc::new(sheet);
+        self.pending = Some(pending);
+        Ok(row)
+    }
+
+    /// Replaces the values of a row, `None` keeps the current value of the column.
+    pub fn update(&mut self, rowid: u32, mut values: Vec<Option<DataType>>) -> Result<(), DataManagerError> {
+        values.truncate(self.column_count);
+        let mut pending = self.take_pending()?;
+        let mut sheet = Arc::try_unwrap(pending.sheet).unwrap_or_else(|p| (*p).clone());
+
+        let (start, _) = self.get_row_bounds(&sheet, &pending.deleted, rowid)?;
+
+        for (i, value) in values.into_iter().enumerate() {
+            if let Some(value) = value {
+                set_cell_value(&mut sheet, (rowid, start.1 + i as u32), value);
+            }
+        }
+
+        pending.sheet = Arc::new(sheet);
+        self.pending = Some(pending);
+        Ok(())
+    }
+
+    /// Marks a row as deleted, the rows below are shifted up when the changes are written.
+    pub fn delete(&mut self, rowid: u32) -> Result<(), DataManagerError> {
+        let mut pending = self.take_pending()?;
+        self.get_row_bounds(&pending.sheet, &pending.deleted, rowid)?;
+
+        Arc::make_mut(&mut pending.deleted).insert(rowid);
+        self.pending = Some(pending);
+        Ok(())
+    }
+
+    /// Writes uncommitted changes to the file.
+    pub fn sync(&mut self) -> Result<(), DataManagerError> {
+        if let Some(pending) = &self.pending {
+            let changes = self.get_changes(pending)?;
+
+            // release the cached file handle before the file is replaced
+            get_cache().invalidate(self.file.as_path());
+            write_worksheet(self.file.as_path(), self.worksheet.as_str(), &changes)
+                .map_err(DataManagerError::Package)?;
+        }
+        Ok(())
+    }
+
+    pub fn commit(&mut self) {
+        self.pending = None;
+    }
+
+    pub fn rollback(&mut self) {
+        self.pending = None;
+    }
+
+    fn take_pending(&mut self) -> Result<PendingChanges, DataManagerError> {
+        if self.is_snapshot() {
+            return Err(DataManagerError::Unsupported(
+                "Snapshot tables are read-only".to_string(),
+            ));
+        }
+        if self.is_union() {
+            return Err(DataManagerError::Unsupported(
+                "Tables over several worksheets or files are read-only".to_string(),
+            ));
+        }
+        if self.merged || !self.filldown.is_empty() {
+            return Err(DataManagerError::Unsupported(
+                "MERGED and FILLDOWN tables are read-only".to_string(),
+            ));
+        }
+
+        match self.pending.take() {
+            Some(pending) => Ok(pending),
+            None => Ok(PendingChanges {
+                sheet: self.read_worksheet_range()?,
+                deleted: Arc::new(BTreeSet::new()),
+            }),
+        }
+    }
+
+    /// Returns the bounds of the table if the row belongs to it and was not deleted.
+    fn get_row_bounds(
+        &self,
+        sheet: &Range<DataType>,
+        deleted: &BTreeSet<u32>,
+        rowid: u32,
+    ) -> Result<Bounds, DataManagerError> {
+        match self.get_bounds(sheet) {
+            Some((start, end)) if rowid >= start.0 && rowid <= end.0 && !deleted.contains(&rowid) => {
+                Ok((start, end))
+            }
+            _ => Err(DataManagerError::Unsupported(format!("Row {} not found", rowid))),
+        }
+    }
+
+    /// Returns the cells changed since the worksheet was read from the file, and the
+    /// deleted rows whose cells below move up.
+    fn get_changes(&self, pending: &PendingChanges) -> Result<SheetChanges, DataManagerError> {
+        let original = self.read_worksheet_range()?;
+        let mut changes = SheetChanges::default();
+
+        if let Some(start) = pending.sheet.start() {
+            for (row, column, value) in pending.sheet.cells() {
+                let position = (start.0 + row as u32, start.1 + column as u32);
+                if original.get_value(position).unwrap_or(&DataType::Empty) != value {
+                    changes.cells.insert(position, value.clone());
+                }
+            }
+        }
+        if let (false, Some((start, end))) = (pending.deleted.is_empty(), self.get_bounds(&pending.sheet)) {
+            changes.deleted_rows = (*pending.deleted).clone();
+            changes.columns = (start.1, end.1);
+            changes.last_row = end.0;
+        }
+        Ok(changes)
+    }
+}
+
+/// Returns the worksheet and the cells of the range a defined name refers to.
+fn find_defined_range(file: &Path, name: &str) -> Result<(String, String), DataManagerError> {
+    let names = get_cache()
+        .with_sheets(file, |sheets| sheets.defined_names().to_vec())
+        .map_err(DataManagerError::Calamine)?;
+    let value = match names.iter().find(|(n, _)| n.eq_ignore_ascii_case(name)) {
+        Some((_, value)) => value.trim_start_matches('='),
+        None => {
+            return Err(DataManagerError::Unsupported(format!(
+                "Name '{}' not found, the workbook defines: {}",
+                name,
+                names.iter().map(|(n, _)| n.as_str()).collect::<Vec<_>>().join(", ")
+            )))
         }
+    };
+
+    if split_areas(value).len() > 1 {
+        return Err(DataManagerError::Unsupported(format!(
+            "Name '{}' refers to more than one area: {}",
+            name, value
+        )));
     }
+    match split_sheet(value) {
+        (Some(sheet), cells) if CellRange::try_parse(cells).is_some() => Ok((sheet, cells.to_string())),
+        _ => Err(DataManagerError::Unsupported(format!(
+            "Name '{}' refers to a formula, not a range: {}",
+            name, value
+        ))),
+    }
+}
 
-    pub fn read(&mut self) -> DataReader {
-        let range = self.get_effective_range();
+/// Reads the merged regions of the worksheet from the package, e.g. `A2:A4`.
+fn read_merged_regions(file: &Path, worksheet: &str) -> Result<Vec<String>, DataManagerError> {
+    let reader = BufReader::new(File::open(file).map_err(|e| DataManagerError::Package(e.into()))?);
+    let mut archive = ZipArchive::new(reader).map_err(|e| DataManagerError::Package(e.into()))?;
+    read_merged_cells(&mut archive, worksheet).map_err(DataManagerError::Package)
+}
 
-        DataReader::new(range)
+/// Sets the value at the absolute position, growing the range when needed.
+fn set_cell_value(range: &mut Range<DataType>, position: (u32, u32), value: DataType) {
+    match (range.start(), range.end()) {
+        (Some(start), Some(end)) => {
+            let new_start = (start.0.min(position.0), start.1.min(position.1));
+            let new_end = (end.0.max(position.0), end.1.max(position.1));
+            if new_start != start || new_end != end {
+                *range = range.range(new_start, new_end);
+            }
+        }
+        _ => *range = Range::new(position, position),
     }
+    range.set_value(position, value);
 }
 
-#[derive(Default)]
 pub struct DataManagerBuilder {
     file: Option<String>,
     worksheet: Option<String>,
-    range: Option<CellRange>,
+    // comma separated worksheet names and patterns read as one table
+    worksheets: Option<String>,
+    selected: Vec<String>,
+    on_file_error: FileErrorPolicy,
+    // 0-based position of the worksheet, instead of its name
+    sheet_index: Option<usize>,
+    // the RANGE reference, optionally qualified with the worksheet
+    range: Option<String>,
+    // detects RANGE and HEADER from the cells of the worksheet
+    auto: bool,
+    // a defined name standing for WORKSHEET and RANGE
+    name: Option<String>,
+    // an Excel table standing for WORKSHEET, RANGE and HEADER
+    table: Option<String>,
+    column_names: Option<Vec<String>>,
     colnames_row: Option<u32>,
+    header_rows: u32,
+    header_separator: String,
+    sample: usize,
+    schema: Option<String>,
+    types: Option<String>,
+    on_type_error: TypeErrorPolicy,
+    dates: DateFormat,
+    errors: ErrorPolicy,
+    names: NameStyle,
+    provenance: bool,
+    formulas: bool,
+    merged: bool,
+    // comma separated column letters of FILLDOWN
+    filldown: Option<String>,
+    skip_empty: bool,
+    trim: bool,
+    until_blank: bool,
+    snapshot: bool,
+    // the first option given a mistyped value, reported when the manager is opened
+    invalid: Option<(String, String)>,
+}
+
+impl Default for DataManagerBuilder {
+    fn default() -> Self {
+        Self {
+            file: None,
+            worksheet: None,
+            worksheets: None,
+            selected: Vec::new(),
+            on_file_error: FileErrorPolicy::Fail,
+            sheet_index: None,
+            range: None,
+            auto: false,
+            name: None,
+            table: None,
+            column_names: None,
+            colnames_row: None,
+            header_rows: 0,
+            header_separator: " ".to_string(),
+            sample: DEFAULT_SAMPLE_ROWS,
+            schema: None,
+            types: None,
+            on_type_error: TypeErrorPolicy::Null,
+            dates: DateFormat::Serial,
+            errors: ErrorPolicy::Null,
+            names: NameStyle::Raw,
+            provenance: false,
+            formulas: false,
+            merged: false,
+            filldown: None,
+            skip_empty: false,
+            trim: false,
+            until_blank: false,
+            snapshot: false,
+            invalid: None,
+        }
+    }
 }
 
 impl DataManagerBuilder {
@@ -99,20 +1063,132 @@ impl DataManagerBuilder {
                 UsingOption::Worksheet(worksheet) => {
                     builder = builder.worksheet(worksheet);
                 }
+                UsingOption::Worksheets(list) => {
+                    builder = builder.worksheets(list);
+                }
+                UsingOption::FileErrors(policy) => {
+                    match FileErrorPolicy::try_parse(policy.as_str()) {
+                        Some(parsed) => builder = builder.on_file_error(parsed),
+                        None => builder = builder.invalid("FILE_ERRORS", policy),
+                    }
+                }
+                UsingOption::SheetIndex(index) => {
+                    match usize::from_str(index.as_str()) {
+                        Ok(parsed) => builder = builder.sheet_index(parsed),
+                        Err(_) => builder = builder.invalid("SHEET_INDEX", index),
+                    }
+                }
                 UsingOption::Range(range) => {
-                    builder = builder.range(CellRange::try_parse(range.as_str()).unwrap());
+                    builder = builder.range(range);
+                }
+                UsingOption::Auto(auto) => {
+                    builder = builder.auto(auto);
+                }
+                UsingOption::Name(name) => {
+                    builder = builder.name(name);
+                }
+                UsingOption::Table(table) => {
+                    builder = builder.table(table);
                 }
                 UsingOption::ColNames(colnames) => {
                     // We subtract 1 to go from excel indexing (which starts at 1) to 0-based
                     // indexing of the row.
-                    builder = builder.colnames_row(u32::from_str(colnames.as_str()).unwrap().saturating_sub(1));
+                    match u32::from_str(colnames.as_str()) {
+                        Ok(row) => builder = builder.colnames_row(row.saturating_sub(1)),
+                        Err(_) => builder = builder.invalid("COLNAMES", colnames),
+                    }
                 },
+                UsingOption::Header(rows) => {
+                    match u32::from_str(rows.as_str()) {
+                        Ok(parsed) => builder = builder.header(parsed),
+                        Err(_) => builder = builder.invalid("HEADER", rows),
+                    }
+                }
+                UsingOption::HeaderSeparator(separator) => {
+                    builder = builder.header_separator(separator);
+                }
+                UsingOption::Provenance(provenance) => {
+                    builder = builder.provenance(provenance);
+                }
+                UsingOption::Formulas(formulas) => {
+                    builder = builder.formulas(formulas);
+                }
+                UsingOption::Merged(policy) => {
+                    builder = builder.merged(policy.eq_ignore_ascii_case("fill"));
+                }
+                UsingOption::Invalid(option, value) => {
+                    builder = builder.invalid(option.as_str(), value);
+                }
+                UsingOption::FillDown(columns) => {
+                    builder = builder.filldown(columns);
+                }
+                UsingOption::SkipEmpty(skip) => {
+                    builder = builder.skip_empty(skip);
+                }
+                UsingOption::Trim(trim) => {
+                    builder = builder.trim(trim);
+                }
+                UsingOption::UntilBlank(until_blank) => {
+                    builder = builder.until_blank(until_blank);
+                }
+                UsingOption::Snapshot(snapshot) => {
+                    builder = builder.snapshot(snapshot);
+                }
+                UsingOption::Sample(rows) => {
+                    match usize::from_str(rows.as_str()) {
+                        Ok(parsed) => builder = builder.sample(parsed),
+                        Err(_) => builder = builder.invalid("SAMPLE", rows),
+                    }
+                }
+                UsingOption::Types(types) => {
+                    builder = builder.types(types);
+                }
+                UsingOption::Schema(schema) => {
+                    builder = builder.schema(schema);
+                }
+                UsingOption::TypeErrors(policy) => {
+                    match TypeErrorPolicy::try_parse(policy.as_str()) {
+                        Some(parsed) => builder = builder.on_type_error(parsed),
+                        None => builder = builder.invalid("TYPE_ERRORS", policy),
+                    }
+                }
+                UsingOption::Dates(format) => {
+                    match DateFormat::try_parse(format.as_str()) {
+                        Some(parsed) => builder = builder.dates(parsed),
+                        None => builder = builder.invalid("DATES", format),
+                    }
+                }
+                UsingOption::Errors(policy) => {
+                    match ErrorPolicy::try_parse(policy.as_str()) {
+                        Some(parsed) => builder = builder.errors(parsed),
+                        None => builder = builder.invalid("ERRORS", policy),
+                    }
+                }
+                UsingOption::Normalize(style) => {
+                    match NameStyle::try_parse(style.as_str()) {
+                        Some(parsed) => builder = builder.names(parsed),
+                        None => builder = builder.invalid("NORMALIZE", style),
+                    }
+                }
             }
         }
 
         builder
     }
 
+    /// Keeps the first option given a mistyped value, `open` reports it.
+    fn invalid(mut self, option: &str, value: String) -> Self {
+        self.invalid.get_or_insert((option.to_string(), value));
+        self
+    }
+
+    fn check_options(&self) -> Result<(), DataManagerError> {
+        match &self.invalid {
+            Some((option, value)) => Err(DataManagerError::Unsupported(format!("Invalid {} '{}'", option, value))),
+            None => Ok(()),
+        }
+    }
+
     pub fn file(mut self, file: String) -> Self {
         self.file = Some(file);
         self
@@ -123,33 +1199,546 @@ impl DataManagerBuilder {
         self
     }
 
-    pub fn range(mut self, range: CellRange) -> Self {
+    /// Comma separated names and patterns of worksheets with the same layout, read one after another.
+    pub fn worksheets(mut self, list: String) -> Self {
+        self.worksheets = Some(list);
+        self
+    }
+
+    pub fn on_file_error(mut self, policy: FileErrorPolicy) -> Self {
+        self.on_file_error = policy;
+        self
+    }
+
+    pub fn sheet_index(mut self, index: usize) -> Self {
+        self.sheet_index = Some(index);
+        self
+    }
+
+    pub fn range(mut self, range: String) -> Self {
         self.range = Some(range);
         self
     }
 
+    /// Reads the largest block of data of the worksheet, the header row being detected too.
+    pub fn auto(mut self, auto: bool) -> Self {
+        self.auto = auto;
+        self
+    }
+
+    pub fn name(mut self, name: String) -> Self {
+        self.name = Some(name);
+        self
+    }
+
+    pub fn table(mut self, table: String) -> Self {
+        self.table = Some(table);
+        self
+    }
+
     pub fn colnames_row(mut self, row: u32) -> Self {
         self.colnames_row = Some(row);
         self
     }
 
+    /// Takes the column names from the first `rows` rows of the range, which are not returned as data.
+    pub fn header(mut self, rows: u32) -> Self {
+        self.header_rows = rows;
+        self
+    }
+
+    /// The text joining the names of a multi-row HEADER.
+    pub fn header_separator(mut self, separator: String) -> Self {
+        self.header_separator = separator;
+        self
+    }
+
+    /// Number of rows sampled to infer the column types, `0` leaves the columns untyped.
+    pub fn sample(mut self, rows: usize) -> Self {
+        self.sample = rows;
+        self
+    }
+
+    /// Comma separated declared types of the columns, values are converted to these types.
+    pub fn types(mut self, types: String) -> Self {
+        self.types = Some(types);
+        self
+    }
+
+    /// `CREATE TABLE` statement declaring the names and the types of the columns.
+    pub fn schema(mut self, schema: String) -> Self {
+        self.schema = Some(schema);
+        self
+    }
+
+    pub fn on_type_error(mut self, policy: TypeErrorPolicy) -> Self {
+        self.on_type_error = policy;
+        self
+    }
+
+    pub fn dates(mut self, format: DateFormat) -> Self {
+        self.dates = format;
+        self
+    }
+
+    pub fn errors(mut self, policy: ErrorPolicy) -> Self {
+        self.errors = policy;
+        self
+    }
+
+    pub fn names(mut self, style: NameStyle) -> Self {
+        self.names = style;
+        self
+    }
+
+    /// Adds the hidden `_file`, `_sheet`, `_row` and `_address` columns telling where each row is read from.
+    pub fn provenance(mut self, provenance: bool) -> Self {
+        self.provenance = provenance;
+        self
+    }
+
+    /// Adds a hidden `<column>_formula` column holding the formula of each cell, e.g. `=B2*C2`.
+    pub fn formulas(mut self, formulas: bool) -> Self {
+        self.formulas = formulas;
+        self
+    }
+
+    /// Copies the value of merged cells into every cell of their region.
+    pub fn merged(mut self, merged: bool) -> Self {
+        self.merged = merged;
+        self
+    }
+
+    /// Comma separated column letters, e.g. `A,B`, whose blank cells take the last value above.
+    pub fn filldown(mut self, columns: String) -> Self {
+        self.filldown = Some(columns);
+        self
+    }
+
+    /// Leaves out the rows whose cells are all empty or spaces.
+    pub fn skip_empty(mut self, skip: bool) -> Self {
+        self.skip_empty = skip;
+        self
+    }
+
+    /// Drops the blank rows and columns at the end of the range, e.g. cells formatted
+    /// down to the last row of the worksheet.
+    pub fn trim(mut self, trim: bool) -> Self {
+        self.trim = trim;
+        self
+    }
+
+    /// Ends the table before its first blank row, leaving out the notes below the data.
+    pub fn until_blank(mut self, until_blank: bool) -> Self {
+        self.until_blank = until_blank;
+        self
+    }
+
+    pub fn snapshot(mut self, snapshot: bool) -> Self {
+        self.snapshot = snapshot;
+        self
+    }
+
+    pub fn is_snapshot(&self) -> bool {
+        self.snapshot
+    }
+
+    /// Creates a manager reading from a snapshot, the file is not required to exist
+    /// unless the table is defined by NAME. Without the file, the worksheet and the
+    /// cells of TABLE are taken from the `source` stored with the snapshot.
+    pub fn open_snapshot(self, range: Range<DataType>, source: TableSource) -> Result<DataManager, DataManagerError> {
+        self.check_options()?;
+        let file = match self.file.clone() {
+            Some(file) => PathBuf::from(file),
+            None => return Err(DataManagerError::NoFilename),
+        };
+        let mut builder = self.resolve_name(file.as_path())?;
+        if file.exists() {
+            builder = builder.resolve_table(file.as_path())?.resolve_worksheet(file.as_path())?;
+        } else {
+            builder = builder.restore_source(source);
+        }
+        match builder.get_worksheet() {
+            // the range is detected again from the snapshot, as it was from the file
+            Some(worksheet) => builder
+                .detect_range(&range, worksheet.as_str())?
+                .build(file, worksheet, Some(Arc::new(range))),
+            None => Err(DataManagerError::NoWorksheet),
+        }
+    }
+
+    /// Replaces WORKSHEET, SHEET_INDEX and TABLE by what they were resolved to
+    /// when the snapshot was taken.
+    fn restore_source(mut self, source: TableSource) -> Self {
+        if self.table.take().is_some() {
+            self.range = source.range;
+            self.header_rows = source.header_rows;
+            self.column_names = source.column_names;
+        }
+        if !source.worksheet.is_empty() {
+            self.worksheet = Some(source.worksheet);
+            self.sheet_index = None;
+        }
+        self
+    }
+
     pub fn open(self) -> Result<DataManager, DataManagerError> {
-        if let Some(file) = self.file {
-            if let Some(worksheet) = self.worksheet {
-                match open_workbook_auto(Path::new(&file)) {
-                    Ok(sheets) => Ok(DataManager {
-                        sheets,
-                        worksheet,
-                        range: self.range,
-                        colnames_row: self.colnames_row,
-                    }),
-                    Err(err) => Err(DataManagerError::Calamine(err)),
-                }
+        self.check_options()?;
+        if let Some(file) = self.file.clone() {
+            let mut file = PathBuf::from(file);
+            let pattern = if is_file_pattern(file.as_path()) {
+                let pattern = file;
+                file = self.find_first_file(pattern.as_path())?;
+                Some(pattern)
             } else {
-                Err(DataManagerError::NoWorksheet)
+                // opening through the cache validates the file and keeps it ready for reads
+                let opened = get_cache().with_sheets(file.as_path(), |_| ());
+                if let Err(err) = opened {
+                    return Err(DataManagerError::Calamine(err));
+                }
+                None
+            };
+
+            let builder = self
+                .resolve_name(file.as_path())?
+                .resolve_table(file.as_path())?
+                .resolve_worksheet(file.as_path())?;
+            let mut manager = match builder.get_worksheet() {
+                Some(worksheet) => builder
+                    .resolve_auto(file.as_path(), worksheet.as_str())?
+                    .check_formulas(file.as_path(), worksheet.as_str())?
+                    .build(file, worksheet, None)?,
+                None => return Err(DataManagerError::NoWorksheet),
+            };
+            if pattern.is_some() {
+                manager.file_pattern = pattern;
+                // reading the files reports the unreadable ones when the table is created
+                manager.load_rows()?;
             }
+            Ok(manager)
         } else {
             Err(DataManagerError::NoFilename)
         }
     }
+
+    /// Returns the first file of the FILENAME pattern that opens, the worksheets and the
+    /// names are resolved against it.
+    fn find_first_file(&self, pattern: &Path) -> Result<PathBuf, DataManagerError> {
+        if self.snapshot {
+            return Err(DataManagerError::Unsupported(
+                "SNAPSHOT requires a single FILENAME".to_string(),
+            ));
+        }
+
+        let files = list_files(pattern).map_err(|err| {
+            DataManagerError::Unsupported(format!("Can't list the files of '{}': {}", pattern.display(), err))
+        })?;
+        for file in files {
+            match get_cache().with_sheets(file.as_path(), |_| ()) {
+                Ok(_) => return Ok(file),
+                Err(_) if self.on_file_error == FileErrorPolicy::Skip => continue,
+                Err(err) => {
+                    return Err(DataManagerError::Unsupported(format!(
+                        "Can't read '{}': {}",
+                        file.display(),
+                        err
+                    )))
+                }
+            }
+        }
+        Err(DataManagerError::Unsupported(format!(
+            "FILENAME '{}' matches no readable file",
+            pattern.display()
+        )))
+    }
+
+    /// Replaces NAME by the worksheet and the range of the defined name.
+    fn resolve_name(mut self, file: &Path) -> Result<Self, DataManagerError> {
+        let name = match &self.name {
+            Some(name) => name,
+            None => return Ok(self),
+        };
+        if self.worksheet.is_some() || self.range.is_some() || self.table.is_some() {
+            return Err(DataManagerError::Unsupported(
+                "NAME can't be combined with WORKSHEET, RANGE or TABLE".to_string(),
+            ));
+        }
+
+        let (worksheet, cells) = find_defined_range(file, name.as_str())?;
+        self.worksheet = Some(worksheet);
+        self.range = Some(cells);
+        Ok(self)
+    }
+
+    /// Replaces TABLE by the worksheet, the range and the column names of the Excel table.
+    /// The totals row is left out.
+    fn resolve_table(mut self, file: &Path) -> Result<Self, DataManagerError> {
+        let table = match self.table.take() {
+            Some(table) => table,
+            None => return Ok(self),
+        };
+        if self.worksheet.is_some() || self.range.is_some() || self.colnames_row.is_some() || self.header_rows > 0 {
+            return Err(DataManagerError::Unsupported(
+                "TABLE can't be combined with WORKSHEET, RANGE, NAME, COLNAMES or HEADER".to_string(),
+            ));
+        }
+
+        let tables = match file.extension().and_then(|e| e.to_str()) {
+            Some(ext) if ext.eq_ignore_ascii_case("xlsx") || ext.eq_ignore_ascii_case("xlsm") => {
+                let reader = BufReader::new(File::open(file).map_err(|e| DataManagerError::Package(e.into()))?);
+                let mut archive = ZipArchive::new(reader).map_err(|e| DataManagerError::Package(e.into()))?;
+                read_table_entries(&mut archive).map_err(DataManagerError::Package)?
+            }
+            _ => {
+                return Err(DataManagerError::Unsupported(
+                    "TABLE requires an .xlsx file".to_string(),
+                ))
+            }
+        };
+        let entry = tables
+            .iter()
+            .find(|t| t.display_name.eq_ignore_ascii_case(&table) || t.name.eq_ignore_ascii_case(&table))
+            .ok_or_else(|| {
+                DataManagerError::Unsupported(format!(
+                    "Table '{}' not found, the workbook defines: {}",
+                    table,
+                    tables.iter().map(|t| t.display_name.as_str()).collect::<Vec<_>>().join(", ")
+                ))
+            })?;
+
+        let range = CellRange::try_parse(entry.reference.as_str()).ok_or_else(|| {
+            DataManagerError::Unsupported(format!("Table '{}' has an invalid ref '{}'", table, entry.reference))
+        })?;
+        let (start, end) = (range.get_start(), range.get_end());
+        let last_row = end.get_y().saturating_sub(entry.totals_rows).max(start.get_y());
+        self.worksheet = Some(entry.sheet.clone());
+        self.range = Some(format!(
+            "{}{}:{}{}",
+            start.get_x_as_string(),
+            start.get_y(),
+            end.get_x_as_string(),
+            last_row
+        ));
+        self.header_rows = entry.header_rows;
+        self.column_names = Some(entry.columns.clone());
+        Ok(self)
+    }
+
+    /// Replaces SHEET_INDEX, or a worksheet name not written exactly as in the workbook,
+    /// by the name of the worksheet.
+    fn resolve_worksheet(mut self, file: &Path) -> Result<Self, DataManagerError> {
+        let wanted = self.get_worksheet();
+        let index = self.sheet_index.take();
+        if let Some(list) = self.worksheets.take() {
+            return self.resolve_worksheets(file, list, wanted.is_some() || index.is_some());
+        }
+        if index.is_some() && wanted.is_some() {
+            return Err(DataManagerError::Unsupported(
+                "SHEET_INDEX can't be combined with WORKSHEET".to_string(),
+            ));
+        }
+        if index.is_none() && wanted.is_none() {
+            return Ok(self);
+        }
+
+        let names = get_cache()
+            .with_sheets(file, |sheets| sheets.sheet_names().to_vec())
+            .map_err(DataManagerError::Calamine)?;
+        let found = match (index, wanted) {
+            (Some(index), _) => find_worksheet_by_index(&names, index),
+            (None, Some(wanted)) => find_worksheet(&names, wanted.as_str()),
+            (None, None) => unreachable!(),
+        };
+        self.worksheet = Some(found.map_err(DataManagerError::Unsupported)?);
+        Ok(self)
+    }
+
+    /// Replaces WORKSHEETS by the names of the selected worksheets, the first one
+    /// standing for the worksheet of the table.
+    fn resolve_worksheets(mut self, file: &Path, list: String, has_worksheet: bool) -> Result<Self, DataManagerError> {
+        if has_worksheet {
+            return Err(DataManagerError::Unsupported(
+                "WORKSHEETS can't be combined with WORKSHEET, SHEET_INDEX, NAME, TABLE or a worksheet in RANGE".to_string(),
+            ));
+        }
+        if self.snapshot {
+            return Err(DataManagerError::Unsupported(
+                "WORKSHEETS can't be combined with SNAPSHOT".to_string(),
+            ));
+        }
+
+        let names = get_cache()
+            .with_sheets(file, |sheets| sheets.sheet_names().to_vec())
+            .map_err(DataManagerError::Calamine)?;
+        self.selected = select_worksheets(&names, list.as_str()).map_err(DataManagerError::Unsupported)?;
+        self.worksheet = self.selected.first().cloned();
+        Ok(self)
+    }
+
+    /// Replaces AUTO by the RANGE and the HEADER detected from the cells of the worksheet.
+    fn resolve_auto(self, file: &Path, worksheet: &str) -> Result<Self, DataManagerError> {
+        if !self.auto {
+            return Ok(self);
+        }
+        let sheet = get_cache()
+            .worksheet_range(file, worksheet)
+            .map_err(DataManagerError::Calamine)?
+            .unwrap_or_else(|| Arc::new(Range::empty()));
+        self.detect_range(&sheet, worksheet)
+    }
+
+    fn detect_range(mut self, sheet: &Range<DataType>, worksheet: &str) -> Result<Self, DataManagerError> {
+        if !self.auto {
+            return Ok(self);
+        }
+        // NAME and TABLE were replaced by a RANGE already
+        if self.range.is_some() || self.colnames_row.is_some() || self.header_rows > 0 {
+            return Err(DataManagerError::Unsupported(
+                "AUTO can't be combined with RANGE, NAME, TABLE, COLNAMES or HEADER".to_string(),
+            ));
+        }
+
+        let detected = detect_table(sheet).ok_or_else(|| {
+            DataManagerError::Unsupported(format!("AUTO found no data in worksheet '{}'", worksheet))
+        })?;
+        // the end row is left open, so that the table grows with the rows added below it
+        self.range = Some(detected.get_open_reference());
+        self.header_rows = detected.header_rows;
+        Ok(self)
+    }
+
+    /// Reads the formulas of the worksheet, so that FORMULAS fails when the table is created
+    /// instead of on every query if they can't be read. The cells of a SNAPSHOT have no formulas.
+    fn check_formulas(self, file: &Path, worksheet: &str) -> Result<Self, DataManagerError> {
+        if !self.formulas {
+            return Ok(self);
+        }
+        if self.snapshot {
+            return Err(DataManagerError::Unsupported(
+                "FORMULAS can't be combined with SNAPSHOT".to_string(),
+            ));
+        }
+        get_cache().worksheet_formula(file, worksheet).map_err(|err| {
+            DataManagerError::Unsupported(format!(
+                "FORMULAS can't read the formulas of worksheet '{}': {}",
+                worksheet, err
+            ))
+        })?;
+        Ok(self)
+    }
+
+    /// The WORKSHEET, or the worksheet the RANGE refers to.
+    fn get_worksheet(&self) -> Option<String> {
+        self.worksheet
+            .clone()
+            .or_else(|| self.range.as_deref().and_then(|range| split_sheet(range).0))
+    }
+
+    fn build(
+        self,
+        file: PathBuf,
+        worksheet: String,
+        snapshot: Option<Arc<Range<DataType>>>,
+    ) -> Result<DataManager, DataManagerError> {
+        let schema = match &self.schema {
+            Some(sql) => Some(parse_schema(sql.as_str()).ok_or_else(|| {
+                DataManagerError::Unsupported(format!("Invalid SCHEMA '{}'", sql))
+            })?),
+            None => None,
+        };
+        let range = match &self.range {
+            Some(reference) => {
+                let (sheet, cells) = split_sheet(reference.as_str());
+                match sheet {
+                    Some(sheet) if !sheet.eq_ignore_ascii_case(worksheet.as_str()) => {
+                        return Err(DataManagerError::Unsupported(format!(
+                            "RANGE '{}' refers to another worksheet than '{}'",
+                            reference, worksheet
+                        )))
+                    }
+                    _ => {}
+                }
+                Some(CellRange::try_parse(cells).ok_or_else(|| {
+                    DataManagerError::Unsupported(format!("Invalid RANGE '{}'", reference))
+                })?)
+            }
+            None => None,
+        };
+        if self.header_rows > 0 && self.colnames_row.is_some() {
+            return Err(DataManagerError::Unsupported(
+                "COLNAMES and HEADER can't be combined".to_string(),
+            ));
+        }
+        if self.merged {
+            if self.snapshot {
+                return Err(DataManagerError::Unsupported(
+                    "MERGED can't be combined with SNAPSHOT".to_string(),
+                ));
+            }
+            match file.extension().and_then(|e| e.to_str()) {
+                Some(ext) if ext.eq_ignore_ascii_case("xlsx") || ext.eq_ignore_ascii_case("xlsm") => {}
+                _ => {
+                    return Err(DataManagerError::Unsupported(
+                        "MERGED requires an .xlsx file".to_string(),
+                    ))
+                }
+            }
+        }
+        let filldown = match &self.filldown {
+            Some(columns) => columns
+                .split(',')
+                .map(str::trim)
+                .filter(|column| !column.is_empty())
+                .map(|column| match CellIndex::try_parse(column) {
+                    Some(index) if index.get_x() > 0 && index.get_y() == 0 => Ok(index.get_x() - 1),
+                    _ => Err(DataManagerError::Unsupported(format!("Invalid FILLDOWN column '{}'", column))),
+                })
+                .collect::<Result<Vec<u32>, DataManagerError>>()?,
+            None => Vec::new(),
+        };
+        let types = self.types.as_ref().map(|types| {
+            types
+                .split(',')
+                .map(|t| t.trim())
+                .map(|t| if t.is_empty() { None } else { Some(t.to_string()) })
+                .collect()
+        });
+
+        Ok(DataManager {
+            file,
+            worksheet,
+            worksheets: self.selected,
+            file_pattern: None,
+            on_file_error: self.on_file_error,
+            warnings: Vec::new(),
+            union: None,
+            provenance: self.provenance,
+            formulas: self.formulas,
+            formula_names: Vec::new(),
+            column_count: 0,
+            merged: self.merged,
+            filldown,
+            filled: Vec::new(),
+            skip_empty: self.skip_empty,
+            trim: self.trim,
+            until_blank: self.until_blank,
+            auto: self.auto,
+            range,
+            name: self.name,
+            colnames_row: self.colnames_row,
+            header_rows: self.header_rows,
+            header_separator: self.header_separator,
+            column_names: self.column_names,
+            sample: self.sample,
+            schema,
+            types,
+            on_type_error: self.on_type_error,
+            dates: self.dates,
+            errors: self.errors,
+            names: self.names,
+            rows_hint: None,
+            pending: None,
+            snapshot,
+        })
+    }
 }
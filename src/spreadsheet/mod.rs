@@ -1,3 +1,14 @@
+pub mod cache;
 pub mod cells;
+pub mod columns;
+pub mod constraints;
+pub mod convert;
+pub mod detect;
+pub mod files;
+pub mod formulas;
 pub mod manager;
+pub mod provenance;
 pub mod reader;
+pub mod worksheets;
+pub mod writer;
+pub mod xlsx;
@@ -0,0 +1,183 @@
+use crate::spreadsheet::cells::CellIndex;
+use calamine::{DataType, Range};
+use std::sync::Arc;
+
+/// A hidden column telling where a row of the table is read from.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Provenance {
+    /// The file of the row.
+    File,
+    /// The worksheet of the row.
+    Sheet,
+    /// The 1-based Excel row number.
+    Row,
+    /// The cells of the row, e.g. `Sheet1!A5:F5`.
+    Address,
+}
+
+impl Provenance {
+    pub const ALL: [Provenance; 4] = [Provenance::File, Provenance::Sheet, Provenance::Row, Provenance::Address];
+
+    pub fn get_name(&self) -> &'static str {
+        match self {
+            Provenance::File => "_file",
+            Provenance::Sheet => "_sheet",
+            Provenance::Row => "_row",
+            Provenance::Address => "_address",
+        }
+    }
+}
+
+/// Consecutive rowids read from the same worksheet.
+#[derive(Debug, Clone)]
+pub struct Origin {
+    pub first_rowid: u32,
+    pub file: String,
+    pub worksheet: String,
+    /// 0-based worksheet row of the first rowid.
+    pub first_row: u32,
+    /// 0-based worksheet columns of the table.
+    pub columns: (u32, u32),
+    /// The formulas of the worksheet, for FORMULAS.
+    pub formulas: Option<Arc<Range<String>>>,
+}
+
+/// Computes the provenance columns of the rows, which follow the other columns
+/// of the table from `first_column` on, and then the FORMULAS columns.
+#[derive(Debug, Clone)]
+pub struct RowProvenance {
+    first_column: usize,
+    columns: Vec<Provenance>,
+    // 0-based worksheet columns whose formulas are returned
+    formulas: Vec<u32>,
+    // sorted by `first_rowid`
+    origins: Vec<Origin>,
+}
+
+impl RowProvenance {
+    pub fn new(first_column: usize, columns: Vec<Provenance>, origins: Vec<Origin>) -> Self {
+        Self {
+            first_column,
+            columns,
+            formulas: Vec::new(),
+            origins,
+        }
+    }
+
+    /// Returns the formulas of the worksheet columns after the provenance columns.
+    pub fn with_formulas(mut self, columns: Vec<u32>) -> Self {
+        self.formulas = columns;
+        self
+    }
+
+    /// True if the i-th column of the table is a computed column.
+    pub fn is_provenance_column(&self, i: usize) -> bool {
+        i >= self.first_column && i - self.first_column < self.columns.len() + self.formulas.len()
+    }
+
+    /// Returns the value of the i-th column of the table for the row, `None` if it
+    /// is not a computed column.
+    pub fn get_value(&self, rowid: u32, i: usize) -> Option<DataType> {
+        let i = i.checked_sub(self.first_column)?;
+        if i >= self.columns.len() + self.formulas.len() {
+            return None;
+        }
+        let index = self.origins.partition_point(|origin| origin.first_rowid <= rowid);
+        let origin = match index.checked_sub(1) {
+            Some(index) => &self.origins[index],
+            None => return Some(DataType::Empty),
+        };
+        let row = origin.first_row + (rowid - origin.first_rowid);
+
+        let column = match self.columns.get(i) {
+            Some(column) => *column,
+            None => {
+                let formula = origin
+                    .formulas
+                    .as_ref()
+                    .and_then(|formulas| formulas.get_value((row, self.formulas[i - self.columns.len()])))
+                    .filter(|formula| !formula.is_empty());
+                return Some(match formula {
+                    Some(formula) => DataType::String(format!("={}", formula)),
+                    None => DataType::Empty,
+                });
+            }
+        };
+        Some(match column {
+            Provenance::File => DataType::String(origin.file.clone()),
+            Provenance::Sheet => DataType::String(origin.worksheet.clone()),
+            Provenance::Row => DataType::Int(row as i64 + 1),
+            Provenance::Address => DataType::String(get_address(origin, row)),
+        })
+    }
+}
+
+/// The Excel reference of the cells of the row, e.g. `'Sheet 1'!A5:F5`.
+fn get_address(origin: &Origin, row: u32) -> String {
+    let worksheet = if origin.worksheet.chars().all(|c| c.is_alphanumeric() || c == '_' || c == '.') {
+        origin.worksheet.clone()
+    } else {
+        format!("'{}'", origin.worksheet.replace('\'', "''"))
+    };
+    let first = CellIndex::new(origin.columns.0 + 1, row + 1);
+    let last = CellIndex::new(origin.columns.1 + 1, row + 1);
+
+    if origin.columns.0 == origin.columns.1 {
+        format!("{}!{}{}", worksheet, first.get_x_as_string(), first.get_y())
+    } else {
+        format!(
+            "{}!{}{}:{}{}",
+            worksheet,
+            first.get_x_as_string(),
+            first.get_y(),
+            last.get_x_as_string(),
+            last.get_y()
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn origin(first_rowid: u32, worksheet: &str, first_row: u32) -> Origin {
+        Origin {
+            first_rowid,
+            file: "book.xlsx".to_string(),
+            worksheet: worksheet.to_string(),
+            first_row,
+            columns: (1, 3),
+            formulas: None,
+        }
+    }
+
+    #[test]
+    fn get_value_maps_rowids_to_worksheet_rows() {
+        let provenance = RowProvenance::new(
+            4,
+            vec![Provenance::Sheet, Provenance::Row, Provenance::Address],
+            vec![origin(0, "Jan", 1), origin(2, "Feb 2024", 4)],
+        );
+
+        assert_eq!(provenance.get_value(1, 3), None);
+        assert_eq!(provenance.get_value(1, 4), Some(DataType::String("Jan".to_string())));
+        assert_eq!(provenance.get_value(1, 5), Some(DataType::Int(3)));
+        assert_eq!(provenance.get_value(3, 5), Some(DataType::Int(6)));
+        assert_eq!(provenance.get_value(3, 6), Some(DataType::String("'Feb 2024'!B6:D6".to_string())));
+        assert!(provenance.is_provenance_column(6));
+        assert!(!provenance.is_provenance_column(7));
+    }
+
+    #[test]
+    fn get_value_returns_formulas_of_columns() {
+        let mut formulas = Range::new((1, 3), (1, 3));
+        formulas.set_value((1, 3), "B2*C2".to_string());
+        let mut first = origin(0, "Jan", 1);
+        first.formulas = Some(Arc::new(formulas));
+        let provenance = RowProvenance::new(4, vec![Provenance::Row], vec![first]).with_formulas(vec![2, 3]);
+
+        assert_eq!(provenance.get_value(0, 5), Some(DataType::Empty));
+        assert_eq!(provenance.get_value(0, 6), Some(DataType::String("=B2*C2".to_string())));
+        assert_eq!(provenance.get_value(0, 7), None);
+    }
+}
@@ -1,35 +1,170 @@
+use crate::spreadsheet::constraints::{get_rowid_bounds, Constraint, ConstraintTarget};
+use crate::spreadsheet::convert::ValueConverter;
+use crate::spreadsheet::provenance::RowProvenance;
+use std::borrow::Cow;
 use calamine::{DataType, Range, Rows};
+use std::collections::BTreeSet;
 use std::mem::transmute;
+use std::sync::Arc;
+
+/// True for empty cells and cells holding only spaces.
+pub fn is_blank(value: &DataType) -> bool {
+    match value {
+        DataType::Empty => true,
+        DataType::String(s) => s.trim().is_empty(),
+        _ => false,
+    }
+}
+
+/// Consecutive rows of the table read from one range, e.g. one worksheet of a union.
+#[derive(Clone)]
+pub struct Segment {
+    pub range: Arc<Range<DataType>>,
+    /// The rowid of the first row of the range.
+    pub first_rowid: u32,
+    /// Number of table columns left of the range, read as empty cells.
+    pub offset: usize,
+    /// Number of columns of the table, the cells right of them are left out.
+    pub width: usize,
+}
+
+impl Segment {
+    /// The whole range, its rowids being the worksheet rows.
+    pub fn of_range(range: Arc<Range<DataType>>) -> Self {
+        Self {
+            first_rowid: range.start().unwrap_or((0, 0)).0,
+            offset: 0,
+            width: range.width(),
+            range,
+        }
+    }
+
+    fn get_last_rowid(&self) -> Option<u32> {
+        (self.first_rowid + self.range.height() as u32).checked_sub(1)
+    }
+}
 
 pub struct DataReader {
-    range: Range<DataType>,
+    segments: Vec<Segment>,
+    constraints: Vec<Constraint>,
+    converter: Option<Arc<ValueConverter>>,
+    excluded: Option<Arc<BTreeSet<u32>>>,
+    provenance: Option<Arc<RowProvenance>>,
+    // skips the rows whose first `skip_empty` cells are blank
+    skip_empty: Option<usize>,
     state: DataReaderState<'static>,
 }
 
 struct DataReaderState<'a> {
-    rows: Rows<'a, DataType>,
+    segment: usize,
+    rows: Option<Rows<'a, DataType>>,
     row: Option<&'a [DataType]>,
     rowid: u32,
+    last_rowid: u32,
 }
 
 impl DataReader {
-    pub fn new(range: Range<DataType>) -> Self {
-        let mut rows = range.rows();
-        let row = rows.next();
-        let rowid = range.start().unwrap_or((0, 0)).0;
+    pub fn new(range: Arc<Range<DataType>>) -> Self {
+        Self::with_constraints(range, Vec::new())
+    }
+
+    pub fn with_constraints(range: Arc<Range<DataType>>, constraints: Vec<Constraint>) -> Self {
+        Self::with_converter(range, constraints, None)
+    }
+
+    /// Creates a reader returning the values converted by `converter`,
+    /// the constraints are checked against the converted values.
+    pub fn with_converter(
+        range: Arc<Range<DataType>>,
+        constraints: Vec<Constraint>,
+        converter: Option<Arc<ValueConverter>>,
+    ) -> Self {
+        Self::with_segments(vec![Segment::of_range(range)], constraints, converter)
+    }
+
+    /// Creates a reader returning the rows of the segments one after another,
+    /// the segments being sorted by their first rowid.
+    pub fn with_segments(
+        segments: Vec<Segment>,
+        constraints: Vec<Constraint>,
+        converter: Option<Arc<ValueConverter>>,
+    ) -> Self {
+        let start = segments.first().map(|s| s.first_rowid).unwrap_or(0);
+        let end = segments.iter().filter_map(Segment::get_last_rowid).max();
 
-        // transmute to static because we have the self-referencing struct
+        // the first row is taken from the segment holding the lowest wanted rowid
+        let (segment, rowid, last_rowid) = match (get_rowid_bounds(&constraints), end) {
+            (Some((low, high)), Some(end)) if low <= end && high >= start => {
+                let first = low.max(start);
+                let segment = segments
+                    .iter()
+                    .position(|s| s.get_last_rowid().is_some_and(|last| last >= first))
+                    .unwrap_or(segments.len());
+                (segment, first.max(segments.get(segment).map_or(first, |s| s.first_rowid)), high.min(end))
+            }
+            _ => (segments.len(), start, start),
+        };
+        let (rows, row) = match segments.get(segment) {
+            Some(s) => {
+                let mut rows = s.range.rows();
+                let row = rows.nth((rowid - s.first_rowid) as usize);
+                (Some(rows), row)
+            }
+            None => (None, None),
+        };
+        let row = row.filter(|_| rowid <= last_rowid);
+
+        // transmute to static because we have the self-referencing struct,
+        // the ranges are kept alive by the segments
         let rows = unsafe {
-            transmute::<Rows<'_, DataType>, Rows<'static, DataType>>(rows)
+            transmute::<Option<Rows<'_, DataType>>, Option<Rows<'static, DataType>>>(rows)
         };
         let row = unsafe {
             transmute::<Option<&'_ [DataType]>, Option<&'static [DataType]>>(row)
         };
 
-        DataReader {
-            range,
-            state: DataReaderState { rows, row, rowid },
+        let mut reader = DataReader {
+            segments,
+            constraints: constraints
+                .into_iter()
+                .filter(|c| c.get_target() != ConstraintTarget::RowId)
+                .collect(),
+            converter,
+            excluded: None,
+            provenance: None,
+            skip_empty: None,
+            state: DataReaderState { segment, rows, row, rowid, last_rowid },
+        };
+
+        if !reader.is_match() {
+            reader.move_next();
+        }
+
+        reader
+    }
+
+    /// Skips the rows with the given rowids, e.g. rows deleted in the current transaction.
+    pub fn excluding(mut self, rowids: Arc<BTreeSet<u32>>) -> Self {
+        self.excluded = Some(rowids);
+        if !self.is_match() {
+            self.move_next();
         }
+        self
+    }
+
+    /// Skips the rows whose first `columns` cells are blank.
+    pub fn skipping_empty(mut self, columns: usize) -> Self {
+        self.skip_empty = Some(columns);
+        if !self.is_match() {
+            self.move_next();
+        }
+        self
+    }
+
+    /// Returns the provenance columns (`_row`, `_address`, ...) of the rows.
+    pub fn with_provenance(mut self, provenance: Arc<RowProvenance>) -> Self {
+        self.provenance = Some(provenance);
+        self
     }
 
     pub fn has_value(&self) -> bool {
@@ -37,13 +172,48 @@ impl DataReader {
     }
 
     pub fn get_value(&self, i: usize) -> Option<&DataType> {
-        if let Some(ref row) = self.state.row {
-            if i < row.len() {
-                let col = &row[i];
-                return Some(col);
+        let row = self.state.row?;
+        let segment = &self.segments[self.state.segment];
+        if i >= segment.width {
+            return None;
+        }
+        match i.checked_sub(segment.offset) {
+            Some(i) => Some(row.get(i).unwrap_or(&DataType::Empty)),
+            None => Some(&DataType::Empty),
+        }
+    }
+
+    /// Returns the cells of the current row, aligned on the columns of the table.
+    pub fn get_row(&self) -> Option<Cow<'_, [DataType]>> {
+        let row = self.state.row?;
+        let segment = &self.segments[self.state.segment];
+        if segment.offset == 0 && row.len() == segment.width {
+            return Some(Cow::Borrowed(row));
+        }
+        Some(Cow::Owned((0..segment.width).map(|i| self.get_value(i).cloned().unwrap_or(DataType::Empty)).collect()))
+    }
+
+    /// Consumes the reader, returning the rowid and the cells of each row.
+    pub fn into_rows(mut self) -> impl Iterator<Item = (u32, Vec<DataType>)> {
+        std::iter::from_fn(move || {
+            let row = (self.get_rowid(), self.get_row()?.into_owned());
+            self.move_next();
+            Some(row)
+        })
+    }
+
+    /// Returns the value of the i-th column converted to its declared type,
+    /// or the message of the conversion error.
+    pub fn get_converted_value(&self, i: usize) -> Result<Option<Cow<'_, DataType>>, String> {
+        if let (Some(provenance), true) = (&self.provenance, self.has_value()) {
+            if let Some(value) = provenance.get_value(self.state.rowid, i) {
+                return Ok(Some(Cow::Owned(value)));
             }
         }
-        None
+        match (self.get_value(i), &self.converter) {
+            (Some(value), Some(converter)) => converter.convert(self.state.rowid, i, value).map(Some),
+            (value, _) => Ok(value.map(Cow::Borrowed)),
+        }
     }
 
     pub fn get_rowid(&self) -> u32 {
@@ -51,10 +221,120 @@ impl DataReader {
     }
 
     pub fn move_next(&mut self) {
-        self.state.row = self.state.rows.next();
+        loop {
+            if self.state.rowid >= self.state.last_rowid {
+                self.state.row = None;
+                return;
+            }
+
+            self.state.row = self.state.rows.as_mut().and_then(|rows| rows.next());
+
+            if self.state.row.is_some() {
+                self.state.rowid += 1;
+            } else {
+                self.next_segment();
+                if self.state.row.is_none() {
+                    return;
+                }
+            }
+
+            if self.is_match() {
+                return;
+            }
+        }
+    }
+
+    /// Moves to the first row of the next segment holding rows.
+    fn next_segment(&mut self) {
+        while self.state.segment + 1 < self.segments.len() {
+            self.state.segment += 1;
+            let segment = &self.segments[self.state.segment];
+            if segment.first_rowid > self.state.last_rowid {
+                break;
+            }
+            let mut rows = segment.range.rows();
+            if let Some(row) = rows.next() {
+                // the range is kept alive by the segments
+                self.state.rows = Some(unsafe { transmute::<Rows<'_, DataType>, Rows<'static, DataType>>(rows) });
+                self.state.row = Some(unsafe { transmute::<&'_ [DataType], &'static [DataType]>(row) });
+                self.state.rowid = segment.first_rowid;
+                return;
+            }
+        }
+        self.state.row = None;
+    }
+
+    fn is_match(&self) -> bool {
+        match self.get_row() {
+            Some(row) => {
+                let excluded = match &self.excluded {
+                    Some(rowids) => rowids.contains(&self.state.rowid),
+                    None => false,
+                };
+                if excluded {
+                    return false;
+                }
+                if let Some(columns) = self.skip_empty {
+                    if row.iter().take(columns).all(is_blank) {
+                        return false;
+                    }
+                }
+                if self.constraints.is_empty() {
+                    return true;
+                }
+
+                let converted = match &self.converter {
+                    Some(converter) => match converter.convert_row(self.state.rowid, &row) {
+                        Ok(converted) => Cow::Owned(converted),
+                        // the row is kept, so that reading it reports the error
+                        Err(_) => return true,
+                    },
+                    None => row,
+                };
+                self.constraints
+                    .iter()
+                    .all(|c| c.may_match(self.state.rowid, &converted))
+            }
+            None => true,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::spreadsheet::constraints::ConstraintOp;
 
-        if self.state.row.is_some() {
-            self.state.rowid += 1;
+    fn segment(first_rowid: u32, offset: usize, values: &[&[f64]]) -> Segment {
+        let mut range = Range::new((0, 0), (values.len() as u32 - 1, values[0].len() as u32 - 1));
+        for (row, cells) in values.iter().enumerate() {
+            for (column, value) in cells.iter().enumerate() {
+                range.set_value((row as u32, column as u32), DataType::Float(*value));
+            }
         }
+        Segment { range: Arc::new(range), first_rowid, offset, width: 3 }
+    }
+
+    #[test]
+    fn reads_segments_in_rowid_order() {
+        let segments = vec![segment(0, 0, &[&[1.0, 2.0, 3.0], &[4.0, 5.0, 6.0]]), segment(2, 1, &[&[7.0]])];
+        let rows: Vec<_> = DataReader::with_segments(segments, Vec::new(), None).into_rows().collect();
+
+        assert_eq!(rows.len(), 3);
+        assert_eq!(rows[1].0, 1);
+        assert_eq!(rows[1].1, vec![DataType::Float(4.0), DataType::Float(5.0), DataType::Float(6.0)]);
+        assert_eq!(rows[2].0, 2);
+        assert_eq!(rows[2].1, vec![DataType::Empty, DataType::Float(7.0), DataType::Empty]);
+    }
+
+    #[test]
+    fn starts_at_the_segment_of_the_rowid() {
+        let segments = vec![segment(0, 0, &[&[1.0, 2.0, 3.0], &[4.0, 5.0, 6.0]]), segment(2, 0, &[&[7.0, 8.0, 9.0]])];
+        let constraints = vec![Constraint::new(ConstraintTarget::RowId, ConstraintOp::Ge, DataType::Int(2))];
+        let reader = DataReader::with_segments(segments, constraints, None);
+
+        assert_eq!(reader.get_rowid(), 2);
+        assert_eq!(reader.get_value(2), Some(&DataType::Float(9.0)));
+        assert_eq!(reader.into_rows().count(), 1);
     }
 }
@@ -0,0 +1,184 @@
+/// Finds the worksheet named `wanted`: by its exact name, ignoring the case, or as
+/// a pattern with `*` and `?` wildcards (the first matching worksheet). The error
+/// lists the worksheets of the workbook and the closest name.
+pub fn find_worksheet(names: &[String], wanted: &str) -> Result<String, String> {
+    let found = names
+        .iter()
+        .find(|name| name.as_str() == wanted)
+        .or_else(|| names.iter().find(|name| name.eq_ignore_ascii_case(wanted)))
+        .or_else(|| {
+            if is_pattern(wanted) {
+                names.iter().find(|name| matches_pattern(wanted, name))
+            } else {
+                None
+            }
+        });
+
+    match found {
+        Some(name) => Ok(name.clone()),
+        None => {
+            let mut message = format!(
+                "Worksheet '{}' not found, the workbook has: {}",
+                wanted,
+                names.join(", ")
+            );
+            if let Some(closest) = find_closest(names, wanted) {
+                message.push_str(format!(". Did you mean '{}'?", closest).as_str());
+            }
+            Err(message)
+        }
+    }
+}
+
+/// Selects the worksheets of a comma-separated list of names and patterns,
+/// e.g. `Jan,Feb,Mar` or `2024-*`, in the order of the list and of the workbook.
+pub fn select_worksheets(names: &[String], list: &str) -> Result<Vec<String>, String> {
+    let mut selected: Vec<String> = Vec::new();
+    for item in list.split(',').map(str::trim).filter(|item| !item.is_empty()) {
+        let matches = if is_pattern(item) {
+            names.iter().filter(|name| matches_pattern(item, name)).cloned().collect()
+        } else {
+            vec![find_worksheet(names, item)?]
+        };
+        for name in matches {
+            if !selected.contains(&name) {
+                selected.push(name);
+            }
+        }
+    }
+
+    if selected.is_empty() {
+        return Err(format!(
+            "WORKSHEETS '{}' matches no worksheet, the workbook has: {}",
+            list,
+            names.join(", ")
+        ));
+    }
+    Ok(selected)
+}
+
+/// Returns the worksheet at the 0-based position.
+pub fn find_worksheet_by_index(names: &[String], index: usize) -> Result<String, String> {
+    names.get(index).cloned().ok_or_else(|| {
+        format!(
+            "Worksheet index {} is out of range, the workbook has {} worksheet(s): {}",
+            index,
+            names.len(),
+            names.join(", ")
+        )
+    })
+}
+
+/// True if the name contains `*` or `?` wildcards.
+pub fn is_pattern(s: &str) -> bool {
+    s.contains(['*', '?'])
+}
+
+/// Matches a name against a pattern with `*` (any text) and `?` (any character)
+/// wildcards, ignoring the case.
+pub fn matches_pattern(pattern: &str, name: &str) -> bool {
+    let pattern: Vec<char> = pattern.to_lowercase().chars().collect();
+    let name: Vec<char> = name.to_lowercase().chars().collect();
+
+    // positions to resume from when a `*` has to match more characters
+    let (mut p, mut n) = (0, 0);
+    let mut star: Option<(usize, usize)> = None;
+    while n < name.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                star = Some((p, n));
+                p += 1;
+            }
+            Some(&ch) if ch == '?' || ch == name[n] => {
+                p += 1;
+                n += 1;
+            }
+            _ => match star {
+                Some((star_p, star_n)) => {
+                    star = Some((star_p, star_n + 1));
+                    p = star_p + 1;
+                    n = star_n + 1;
+                }
+                None => return false,
+            },
+        }
+    }
+
+    pattern[p..].iter().all(|ch| *ch == '*')
+}
+
+/// The name with the smallest edit distance to `wanted`, if it is reasonably close.
+fn find_closest<'a>(names: &'a [String], wanted: &str) -> Option<&'a String> {
+    let wanted = wanted.to_lowercase();
+    names
+        .iter()
+        .map(|name| (name, edit_distance(name.to_lowercase().as_str(), wanted.as_str())))
+        .filter(|(name, distance)| *distance <= name.chars().count().max(wanted.chars().count()) / 2)
+        .min_by_key(|(_, distance)| *distance)
+        .map(|(name, _)| name)
+}
+
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut current = vec![i + 1; b.len() + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = previous[j] + if ca == *cb { 0 } else { 1 };
+            current[j + 1] = substitution.min(previous[j + 1] + 1).min(current[j] + 1);
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn names() -> Vec<String> {
+        vec!["Summary".to_string(), "2024-Jan".to_string(), "2024-Feb".to_string()]
+    }
+
+    #[test]
+    fn find_worksheet_matches_case_and_patterns() {
+        assert_eq!(find_worksheet(&names(), "summary").unwrap(), "Summary");
+        assert_eq!(find_worksheet(&names(), "2024-*").unwrap(), "2024-Jan");
+        assert_eq!(find_worksheet(&names(), "2024-f?b").unwrap(), "2024-Feb");
+    }
+
+    #[test]
+    fn find_worksheet_suggests_closest_name() {
+        assert_eq!(
+            find_worksheet(&names(), "Sumary").unwrap_err(),
+            "Worksheet 'Sumary' not found, the workbook has: Summary, 2024-Jan, 2024-Feb. Did you mean 'Summary'?"
+        );
+        assert!(!find_worksheet(&names(), "Totals").unwrap_err().contains("Did you mean"));
+    }
+
+    #[test]
+    fn select_worksheets_expands_lists_and_patterns() {
+        assert_eq!(select_worksheets(&names(), "2024-*").unwrap(), vec!["2024-Jan", "2024-Feb"]);
+        assert_eq!(
+            select_worksheets(&names(), "2024-feb, summary, 2024-*").unwrap(),
+            vec!["2024-Feb", "Summary", "2024-Jan"]
+        );
+        assert!(select_worksheets(&names(), "2025-*").unwrap_err().contains("matches no worksheet"));
+        assert!(select_worksheets(&names(), "Summary,Totals").unwrap_err().contains("'Totals' not found"));
+    }
+
+    #[test]
+    fn matches_pattern_handles_wildcards() {
+        assert!(matches_pattern("*", ""));
+        assert!(matches_pattern("a*c", "abbbc"));
+        assert!(matches_pattern("*-jan", "2024-Jan"));
+        assert!(!matches_pattern("a?c", "ac"));
+        assert!(!matches_pattern("a*d", "abc"));
+    }
+
+    #[test]
+    fn find_worksheet_by_index_checks_bounds() {
+        assert_eq!(find_worksheet_by_index(&names(), 1).unwrap(), "2024-Jan");
+        assert!(find_worksheet_by_index(&names(), 3).unwrap_err().contains("3 worksheet(s)"));
+    }
+}
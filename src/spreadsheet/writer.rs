@@ -0,0 +1,589 @@
+use crate::spreadsheet::cells::CellIndex;
+use crate::spreadsheet::formulas::translate_formula;
+use crate::spreadsheet::xlsx::{escape_xml, read_part, read_sheet_entries, PackageError};
+use calamine::DataType;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use zip::write::FileOptions;
+use zip::{CompressionMethod, ZipArchive, ZipWriter};
+
+const CALC_CHAIN_PART: &str = "xl/calcChain.xml";
+
+/// Changes of the cells of a worksheet, written by `write_worksheet`.
+#[derive(Debug, Default)]
+pub struct SheetChanges {
+    /// New values of the cells at 0-based (row, column) positions, `Empty` clears a cell.
+    pub cells: BTreeMap<(u32, u32), DataType>,
+    /// 0-based rows removed from the `columns` of a table, the cells of these columns
+    /// below them move up, down to `last_row`.
+    pub deleted_rows: BTreeSet<u32>,
+    pub columns: (u32, u32),
+    pub last_row: u32,
+}
+
+/// Writes the changed cells of a worksheet into an .xlsx file.
+///
+/// Only the changed cells and the rows holding them are rewritten: the styles and the
+/// formulas of the other cells, the other worksheets and the remaining parts of the
+/// package are kept as they are. A changed cell keeps its style, new cells take the
+/// style of the cell above them unless it holds text.
+pub fn write_worksheet(
+    path: &Path,
+    worksheet: &str,
+    changes: &SheetChanges,
+) -> Result<(), PackageError> {
+    match path.extension().and_then(|e| e.to_str()) {
+        Some(ext) if ext.eq_ignore_ascii_case("xlsx") || ext.eq_ignore_ascii_case("xlsm") => {}
+        _ => {
+            return Err(PackageError::Unsupported(format!(
+                "Writing is supported only for .xlsx files: {}",
+                path.display()
+            )))
+        }
+    }
+
+    let mut archive = ZipArchive::new(BufReader::new(File::open(path)?))?;
+
+    let entry = read_sheet_entries(&mut archive)?
+        .into_iter()
+        .find(|e| e.name == worksheet)
+        .ok_or_else(|| PackageError::Invalid(format!("worksheet '{}' not found", worksheet)))?;
+
+    let sheet_xml = read_part(&mut archive, entry.part.as_str())?
+        .ok_or_else(|| PackageError::Invalid(format!("{} is missing", entry.part)))?;
+    let (sheet_xml, formulas_changed) = patch_sheet_data(sheet_xml.as_str(), changes)?;
+
+    // the calculation chain lists the formula cells, Excel rebuilds it when it is missing
+    let has_calc_chain = formulas_changed && archive.file_names().any(|n| n == CALC_CHAIN_PART);
+
+    let temp_path = get_temp_path(path);
+    let result = (|| {
+        let mut writer = ZipWriter::new(BufWriter::new(File::create(&temp_path)?));
+        let options = FileOptions::default().compression_method(CompressionMethod::Deflated);
+
+        for i in 0..archive.len() {
+            let name = archive.by_index_raw(i)?.name().to_string();
+            if name == entry.part {
+                writer.start_file(name, options)?;
+                writer.write_all(sheet_xml.as_bytes())?;
+            } else if has_calc_chain && name == CALC_CHAIN_PART {
+                continue;
+            } else if has_calc_chain
+                && (name == "[Content_Types].xml" || name == "xl/_rels/workbook.xml.rels")
+            {
+                let xml = read_part(&mut archive, name.as_str())?.unwrap_or_default();
+                writer.start_file(name, options)?;
+                writer.write_all(remove_elements_containing(xml.as_str(), "calcChain").as_bytes())?;
+            } else {
+                writer.raw_copy_file(archive.by_index_raw(i)?)?;
+            }
+        }
+
+        writer.finish()?.flush()?;
+        Ok::<(), PackageError>(())
+    })();
+
+    match result {
+        Ok(_) => {
+            drop(archive);
+            std::fs::rename(&temp_path, path)?;
+            Ok(())
+        }
+        Err(e) => {
+            let _ = std::fs::remove_file(&temp_path);
+            Err(e)
+        }
+    }
+}
+
+fn get_temp_path(path: &Path) -> PathBuf {
+    let name = path
+        .file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    path.with_file_name(format!(".{}.xlite-tmp", name))
+}
+
+/// Applies the changes to the `<sheetData>` of the worksheet and updates its `<dimension>`.
+/// Also returns whether formula cells were overwritten, moved or removed.
+fn patch_sheet_data(xml: &str, changes: &SheetChanges) -> Result<(String, bool), PackageError> {
+    let invalid = || PackageError::Invalid("worksheet has no <sheetData> element".to_string());
+
+    let start = xml.find("<sheetData").ok_or_else(invalid)?;
+    let tag_end = start + xml[start..].find('>').ok_or_else(invalid)?;
+    let (content, end) = if xml[..tag_end].ends_with('/') {
+        ("", tag_end + 1)
+    } else {
+        let close = start + xml[start..].find("</sheetData>").ok_or_else(invalid)?;
+        (&xml[tag_end + 1..close], close + "</sheetData>".len())
+    };
+
+    let mut sheet = SheetData::parse(content)?;
+    let formulas_changed = sheet.apply(changes);
+
+    let mut out = String::with_capacity(xml.len());
+    out.push_str(&xml[..start]);
+    out.push_str(sheet.write().as_str());
+    out.push_str(&xml[end..]);
+
+    Ok((replace_dimension(out.as_str(), sheet.get_dimension()), formulas_changed))
+}
+
+/// A `<row>` element: its start tag and its `<c>` elements by 0-based column.
+struct RowElement {
+    tag: String,
+    cells: BTreeMap<u32, String>,
+    // the element as written in the file, `None` once the row changed
+    raw: Option<String>,
+}
+
+/// The rows of `<sheetData>` by 0-based row, parsed just enough to patch single cells.
+struct SheetData {
+    rows: BTreeMap<u32, RowElement>,
+}
+
+impl SheetData {
+    fn parse(content: &str) -> Result<Self, PackageError> {
+        let mut rows = BTreeMap::new();
+        let mut next_row = 0;
+        let mut rest = content;
+        while let Some(start) = find_element(rest, "row") {
+            rest = &rest[start..];
+            let tag_end = rest.find('>').ok_or_else(|| invalid_element("row"))? + 1;
+            let (tag, inner, len) = if rest[..tag_end].ends_with("/>") {
+                (format!("{}>", &rest[..tag_end - 2]), "", tag_end)
+            } else {
+                let close = rest.find("</row>").ok_or_else(|| invalid_element("row"))?;
+                (rest[..tag_end].to_string(), &rest[tag_end..close], close + "</row>".len())
+            };
+            let row = match get_attribute(&tag, "r").and_then(|r| r.parse::<u32>().ok()) {
+                Some(r) if r > 0 => r - 1,
+                _ => next_row,
+            };
+
+            let mut cells = BTreeMap::new();
+            let mut next_column = 0;
+            let mut cell_rest = inner;
+            while let Some(start) = find_element(cell_rest, "c") {
+                cell_rest = &cell_rest[start..];
+                let tag_end = cell_rest.find('>').ok_or_else(|| invalid_element("c"))? + 1;
+                let len = if cell_rest[..tag_end].ends_with("/>") {
+                    tag_end
+                } else {
+                    cell_rest.find("</c>").ok_or_else(|| invalid_element("c"))? + "</c>".len()
+                };
+                let cell = &cell_rest[..len];
+                let column = match get_attribute(cell, "r").and_then(|r| CellIndex::try_parse(r.as_str())) {
+                    Some(index) if index.get_x() > 0 => index.get_x() - 1,
+                    _ => next_column,
+                };
+                cells.insert(column, cell.to_string());
+                next_column = column + 1;
+                cell_rest = &cell_rest[len..];
+            }
+
+            rows.insert(row, RowElement { tag, cells, raw: Some(rest[..len].to_string()) });
+            next_row = row + 1;
+            rest = &rest[len..];
+        }
+        Ok(Self { rows })
+    }
+
+    fn get_cell(&self, (row, column): (u32, u32)) -> Option<&String> {
+        self.rows.get(&row).and_then(|r| r.cells.get(&column))
+    }
+
+    fn get_row_mut(&mut self, row: u32) -> &mut RowElement {
+        let element = self.rows.entry(row).or_insert_with(|| RowElement {
+            tag: "<row>".to_string(),
+            cells: BTreeMap::new(),
+            raw: None,
+        });
+        element.raw = None;
+        element
+    }
+
+    /// Applies the changes, returns whether formula cells were overwritten, moved or removed.
+    fn apply(&mut self, changes: &SheetChanges) -> bool {
+        let moved = changes.deleted_rows.first().map(|first| {
+            move |(row, column): (u32, u32)| {
+                row >= *first && row <= changes.last_row && column >= changes.columns.0 && column <= changes.columns.1
+            }
+        });
+        // the cells of a shared formula need the formula of the first cell of the block
+        self.expand_shared_formulas(|position| {
+            changes.cells.contains_key(&position) || moved.as_ref().is_some_and(|moved| moved(position))
+        });
+
+        let mut formulas_changed = false;
+        for (&position, value) in &changes.cells {
+            let current = self.get_cell(position);
+            formulas_changed |= current.is_some_and(|cell| has_formula(cell));
+            let style = match current {
+                Some(cell) => get_attribute(cell, "s"),
+                // new cells continue the format of their column, e.g. a date
+                None => position
+                    .0
+                    .checked_sub(1)
+                    .and_then(|above| self.get_cell((above, position.1)))
+                    .filter(|cell| !matches!(get_attribute(cell, "t").as_deref(), Some("s" | "inlineStr" | "str")))
+                    .and_then(|cell| get_attribute(cell, "s")),
+            };
+            let reference = get_cell_reference(position.0, position.1);
+            let cell = write_cell(reference.as_str(), value, style.as_deref());
+
+            let row = self.get_row_mut(position.0);
+            if cell.is_empty() {
+                row.cells.remove(&position.1);
+            } else {
+                row.cells.insert(position.1, cell);
+            }
+        }
+
+        if let Some(moved) = moved {
+            let mut taken = Vec::new();
+            let rows: Vec<u32> = self.rows.range(changes.deleted_rows.first().copied().unwrap_or(0)..).map(|(r, _)| *r).collect();
+            for row in rows {
+                let columns: Vec<u32> = self.rows[&row].cells.keys().copied().filter(|c| moved((row, *c))).collect();
+                if columns.is_empty() {
+                    continue;
+                }
+                let element = self.get_row_mut(row);
+                for column in columns {
+                    taken.push((row, column, element.cells.remove(&column).unwrap()));
+                }
+            }
+            for (row, column, cell) in taken {
+                formulas_changed |= has_formula(&cell);
+                if changes.deleted_rows.contains(&row) {
+                    continue;
+                }
+                let shift = changes.deleted_rows.range(..row).count() as u32;
+                let cell = move_cell(cell.as_str(), (row - shift, column), -(shift as i64));
+                self.get_row_mut(row - shift).cells.insert(column, cell);
+            }
+        }
+
+        formulas_changed
+    }
+
+    /// Replaces the shared formulas touching the selected cells by a formula in every
+    /// cell of their block.
+    fn expand_shared_formulas(&mut self, selected: impl Fn((u32, u32)) -> bool) {
+        // shared index -> members, and the position and the text of the first cell
+        let mut members: HashMap<String, Vec<(u32, u32)>> = HashMap::new();
+        let mut masters: HashMap<String, ((u32, u32), String)> = HashMap::new();
+        for (&row, element) in &self.rows {
+            for (&column, cell) in &element.cells {
+                if let Some((tag, text)) = get_formula(cell) {
+                    if get_attribute(tag, "t").as_deref() == Some("shared") {
+                        if let Some(index) = get_attribute(tag, "si") {
+                            if !text.is_empty() {
+                                masters.insert(index.clone(), ((row, column), text.to_string()));
+                            }
+                            members.entry(index).or_default().push((row, column));
+                        }
+                    }
+                }
+            }
+        }
+
+        for (index, positions) in members {
+            let ((master_row, master_column), text) = match masters.get(&index) {
+                Some(master) if positions.iter().any(|p| selected(*p)) => master,
+                _ => continue,
+            };
+            for (row, column) in positions {
+                let formula = translate_formula(
+                    text,
+                    row as i64 - *master_row as i64,
+                    column as i64 - *master_column as i64,
+                );
+                let element = self.get_row_mut(row);
+                if let Some(cell) = element.cells.get_mut(&column) {
+                    *cell = replace_formula(cell, formula.as_str());
+                }
+            }
+        }
+    }
+
+    /// Bounds of the cells as an A1 range.
+    fn get_dimension(&self) -> String {
+        let positions = self.rows.iter().flat_map(|(row, e)| e.cells.keys().map(move |column| (*row, *column)));
+        let bounds = positions.fold(None, |bounds: Option<((u32, u32), (u32, u32))>, (row, column)| {
+            Some(match bounds {
+                Some((start, end)) => ((start.0.min(row), start.1.min(column)), (end.0.max(row), end.1.max(column))),
+                None => ((row, column), (row, column)),
+            })
+        });
+        match bounds {
+            Some((start, end)) => format!(
+                "{}:{}",
+                get_cell_reference(start.0, start.1),
+                get_cell_reference(end.0, end.1)
+            ),
+            None => "A1".to_string(),
+        }
+    }
+
+    fn write(&self) -> String {
+        let mut xml = String::from("<sheetData>");
+        for (row, element) in &self.rows {
+            match &element.raw {
+                Some(raw) => xml.push_str(raw),
+                None => {
+                    let tag = set_attribute(remove_attribute(element.tag.as_str(), "spans").as_str(), "r", (row + 1).to_string().as_str());
+                    xml.push_str(tag.as_str());
+                    for cell in element.cells.values() {
+                        xml.push_str(cell);
+                    }
+                    xml.push_str("</row>");
+                }
+            }
+        }
+        xml.push_str("</sheetData>");
+        xml
+    }
+}
+
+fn invalid_element(name: &str) -> PackageError {
+    PackageError::Invalid(format!("worksheet has an unterminated <{}> element", name))
+}
+
+/// Finds the next start tag of the element, e.g. `<c ` but not `<col`.
+fn find_element(xml: &str, name: &str) -> Option<usize> {
+    let open = format!("<{}", name);
+    let mut from = 0;
+    while let Some(pos) = xml[from..].find(open.as_str()) {
+        let start = from + pos;
+        match xml[start + open.len()..].chars().next() {
+            Some(' ' | '>' | '/' | '\t' | '\n' | '\r') => return Some(start),
+            _ => from = start + open.len(),
+        }
+    }
+    None
+}
+
+/// The value of an attribute of the start tag at the beginning of `xml`.
+fn get_attribute(xml: &str, name: &str) -> Option<String> {
+    let tag = &xml[..xml.find('>').map_or(xml.len(), |end| end + 1)];
+    for quote in ['"', '\''] {
+        let pattern = format!(" {}={}", name, quote);
+        if let Some(pos) = tag.find(pattern.as_str()) {
+            let value = &tag[pos + pattern.len()..];
+            return value.find(quote).map(|end| value[..end].to_string());
+        }
+    }
+    None
+}
+
+/// Sets an attribute of the start tag at the beginning of `xml`.
+fn set_attribute(xml: &str, name: &str, value: &str) -> String {
+    let xml = remove_attribute(xml, name);
+    let name_end = xml.find([' ', '>', '/']).unwrap_or(xml.len());
+    format!("{} {}=\"{}\"{}", &xml[..name_end], name, value, &xml[name_end..])
+}
+
+fn remove_attribute(xml: &str, name: &str) -> String {
+    let tag_end = xml.find('>').unwrap_or(xml.len());
+    for quote in ['"', '\''] {
+        let pattern = format!(" {}={}", name, quote);
+        if let Some(pos) = xml[..tag_end].find(pattern.as_str()) {
+            if let Some(len) = xml[pos + pattern.len()..].find(quote) {
+                let end = pos + pattern.len() + len + 1;
+                return format!("{}{}", &xml[..pos], &xml[end..]);
+            }
+        }
+    }
+    xml.to_string()
+}
+
+fn has_formula(cell: &str) -> bool {
+    find_element(cell, "f").is_some()
+}
+
+/// The start tag and the text of the `<f>` element of a cell.
+fn get_formula(cell: &str) -> Option<(&str, &str)> {
+    let start = find_element(cell, "f")?;
+    let tag_end = start + cell[start..].find('>')? + 1;
+    let tag = &cell[start..tag_end];
+    if tag.ends_with("/>") {
+        return Some((tag, ""));
+    }
+    let close = tag_end + cell[tag_end..].find("</f>")?;
+    Some((tag, &cell[tag_end..close]))
+}
+
+/// Replaces the `<f>` element of a cell by a formula of its own.
+fn replace_formula(cell: &str, formula: &str) -> String {
+    let start = match find_element(cell, "f") {
+        Some(start) => start,
+        None => return cell.to_string(),
+    };
+    let tag_end = match cell[start..].find('>') {
+        Some(len) => start + len + 1,
+        None => return cell.to_string(),
+    };
+    let end = if cell[..tag_end].ends_with("/>") {
+        tag_end
+    } else {
+        match cell[tag_end..].find("</f>") {
+            Some(len) => tag_end + len + "</f>".len(),
+            None => return cell.to_string(),
+        }
+    };
+    format!("{}<f>{}</f>{}", &cell[..start], formula, &cell[end..])
+}
+
+/// Moves a cell to a new position, its formula following it.
+fn move_cell(cell: &str, (row, column): (u32, u32), rows: i64) -> String {
+    let cell = set_attribute(cell, "r", get_cell_reference(row, column).as_str());
+    match get_formula(cell.as_str()) {
+        Some((_, text)) if !text.is_empty() => {
+            // the text of a formula is escaped XML, references contain no escaped characters
+            let formula = translate_formula(text, rows, 0);
+            replace_formula(cell.as_str(), formula.as_str())
+        }
+        _ => cell,
+    }
+}
+
+fn replace_dimension(xml: &str, reference: String) -> String {
+    if let Some(start) = xml.find("<dimension ") {
+        if let Some(len) = xml[start..].find("/>") {
+            return format!(
+                "{}<dimension ref=\"{}\"/>{}",
+                &xml[..start],
+                reference,
+                &xml[start + len + 2..]
+            );
+        }
+    }
+
+    xml.to_string()
+}
+
+/// Removes the self-closing elements whose markup contains `needle`.
+fn remove_elements_containing(xml: &str, needle: &str) -> String {
+    let mut out = xml.to_string();
+    while let Some(pos) = out.find(needle) {
+        let start = match out[..pos].rfind('<') {
+            Some(start) => start,
+            None => break,
+        };
+        let end = match out[pos..].find("/>") {
+            Some(len) => pos + len + 2,
+            None => break,
+        };
+        out.replace_range(start..end, "");
+    }
+    out
+}
+
+/// Writes a `<c>` element with the style, an empty cell keeps only its style.
+fn write_cell(reference: &str, value: &DataType, style: Option<&str>) -> String {
+    let attributes = match style {
+        Some(style) => format!("r=\"{}\" s=\"{}\"", reference, style),
+        None => format!("r=\"{}\"", reference),
+    };
+    match value {
+        DataType::Empty if style.is_some() => format!("<c {}/>", attributes),
+        DataType::Empty => String::new(),
+        DataType::String(s) => format!(
+            "<c {} t=\"inlineStr\"><is><t xml:space=\"preserve\">{}</t></is></c>",
+            attributes,
+            escape_xml(s)
+        ),
+        DataType::Int(n) => format!("<c {}><v>{}</v></c>", attributes, n),
+        DataType::Float(f) | DataType::DateTime(f) => {
+            if f.is_finite() {
+                format!("<c {}><v>{}</v></c>", attributes, f)
+            } else {
+                format!("<c {} t=\"e\"><v>#NUM!</v></c>", attributes)
+            }
+        }
+        DataType::Bool(b) => format!(
+            "<c {} t=\"b\"><v>{}</v></c>",
+            attributes,
+            if *b { 1 } else { 0 }
+        ),
+        DataType::Error(e) => format!(
+            "<c {} t=\"e\"><v>{}</v></c>",
+            attributes,
+            escape_xml(e.to_string().as_str())
+        ),
+    }
+}
+
+/// Formats a 0-based (row, column) position as an A1 reference.
+pub fn get_cell_reference(row: u32, col: u32) -> String {
+    let index = CellIndex::new(col + 1, row);
+    format!("{}{}", index.get_x_as_string(), index.get_y_as_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn get_cell_reference_formats_a1_reference() {
+        assert_eq!(get_cell_reference(0, 0), "A1");
+        assert_eq!(get_cell_reference(9, 27), "AB10");
+    }
+
+    #[test]
+    fn patch_sheet_data_keeps_untouched_cells() {
+        let xml = "<worksheet><dimension ref=\"A1:C2\"/><sheetData>\
+            <row r=\"1\" spans=\"1:3\"><c r=\"A1\" s=\"2\"><v>45292</v></c><c r=\"C1\"><f>A1+1</f><v>45293</v></c></row>\
+            <row r=\"2\"><c r=\"A2\" s=\"2\"><v>45300</v></c></row>\
+            </sheetData><mergeCells/></worksheet>";
+        let mut changes = SheetChanges::default();
+        changes.cells.insert((1, 1), DataType::String("a<b".to_string()));
+        changes.cells.insert((2, 0), DataType::Float(45301.0));
+
+        let (out, formulas_changed) = patch_sheet_data(xml, &changes).unwrap();
+
+        assert_eq!(out, "<worksheet><dimension ref=\"A1:C3\"/><sheetData>\
+            <row r=\"1\" spans=\"1:3\"><c r=\"A1\" s=\"2\"><v>45292</v></c><c r=\"C1\"><f>A1+1</f><v>45293</v></c></row>\
+            <row r=\"2\"><c r=\"A2\" s=\"2\"><v>45300</v></c>\
+            <c r=\"B2\" t=\"inlineStr\"><is><t xml:space=\"preserve\">a&lt;b</t></is></c></row>\
+            <row r=\"3\"><c r=\"A3\" s=\"2\"><v>45301</v></c></row>\
+            </sheetData><mergeCells/></worksheet>");
+        assert!(!formulas_changed);
+    }
+
+    #[test]
+    fn patch_sheet_data_shifts_rows_of_deleted_cells() {
+        let xml = "<worksheet><sheetData>\
+            <row r=\"1\"><c r=\"A1\"><v>1</v></c><c r=\"C1\"><v>9</v></c></row>\
+            <row r=\"2\"><c r=\"A2\"><v>2</v></c><c r=\"B2\"><f t=\"shared\" ref=\"B2:B3\" si=\"0\">A2*2</f><v>4</v></c></row>\
+            <row r=\"3\" ht=\"20\"><c r=\"A3\" s=\"1\"><v>3</v></c><c r=\"B3\"><f t=\"shared\" si=\"0\"/><v>6</v></c></row>\
+            </sheetData></worksheet>";
+        let changes = SheetChanges {
+            deleted_rows: BTreeSet::from([1]),
+            columns: (0, 1),
+            last_row: 2,
+            ..Default::default()
+        };
+
+        let (out, formulas_changed) = patch_sheet_data(xml, &changes).unwrap();
+
+        assert_eq!(out, "<worksheet><sheetData>\
+            <row r=\"1\"><c r=\"A1\"><v>1</v></c><c r=\"C1\"><v>9</v></c></row>\
+            <row r=\"2\"><c r=\"A2\" s=\"1\"><v>3</v></c><c r=\"B2\"><f>A2*2</f><v>6</v></c></row>\
+            <row r=\"3\" ht=\"20\"></row>\
+            </sheetData></worksheet>");
+        assert!(formulas_changed);
+    }
+
+    #[test]
+    fn remove_elements_containing_drops_calc_chain_references() {
+        let xml = "<Types><Override PartName=\"/xl/calcChain.xml\" ContentType=\"x\"/><Default Extension=\"xml\"/></Types>";
+        assert_eq!(
+            remove_elements_containing(xml, "calcChain"),
+            "<Types><Default Extension=\"xml\"/></Types>"
+        );
+    }
+}
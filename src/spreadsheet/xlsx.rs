@@ -0,0 +1,498 @@
+use crate::spreadsheet::cells::CellIndex;
+use crate::spreadsheet::formulas::translate_formula;
+use quick_xml::events::{BytesStart, Event};
+use quick_xml::Reader;
+use std::collections::HashMap;
+use std::fmt;
+use std::io::{BufReader, Read, Seek};
+use zip::result::ZipError;
+use zip::ZipArchive;
+
+const WORKBOOK_PART: &str = "xl/workbook.xml";
+const WORKBOOK_RELS_PART: &str = "xl/_rels/workbook.xml.rels";
+
+#[derive(Debug)]
+pub enum PackageError {
+    Io(std::io::Error),
+    Zip(ZipError),
+    Xml(quick_xml::Error),
+    Unsupported(String),
+    Invalid(String),
+}
+
+impl fmt::Display for PackageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PackageError::Io(e) => write!(f, "I/O error: {}", e),
+            PackageError::Zip(e) => write!(f, "Zip error: {}", e),
+            PackageError::Xml(e) => write!(f, "Xml error: {}", e),
+            PackageError::Unsupported(msg) => write!(f, "{}", msg),
+            PackageError::Invalid(msg) => write!(f, "Invalid xlsx package: {}", msg),
+        }
+    }
+}
+
+impl From<std::io::Error> for PackageError {
+    fn from(e: std::io::Error) -> Self {
+        PackageError::Io(e)
+    }
+}
+
+impl From<ZipError> for PackageError {
+    fn from(e: ZipError) -> Self {
+        PackageError::Zip(e)
+    }
+}
+
+impl From<quick_xml::Error> for PackageError {
+    fn from(e: quick_xml::Error) -> Self {
+        PackageError::Xml(e)
+    }
+}
+
+/// A worksheet as declared in `xl/workbook.xml`.
+#[derive(Debug, Clone)]
+pub struct SheetEntry {
+    pub name: String,
+    pub state: Option<String>,
+    pub part: String,
+}
+
+/// The 0-based position of a cell and its formula, without the leading `=`.
+pub type CellFormula = ((u32, u32), String);
+
+/// An Excel table (ListObject) as declared in `xl/tables/tableN.xml`.
+#[derive(Debug, Clone)]
+pub struct TableEntry {
+    pub name: String,
+    pub display_name: String,
+    pub sheet: String,
+    /// The cells of the table, including the header and totals rows, e.g. `A1:D7`.
+    pub reference: String,
+    pub header_rows: u32,
+    pub totals_rows: u32,
+    pub columns: Vec<String>,
+}
+
+/// Reads a part of the package as text, `None` if the part does not exist.
+pub fn read_part<R: Read + Seek>(
+    archive: &mut ZipArchive<R>,
+    name: &str,
+) -> Result<Option<String>, PackageError> {
+    match archive.by_name(name) {
+        Ok(mut file) => {
+            let mut s = String::new();
+            file.read_to_string(&mut s)?;
+            Ok(Some(s))
+        }
+        Err(ZipError::FileNotFound) => Ok(None),
+        Err(e) => Err(PackageError::Zip(e)),
+    }
+}
+
+/// Lists the worksheets of the workbook in their declared order.
+pub fn read_sheet_entries<R: Read + Seek>(
+    archive: &mut ZipArchive<R>,
+) -> Result<Vec<SheetEntry>, PackageError> {
+    let workbook = read_part(archive, WORKBOOK_PART)?
+        .ok_or_else(|| PackageError::Invalid(format!("{} is missing", WORKBOOK_PART)))?;
+    let rels = read_part(archive, WORKBOOK_RELS_PART)?
+        .ok_or_else(|| PackageError::Invalid(format!("{} is missing", WORKBOOK_RELS_PART)))?;
+
+    let targets = read_relationship_targets(rels.as_str())?;
+
+    let mut entries = Vec::new();
+    let mut reader = Reader::from_str(workbook.as_str());
+    loop {
+        match reader.read_event()? {
+            Event::Start(ref e) | Event::Empty(ref e) if e.local_name().as_ref() == b"sheet" => {
+                let name = get_attribute(&reader, e, b"name", false)?.unwrap_or_default();
+                let state = get_attribute(&reader, e, b"state", false)?;
+                let id = get_attribute(&reader, e, b"id", true)?.unwrap_or_default();
+                let target = targets.get(&id).ok_or_else(|| {
+                    PackageError::Invalid(format!("no relationship for sheet '{}'", name))
+                })?;
+                entries.push(SheetEntry {
+                    name,
+                    state,
+                    part: resolve_part("xl", target),
+                });
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+
+    Ok(entries)
+}
+
+/// Lists the tables of all worksheets of the workbook.
+pub fn read_table_entries<R: Read + Seek>(
+    archive: &mut ZipArchive<R>,
+) -> Result<Vec<TableEntry>, PackageError> {
+    let mut tables = Vec::new();
+    for sheet in read_sheet_entries(archive)? {
+        let (folder, file) = sheet.part.rsplit_once('/').unwrap_or(("", sheet.part.as_str()));
+        let rels = match read_part(archive, format!("{}/_rels/{}.rels", folder, file).as_str())? {
+            Some(rels) => rels,
+            None => continue,
+        };
+
+        for target in read_relationship_targets_of_type(rels.as_str(), "/table")? {
+            let part = resolve_part(folder, target.as_str());
+            let xml = read_part(archive, part.as_str())?
+                .ok_or_else(|| PackageError::Invalid(format!("{} is missing", part)))?;
+            tables.push(read_table(xml.as_str(), sheet.name.as_str())?);
+        }
+    }
+    Ok(tables)
+}
+
+/// Lists the merged regions of the worksheet, e.g. `A2:A4`.
+pub fn read_merged_cells<R: Read + Seek>(
+    archive: &mut ZipArchive<R>,
+    sheet: &str,
+) -> Result<Vec<String>, PackageError> {
+    let entry = match read_sheet_entries(archive)?.into_iter().find(|e| e.name == sheet) {
+        Some(entry) => entry,
+        None => return Ok(Vec::new()),
+    };
+    let xml = read_part(archive, entry.part.as_str())?
+        .ok_or_else(|| PackageError::Invalid(format!("{} is missing", entry.part)))?;
+
+    let mut regions = Vec::new();
+    let mut reader = Reader::from_str(xml.as_str());
+    loop {
+        match reader.read_event()? {
+            Event::Start(ref e) | Event::Empty(ref e) if e.local_name().as_ref() == b"mergeCell" => {
+                if let Some(reference) = get_attribute(&reader, e, b"ref", false)? {
+                    regions.push(reference);
+                }
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+    Ok(regions)
+}
+
+/// Reads the `<dimension ref>` of a worksheet part, e.g. `A1:D6`, without reading its cells.
+/// `None` if the worksheet doesn't declare its dimension.
+pub fn read_dimension<R: Read + Seek>(
+    archive: &mut ZipArchive<R>,
+    part: &str,
+) -> Result<Option<String>, PackageError> {
+    let file = match archive.by_name(part) {
+        Ok(file) => file,
+        Err(ZipError::FileNotFound) => return Err(PackageError::Invalid(format!("{} is missing", part))),
+        Err(e) => return Err(PackageError::Zip(e)),
+    };
+
+    let mut reader = Reader::from_reader(BufReader::new(file));
+    let mut buf = Vec::new();
+    loop {
+        match reader.read_event_into(&mut buf)? {
+            Event::Start(ref e) | Event::Empty(ref e) if e.local_name().as_ref() == b"dimension" => {
+                return get_attribute(&reader, e, b"ref", false);
+            }
+            // the dimension precedes the cells
+            Event::Start(ref e) | Event::Empty(ref e) if e.local_name().as_ref() == b"sheetData" => {
+                return Ok(None);
+            }
+            Event::Eof => return Ok(None),
+            _ => {}
+        }
+        buf.clear();
+    }
+}
+
+/// Reads the formulas of the cells sharing the formula of the first cell of their block,
+/// which are stored without text (`<f t="shared" si="0"/>`). The formula of the first
+/// cell is translated to each of them, e.g. `B3*C3` below `B2*C2`.
+pub fn read_shared_formulas<R: Read + Seek>(
+    archive: &mut ZipArchive<R>,
+    sheet: &str,
+) -> Result<Vec<CellFormula>, PackageError> {
+    let entry = match read_sheet_entries(archive)?.into_iter().find(|e| e.name == sheet) {
+        Some(entry) => entry,
+        None => return Ok(Vec::new()),
+    };
+    let file = match archive.by_name(entry.part.as_str()) {
+        Ok(file) => file,
+        Err(ZipError::FileNotFound) => return Err(PackageError::Invalid(format!("{} is missing", entry.part))),
+        Err(e) => return Err(PackageError::Zip(e)),
+    };
+
+    // shared index -> the position and the text of the first cell
+    let mut masters: HashMap<String, ((u32, u32), String)> = HashMap::new();
+    let mut members = Vec::new();
+    let mut cell = None;
+    // the shared index and the text of the formula being read
+    let mut formula: Option<(String, String)> = None;
+
+    let mut reader = Reader::from_reader(BufReader::new(file));
+    let mut buf = Vec::new();
+    loop {
+        match reader.read_event_into(&mut buf)? {
+            Event::Start(ref e) if e.local_name().as_ref() == b"c" => {
+                cell = get_attribute(&reader, e, b"r", false)?
+                    .and_then(|r| CellIndex::try_parse(r.as_str()))
+                    .map(CellIndex::to_zero_indexed);
+            }
+            Event::Start(ref e) if e.local_name().as_ref() == b"f" => {
+                if let Some(index) = get_shared_index(&reader, e)? {
+                    formula = Some((index, String::new()));
+                }
+            }
+            Event::Empty(ref e) if e.local_name().as_ref() == b"f" => {
+                if let (Some(index), Some(position)) = (get_shared_index(&reader, e)?, cell) {
+                    members.push((position, index));
+                }
+            }
+            Event::Text(ref t) => {
+                if let Some((_, text)) = formula.as_mut() {
+                    text.push_str(&t.unescape()?);
+                }
+            }
+            Event::End(ref e) if e.local_name().as_ref() == b"f" => {
+                if let (Some((index, text)), Some(position)) = (formula.take(), cell) {
+                    if text.is_empty() {
+                        members.push((position, index));
+                    } else {
+                        masters.insert(index, (position, text));
+                    }
+                }
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+        buf.clear();
+    }
+
+    Ok(members
+        .into_iter()
+        .filter_map(|((row, column), index)| {
+            let ((master_row, master_column), text) = masters.get(&index)?;
+            let formula = translate_formula(
+                text,
+                row as i64 - *master_row as i64,
+                column as i64 - *master_column as i64,
+            );
+            Some(((row, column), formula))
+        })
+        .collect())
+}
+
+/// The `si` index of a `<f t="shared">` formula, `None` for other formulas.
+fn get_shared_index<B>(reader: &Reader<B>, e: &BytesStart) -> Result<Option<String>, PackageError> {
+    match get_attribute(reader, e, b"t", false)?.as_deref() {
+        Some("shared") => get_attribute(reader, e, b"si", false),
+        _ => Ok(None),
+    }
+}
+
+fn read_table(xml: &str, sheet: &str) -> Result<TableEntry, PackageError> {
+    let mut table = None;
+    let mut columns = Vec::new();
+    let mut reader = Reader::from_str(xml);
+    loop {
+        match reader.read_event()? {
+            Event::Start(ref e) | Event::Empty(ref e) if e.local_name().as_ref() == b"table" => {
+                let count = |name: &[u8], default: u32| -> Result<u32, PackageError> {
+                    Ok(get_attribute(&reader, e, name, false)?
+                        .and_then(|v| v.parse().ok())
+                        .unwrap_or(default))
+                };
+                let name = get_attribute(&reader, e, b"name", false)?.unwrap_or_default();
+                table = Some(TableEntry {
+                    display_name: get_attribute(&reader, e, b"displayName", false)?
+                        .unwrap_or_else(|| name.clone()),
+                    name,
+                    sheet: sheet.to_string(),
+                    reference: get_attribute(&reader, e, b"ref", false)?.unwrap_or_default(),
+                    header_rows: count(b"headerRowCount", 1)?,
+                    totals_rows: count(b"totalsRowCount", 0)?,
+                    columns: Vec::new(),
+                });
+            }
+            Event::Start(ref e) | Event::Empty(ref e) if e.local_name().as_ref() == b"tableColumn" => {
+                columns.push(get_attribute(&reader, e, b"name", false)?.unwrap_or_default());
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+
+    let mut table = table.ok_or_else(|| PackageError::Invalid("table definition is missing".to_string()))?;
+    table.columns = columns;
+    Ok(table)
+}
+
+/// Resolves a relationship target relative to the folder of the source part.
+pub fn resolve_part(folder: &str, target: &str) -> String {
+    if let Some(absolute) = target.strip_prefix('/') {
+        return absolute.to_string();
+    }
+
+    let mut parts: Vec<&str> = folder.split('/').filter(|p| !p.is_empty()).collect();
+    for segment in target.split('/') {
+        match segment {
+            ".." => {
+                parts.pop();
+            }
+            "." | "" => {}
+            s => parts.push(s),
+        }
+    }
+    parts.join("/")
+}
+
+pub fn read_relationship_targets(xml: &str) -> Result<HashMap<String, String>, PackageError> {
+    let mut targets = HashMap::new();
+    let mut reader = Reader::from_str(xml);
+    loop {
+        match reader.read_event()? {
+            Event::Start(ref e) | Event::Empty(ref e)
+                if e.local_name().as_ref() == b"Relationship" =>
+            {
+                let id = get_attribute(&reader, e, b"Id", false)?;
+                let target = get_attribute(&reader, e, b"Target", false)?;
+                if let (Some(id), Some(target)) = (id, target) {
+                    targets.insert(id, target);
+                }
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+    Ok(targets)
+}
+
+/// Returns the targets of the relationships whose type ends with `kind`, e.g. `/table`.
+fn read_relationship_targets_of_type(xml: &str, kind: &str) -> Result<Vec<String>, PackageError> {
+    let mut targets = Vec::new();
+    let mut reader = Reader::from_str(xml);
+    loop {
+        match reader.read_event()? {
+            Event::Start(ref e) | Event::Empty(ref e)
+                if e.local_name().as_ref() == b"Relationship" =>
+            {
+                let kind_matches = get_attribute(&reader, e, b"Type", false)?
+                    .is_some_and(|t| t.ends_with(kind));
+                if let (true, Some(target)) = (kind_matches, get_attribute(&reader, e, b"Target", false)?) {
+                    targets.push(target);
+                }
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+    Ok(targets)
+}
+
+/// Reads an attribute by its local name, `prefixed` selects attributes
+/// with a namespace prefix (such as `r:id`) or without one.
+pub fn get_attribute<B>(
+    reader: &Reader<B>,
+    e: &BytesStart,
+    local_name: &[u8],
+    prefixed: bool,
+) -> Result<Option<String>, PackageError> {
+    for attribute in e.attributes() {
+        let attribute = attribute.map_err(|err| PackageError::Xml(err.into()))?;
+        let key = attribute.key;
+        if key.local_name().as_ref() == local_name && key.prefix().is_some() == prefixed {
+            return Ok(Some(attribute.decode_and_unescape_value(reader)?.into_owned()));
+        }
+    }
+    Ok(None)
+}
+
+pub fn escape_xml(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for ch in s.chars() {
+        match ch {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&apos;"),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::File;
+
+    #[test]
+    fn resolve_part_handles_relative_and_absolute_targets() {
+        assert_eq!(resolve_part("xl", "worksheets/sheet1.xml"), "xl/worksheets/sheet1.xml");
+        assert_eq!(resolve_part("xl", "/xl/worksheets/sheet1.xml"), "xl/worksheets/sheet1.xml");
+        assert_eq!(resolve_part("xl/worksheets", "../tables/table1.xml"), "xl/tables/table1.xml");
+    }
+
+    #[test]
+    fn read_sheet_entries_lists_worksheets() {
+        let file = File::open("./tests/abcdef.xlsx").unwrap();
+        let mut archive = ZipArchive::new(file).unwrap();
+        let entries = read_sheet_entries(&mut archive).unwrap();
+
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].name, "Sheet1");
+        assert_eq!(entries[0].part, "xl/worksheets/sheet1.xml");
+    }
+
+    #[test]
+    fn read_table_entries_lists_tables() {
+        let file = File::open("./tests/tables.xlsx").unwrap();
+        let mut archive = ZipArchive::new(file).unwrap();
+        let tables = read_table_entries(&mut archive).unwrap();
+
+        assert_eq!(tables.len(), 1);
+        assert_eq!(tables[0].name, "Table1");
+        assert_eq!(tables[0].display_name, "SalesTable");
+        assert_eq!(tables[0].sheet, "Sheet1");
+        assert_eq!(tables[0].reference, "A1:D7");
+        assert_eq!((tables[0].header_rows, tables[0].totals_rows), (1, 1));
+        assert_eq!(tables[0].columns, vec!["alpha", "number", "word", "kind"]);
+    }
+
+    #[test]
+    fn read_merged_cells_lists_regions() {
+        let file = File::open("./tests/merged.xlsx").unwrap();
+        let mut archive = ZipArchive::new(file).unwrap();
+
+        assert_eq!(read_merged_cells(&mut archive, "Sheet1").unwrap(), vec!["A2:A4", "A5:A6"]);
+        assert!(read_merged_cells(&mut archive, "Missing").unwrap().is_empty());
+    }
+
+    #[test]
+    fn read_dimension_reads_used_range() {
+        let file = File::open("./tests/abcdef_colnames.xlsx").unwrap();
+        let mut archive = ZipArchive::new(file).unwrap();
+        assert_eq!(
+            read_dimension(&mut archive, "xl/worksheets/sheet1.xml").unwrap(),
+            Some("A1:D7".to_string())
+        );
+
+        let file = File::open("./tests/abcdef.xlsx").unwrap();
+        let mut archive = ZipArchive::new(file).unwrap();
+        assert_eq!(read_dimension(&mut archive, "xl/worksheets/sheet1.xml").unwrap(), None);
+    }
+
+    #[test]
+    fn read_shared_formulas_translates_the_first_formula() {
+        let file = File::open("./tests/shared_formulas.xlsx").unwrap();
+        let mut archive = ZipArchive::new(file).unwrap();
+
+        assert_eq!(
+            read_shared_formulas(&mut archive, "Sheet1").unwrap(),
+            vec![((2, 3), "B3*C3".to_string())]
+        );
+        assert!(read_shared_formulas(&mut archive, "Missing").unwrap().is_empty());
+    }
+}
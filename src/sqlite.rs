@@ -1,6 +1,7 @@
 #![allow(non_snake_case)]
 #![allow(dead_code)]
 #![allow(non_camel_case_types)]
+#![allow(clippy::all)]
 
 /* automatically generated by rust-bindgen */
 
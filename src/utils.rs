@@ -1,7 +1,10 @@
 use std::ffi::{c_void, CStr, CString};
+use std::collections::HashSet;
 use std::os::raw::{c_char, c_int, c_longlong};
 use std::ptr::copy_nonoverlapping;
 use calamine::DataType;
+use crate::sqlite::{sqlite3_value, sqlite3_vtab, SQLITE_ERROR, SQLITE_FLOAT, SQLITE_INTEGER, SQLITE_NULL, SQLITE_TEXT, SQLITE_WARNING};
+use crate::spreadsheet::columns::Column;
 use crate::{parse_option, sqlite3, sqlite3_api_routines, sqlite3_context, UsingOption};
 
 pub unsafe fn read_string_from_raw(raw: *const c_char) -> String {
@@ -14,7 +17,7 @@ pub unsafe fn collect_strings_from_raw(n: usize, args: *const *const c_char) ->
 
     let args = args as *mut *const c_char;
     for i in 0..n {
-        let arg = *(args.offset(i as isize));
+        let arg = *(args.add(i));
         let s = read_string_from_raw(arg);
         vec.push(s);
     }
@@ -23,7 +26,11 @@ pub unsafe fn collect_strings_from_raw(n: usize, args: *const *const c_char) ->
 }
 
 pub unsafe fn error_to_sqlite3_string(api: *mut sqlite3_api_routines, err: String) -> Option<*mut c_char> {
-    let cstr = CString::new(err).ok()?;
+    string_to_sqlite3_string(api, err)
+}
+
+pub unsafe fn string_to_sqlite3_string(api: *mut sqlite3_api_routines, s: String) -> Option<*mut c_char> {
+    let cstr = CString::new(s).ok()?;
     let len = cstr.as_bytes_with_nul().len();
 
     let ptr = ((*api).malloc.unwrap())(len as c_int) as *mut c_char;
@@ -35,12 +42,53 @@ pub unsafe fn error_to_sqlite3_string(api: *mut sqlite3_api_routines, err: Strin
     }
 }
 
-pub unsafe fn declare_table(db: *mut sqlite3, api: *mut sqlite3_api_routines, columns: Vec<String>) -> c_int {
+/// Replaces the error message of the virtual table, returns SQLITE_ERROR.
+pub unsafe fn set_error_message(api: *mut sqlite3_api_routines, p_vtab: *mut sqlite3_vtab, message: String) -> c_int {
+    if !(*p_vtab).zErrMsg.is_null() {
+        ((*api).free.unwrap())((*p_vtab).zErrMsg as *mut c_void);
+    }
+    (*p_vtab).zErrMsg = string_to_sqlite3_string(api, message).unwrap_or(std::ptr::null_mut());
+
+    SQLITE_ERROR
+}
+
+/// Writes a warning to the SQLite error log.
+pub unsafe fn log_warning(api: *mut sqlite3_api_routines, message: String) {
+    if let Ok(cstr) = CString::new(message) {
+        ((*api).log.unwrap())(SQLITE_WARNING, c"%s".as_ptr(), cstr.as_ptr());
+    }
+}
+
+/// Declares the columns of the virtual table followed by the hidden ones. A column taking
+/// the name of a hidden column, e.g. one declared by SCHEMA, gets a `_2`, `_3`, ... suffix.
+pub unsafe fn declare_table(db: *mut sqlite3, api: *mut sqlite3_api_routines, columns: Vec<Column>, hidden: Vec<String>) -> c_int {
+    let mut used: HashSet<String> = columns.iter().map(|column| column.get_name().to_lowercase()).collect();
+    let reserved: HashSet<String> = hidden.iter().map(|name| name.to_lowercase()).collect();
+    used.extend(reserved.iter().cloned());
+
     let mut sql = String::from("CREATE TABLE sheet(");
     for column in columns {
+        let mut name = column.get_name().to_string();
+        if reserved.contains(&name.to_lowercase()) {
+            let mut suffix = 2;
+            while !used.insert(format!("{}_{}", column.get_name(), suffix).to_lowercase()) {
+                suffix += 1;
+            }
+            name = format!("{}_{}", column.get_name(), suffix);
+        }
+        sql.push('`');
+        sql.push_str(name.replace('`', "``").as_str());
+        sql.push('`');
+        if let Some(declared_type) = column.get_declared_type() {
+            sql.push(' ');
+            sql.push_str(declared_type);
+        }
+        sql.push(',');
+    }
+    for column in hidden {
         sql.push('`');
-        sql.push_str(column.as_str());
-        sql.push_str("`,");
+        sql.push_str(column.replace('`', "``").as_str());
+        sql.push_str("` HIDDEN,");
     }
     sql.pop();
     sql.push(')');
@@ -54,15 +102,29 @@ pub unsafe fn collect_options_from_args(argc: c_int, argv: *const *const c_char)
 
     let args = collect_strings_from_raw(argc as usize, argv);
     for arg in args {
-        match parse_option(arg.as_str()) {
-            Ok((_, option)) => options.push(option),
-            _ => {}
+        if let Ok((_, option)) = parse_option(arg.as_str()) {
+            options.push(option);
         }
     }
 
     options
 }
 
+pub unsafe fn read_value(api: *mut sqlite3_api_routines, value: *mut sqlite3_value) -> Option<DataType> {
+    match ((*api).value_type.unwrap())(value) {
+        SQLITE_INTEGER => Some(DataType::Int(((*api).value_int64.unwrap())(value))),
+        SQLITE_FLOAT => Some(DataType::Float(((*api).value_double.unwrap())(value))),
+        SQLITE_TEXT => {
+            let raw = ((*api).value_text.unwrap())(value);
+            let len = ((*api).value_bytes.unwrap())(value);
+            let bytes = std::slice::from_raw_parts(raw, len as usize);
+            Some(DataType::String(String::from_utf8_lossy(bytes).into_owned()))
+        }
+        SQLITE_NULL => Some(DataType::Empty),
+        _ => None,
+    }
+}
+
 pub unsafe fn yield_result(p_context: *mut sqlite3_context, api: *mut sqlite3_api_routines, value: &DataType) {
     match value {
         DataType::String(s) => {
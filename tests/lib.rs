@@ -1,4 +1,5 @@
 use rusqlite::{Connection, params};
+use std::sync::{Mutex, Once};
 
 #[cfg(target_os = "linux")]
 static LIB_PATH: &str = "./target/debug/libxlite.so";
@@ -9,9 +10,31 @@ static LIB_PATH: &str = "./target/debug/libxlite.dylib";
 #[cfg(target_os = "windows")]
 static LIB_PATH: &str = "./target/debug/xlite.dll";
 
+static LOG: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
 fn init_connection() -> Connection {
-    let connection = Connection::open_in_memory().unwrap();
+    init_log();
+    load_extension(Connection::open_in_memory().unwrap())
+}
+
+fn init_file_connection(path: &std::path::Path) -> Connection {
+    init_log();
+    load_extension(Connection::open(path).unwrap())
+}
+
+// the log has to be set up before SQLite is initialized by the first connection
+fn init_log() {
+    static INIT: Once = Once::new();
+    INIT.call_once(|| unsafe {
+        rusqlite::trace::config_log(Some(|_, message| LOG.lock().unwrap().push(message.to_string()))).unwrap();
+    });
+}
+
+fn logged(text: &str) -> usize {
+    LOG.lock().unwrap().iter().filter(|message| message.contains(text)).count()
+}
 
+fn load_extension(connection: Connection) -> Connection {
     unsafe {
         connection.load_extension_enable().unwrap();
         connection.load_extension(LIB_PATH, None).unwrap();
@@ -178,3 +201,1383 @@ fn test_abcdef_file_with_colnames() {
         Abcd { alpha: "F".to_string(), number: 15.0, word: "fifteen".to_string(), kind: "odd".to_string() },
     ]);
 }
+
+#[test]
+fn test_abcdef_file_with_rowid_constraints() {
+    let connection = init_connection();
+    connection.execute("\
+        CREATE VIRTUAL TABLE test_data USING xlite(\
+            FILENAME './tests/abcdef.xlsx',\
+            WORKSHEET 'Sheet1'\
+        );\
+    ", params![]).unwrap();
+
+    let mut query = connection.prepare("\
+        SELECT B, C FROM test_data WHERE rowid BETWEEN 1 AND 2;\
+    ").unwrap();
+
+    let rows = query.query_map(params![], |row| Ok(Bc {
+        number: row.get(0).unwrap(),
+        word: row.get(1).unwrap(),
+    })).unwrap();
+
+    let data = rows
+        .map(|r| r.unwrap())
+        .collect::<Vec<Bc>>();
+
+    assert_eq!(data, vec![
+        Bc { number: 11.0, word: "eleven".to_string() },
+        Bc { number: 12.0, word: "twelve".to_string() },
+    ]);
+}
+
+#[test]
+fn test_abcdef_file_with_column_constraints() {
+    let connection = init_connection();
+    connection.execute("\
+        CREATE VIRTUAL TABLE test_data USING xlite(\
+            FILENAME './tests/abcdef.xlsx',\
+            WORKSHEET 'Sheet1'\
+        );\
+    ", params![]).unwrap();
+
+    let mut query = connection.prepare("\
+        SELECT B, C FROM test_data WHERE D = 'odd' AND B > 11;\
+    ").unwrap();
+
+    let rows = query.query_map(params![], |row| Ok(Bc {
+        number: row.get(0).unwrap(),
+        word: row.get(1).unwrap(),
+    })).unwrap();
+
+    let data = rows
+        .map(|r| r.unwrap())
+        .collect::<Vec<Bc>>();
+
+    assert_eq!(data, vec![
+        Bc { number: 13.0, word: "thirteen".to_string() },
+        Bc { number: 15.0, word: "fifteen".to_string() },
+    ]);
+}
+
+#[test]
+fn test_abcdef_file_self_join() {
+    let connection = init_connection();
+    connection.execute("\
+        CREATE VIRTUAL TABLE test_data USING xlite(\
+            FILENAME './tests/abcdef.xlsx',\
+            WORKSHEET 'Sheet1'\
+        );\
+    ", params![]).unwrap();
+
+    let count: i32 = connection.query_row("\
+        SELECT COUNT(*) FROM test_data a JOIN test_data b ON a.D = b.D;\
+    ", params![], |row| row.get(0)).unwrap();
+
+    assert_eq!(count, 18);
+}
+
+#[test]
+fn test_reload_after_file_change() {
+    let path = std::env::temp_dir().join("xlite_test_reload_after_file_change.xlsx");
+    std::fs::copy("./tests/abcdef.xlsx", &path).unwrap();
+
+    let connection = init_connection();
+    connection.execute(format!("\
+        CREATE VIRTUAL TABLE test_data USING xlite(\
+            FILENAME '{}',\
+            WORKSHEET 'Sheet1'\
+        );\
+    ", path.display()).as_str(), params![]).unwrap();
+
+    let first: String = connection.query_row("\
+        SELECT A FROM test_data LIMIT 1;\
+    ", params![], |row| row.get(0)).unwrap();
+
+    std::fs::copy("./tests/abcdef_colnames.xlsx", &path).unwrap();
+
+    let second: String = connection.query_row("\
+        SELECT A FROM test_data LIMIT 1;\
+    ", params![], |row| row.get(0)).unwrap();
+
+    std::fs::remove_file(&path).unwrap();
+
+    assert_eq!(first, "A");
+    assert_eq!(second, "alpha");
+}
+
+#[test]
+fn test_missing_worksheet_fails_queries() {
+    let path = std::env::temp_dir().join("xlite_test_missing_worksheet_fails_queries.xlsx");
+    std::fs::copy("./tests/abcdef.xlsx", &path).unwrap();
+
+    let connection = init_connection();
+    connection.execute(format!("\
+        CREATE VIRTUAL TABLE test_data USING xlite(\
+            FILENAME '{}',\
+            WORKSHEET 'Sheet1'\
+        );\
+    ", path.display()).as_str(), params![]).unwrap();
+
+    // the new version of the workbook has no Sheet1
+    std::fs::copy("./tests/report.xlsx", &path).unwrap();
+    let missing = connection.query_row("SELECT count(*) FROM test_data;", params![], |row| row.get::<_, i64>(0));
+
+    std::fs::remove_file(&path).unwrap();
+
+    assert!(missing.unwrap_err().to_string().contains("Worksheet 'Sheet1' not found"));
+}
+
+#[test]
+fn test_insert_appends_rows_to_file() {
+    let path = std::env::temp_dir().join("xlite_test_insert_appends_rows_to_file.xlsx");
+    std::fs::copy("./tests/abcdef.xlsx", &path).unwrap();
+
+    let create = format!("\
+        CREATE VIRTUAL TABLE test_data USING xlite(\
+            FILENAME '{}',\
+            WORKSHEET 'Sheet1'\
+        );\
+    ", path.display());
+
+    let connection = init_connection();
+    connection.execute(create.as_str(), params![]).unwrap();
+    connection.execute("\
+        INSERT INTO test_data VALUES ('G', 16, 'sixteen', 'even'), ('H', 17, 'seventeen', 'odd');\
+    ", params![]).unwrap();
+    drop(connection);
+
+    let connection = init_connection();
+    connection.execute(create.as_str(), params![]).unwrap();
+
+    let mut query = connection.prepare("\
+        SELECT rowid, A, B, C, D FROM test_data WHERE rowid >= 5;\
+    ").unwrap();
+
+    let rows = query.query_map(params![], |row| Ok((row.get(0).unwrap(), Abcd {
+        alpha: row.get(1).unwrap(),
+        number: row.get(2).unwrap(),
+        word: row.get(3).unwrap(),
+        kind: row.get(4).unwrap(),
+    }))).unwrap();
+
+    let data = rows
+        .map(|r| r.unwrap())
+        .collect::<Vec<(i64, Abcd)>>();
+
+    std::fs::remove_file(&path).unwrap();
+
+    assert_eq!(data, vec![
+        (5, Abcd { alpha: "F".to_string(), number: 15.0, word: "fifteen".to_string(), kind: "odd".to_string() }),
+        (6, Abcd { alpha: "G".to_string(), number: 16.0, word: "sixteen".to_string(), kind: "even".to_string() }),
+        (7, Abcd { alpha: "H".to_string(), number: 17.0, word: "seventeen".to_string(), kind: "odd".to_string() }),
+    ]);
+}
+
+#[test]
+fn test_insert_is_rejected_for_bounded_range() {
+    let connection = init_connection();
+    connection.execute("\
+        CREATE VIRTUAL TABLE test_data USING xlite(\
+            FILENAME './tests/abcdef.xlsx',\
+            WORKSHEET 'Sheet1',\
+            RANGE 'B2:C5'\
+        );\
+    ", params![]).unwrap();
+
+    let result = connection.execute("\
+        INSERT INTO test_data VALUES (16, 'sixteen');\
+    ", params![]);
+
+    assert!(result.is_err());
+}
+
+#[test]
+fn test_update_and_delete_rewrite_file() {
+    let path = std::env::temp_dir().join("xlite_test_update_and_delete_rewrite_file.xlsx");
+    std::fs::copy("./tests/abcdef.xlsx", &path).unwrap();
+
+    let create = format!("\
+        CREATE VIRTUAL TABLE test_data USING xlite(\
+            FILENAME '{}',\
+            WORKSHEET 'Sheet1'\
+        );\
+    ", path.display());
+
+    let connection = init_connection();
+    connection.execute(create.as_str(), params![]).unwrap();
+    connection.execute("\
+        UPDATE test_data SET C = 'TEN' WHERE rowid = 0;\
+    ", params![]).unwrap();
+    connection.execute("\
+        DELETE FROM test_data WHERE D = 'odd';\
+    ", params![]).unwrap();
+    drop(connection);
+
+    let connection = init_connection();
+    connection.execute(create.as_str(), params![]).unwrap();
+
+    let mut query = connection.prepare("\
+        SELECT A, B, C, D FROM test_data;\
+    ").unwrap();
+
+    let rows = query.query_map(params![], |row| Ok(Abcd {
+        alpha: row.get(0).unwrap(),
+        number: row.get(1).unwrap(),
+        word: row.get(2).unwrap(),
+        kind: row.get(3).unwrap(),
+    })).unwrap();
+
+    let data = rows
+        .map(|r| r.unwrap())
+        .collect::<Vec<Abcd>>();
+
+    std::fs::remove_file(&path).unwrap();
+
+    assert_eq!(data, vec![
+        Abcd { alpha: "A".to_string(), number: 10.0, word: "TEN".to_string(), kind: "even".to_string() },
+        Abcd { alpha: "C".to_string(), number: 12.0, word: "twelve".to_string(), kind: "even".to_string() },
+        Abcd { alpha: "E".to_string(), number: 14.0, word: "fourteen".to_string(), kind: "even".to_string() },
+    ]);
+}
+
+#[test]
+fn test_snapshot_survives_file_removal() {
+    let path = std::env::temp_dir().join("xlite_test_snapshot_survives_file_removal.xlsx");
+    let db_path = std::env::temp_dir().join("xlite_test_snapshot_survives_file_removal.db");
+    std::fs::copy("./tests/abcdef.xlsx", &path).unwrap();
+    let _ = std::fs::remove_file(&db_path);
+
+    let connection = init_file_connection(&db_path);
+    connection.execute(format!("\
+        CREATE VIRTUAL TABLE test_data USING xlite(\
+            FILENAME '{}',\
+            WORKSHEET 'Sheet1',\
+            SNAPSHOT 'yes'\
+        );\
+    ", path.display()).as_str(), params![]).unwrap();
+    drop(connection);
+
+    std::fs::remove_file(&path).unwrap();
+
+    let connection = init_file_connection(&db_path);
+    let mut query = connection.prepare("\
+        SELECT A, B, C, D FROM test_data WHERE D = 'odd';\
+    ").unwrap();
+
+    let rows = query.query_map(params![], |row| Ok(Abcd {
+        alpha: row.get(0).unwrap(),
+        number: row.get(1).unwrap(),
+        word: row.get(2).unwrap(),
+        kind: row.get(3).unwrap(),
+    })).unwrap();
+
+    let data = rows
+        .map(|r| r.unwrap())
+        .collect::<Vec<Abcd>>();
+
+    let updated = connection.execute("\
+        UPDATE test_data SET C = 'TEN' WHERE rowid = 0;\
+    ", params![]);
+
+    drop(query);
+    drop(connection);
+    std::fs::remove_file(&db_path).unwrap();
+
+    assert!(updated.is_err());
+    assert_eq!(data, vec![
+        Abcd { alpha: "B".to_string(), number: 11.0, word: "eleven".to_string(), kind: "odd".to_string() },
+        Abcd { alpha: "D".to_string(), number: 13.0, word: "thirteen".to_string(), kind: "odd".to_string() },
+        Abcd { alpha: "F".to_string(), number: 15.0, word: "fifteen".to_string(), kind: "odd".to_string() },
+    ]);
+}
+
+#[test]
+fn test_table_snapshot_survives_file_removal() {
+    let path = std::env::temp_dir().join("xlite_test_table_snapshot_survives_file_removal.xlsx");
+    let db_path = std::env::temp_dir().join("xlite_test_table_snapshot_survives_file_removal.db");
+    std::fs::copy("./tests/tables.xlsx", &path).unwrap();
+    let _ = std::fs::remove_file(&db_path);
+
+    let connection = init_file_connection(&db_path);
+    connection.execute(format!("\
+        CREATE VIRTUAL TABLE sales USING xlite(\
+            FILENAME '{}',\
+            TABLE 'SalesTable',\
+            SNAPSHOT 'yes'\
+        );\
+    ", path.display()).as_str(), params![]).unwrap();
+    drop(connection);
+
+    std::fs::remove_file(&path).unwrap();
+
+    let connection = init_file_connection(&db_path);
+    let query = connection.prepare("SELECT * FROM sales;").unwrap();
+    let names = query.column_names().into_iter().map(String::from).collect::<Vec<String>>();
+    let (count, alphas, total): (i64, String, f64) = connection.query_row("\
+        SELECT count(*), group_concat(alpha, ''), sum(number) FROM sales;\
+    ", params![], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?))).unwrap();
+
+    drop(query);
+    drop(connection);
+    std::fs::remove_file(&db_path).unwrap();
+
+    assert_eq!(names, vec!["alpha", "number", "word", "kind"]);
+    assert_eq!((count, alphas, total), (5, "ABCDE".to_string(), 60.0));
+}
+
+#[test]
+fn test_stale_snapshot_is_reported() {
+    let path = std::env::temp_dir().join("xlite_test_stale_snapshot_is_reported.xlsx");
+    let db_path = std::env::temp_dir().join("xlite_test_stale_snapshot_is_reported.db");
+    std::fs::copy("./tests/abcdef.xlsx", &path).unwrap();
+    let _ = std::fs::remove_file(&db_path);
+    let warning = format!("is older than '{}'", path.display());
+
+    let connection = init_file_connection(&db_path);
+    connection.execute(format!("\
+        CREATE VIRTUAL TABLE test_data USING xlite(\
+            FILENAME '{}',\
+            WORKSHEET 'Sheet1',\
+            SNAPSHOT 'yes'\
+        );\
+    ", path.display()).as_str(), params![]).unwrap();
+    drop(connection);
+
+    let connection = init_file_connection(&db_path);
+    connection.query_row("SELECT count(*) FROM test_data;", params![], |row| row.get::<_, i64>(0)).unwrap();
+    drop(connection);
+    let fresh = logged(&warning);
+
+    std::fs::copy("./tests/tables.xlsx", &path).unwrap();
+    let connection = init_file_connection(&db_path);
+    let count: i64 = connection.query_row("SELECT count(*) FROM test_data;", params![], |row| row.get(0)).unwrap();
+    drop(connection);
+    let stale = logged(&warning);
+
+    let connection = init_file_connection(&db_path);
+    connection.execute("INSERT INTO test_data(test_data) VALUES ('refresh');", params![]).unwrap();
+    drop(connection);
+    let refreshing = logged(&warning);
+    let connection = init_file_connection(&db_path);
+    connection.query_row("SELECT count(*) FROM test_data;", params![], |row| row.get::<_, i64>(0)).unwrap();
+    drop(connection);
+    let refreshed = logged(&warning);
+
+    std::fs::remove_file(&path).unwrap();
+    std::fs::remove_file(&db_path).unwrap();
+
+    assert_eq!(fresh, 0);
+    assert_eq!(stale, 1);
+    assert_eq!(refreshed, refreshing);
+    assert_eq!(count, 6);
+}
+
+#[test]
+fn test_snapshot_refresh_and_drop() {
+    let path = std::env::temp_dir().join("xlite_test_snapshot_refresh_and_drop.xlsx");
+    std::fs::copy("./tests/abcdef.xlsx", &path).unwrap();
+
+    let connection = init_connection();
+    connection.execute(format!("\
+        CREATE VIRTUAL TABLE test_data USING xlite(\
+            FILENAME '{}',\
+            WORKSHEET 'Sheet1',\
+            SNAPSHOT 'yes'\
+        );\
+    ", path.display()).as_str(), params![]).unwrap();
+
+    std::fs::copy("./tests/abcdef_colnames.xlsx", &path).unwrap();
+
+    let before: String = connection.query_row("\
+        SELECT A FROM test_data LIMIT 1;\
+    ", params![], |row| row.get(0)).unwrap();
+
+    connection.execute("\
+        INSERT INTO test_data(test_data) VALUES ('refresh');\
+    ", params![]).unwrap();
+
+    let after: String = connection.query_row("\
+        SELECT A FROM test_data LIMIT 1;\
+    ", params![], |row| row.get(0)).unwrap();
+
+    let shadow_before: i64 = connection.query_row("\
+        SELECT count(*) FROM sqlite_master WHERE name LIKE 'test_data_%';\
+    ", params![], |row| row.get(0)).unwrap();
+
+    connection.execute("DROP TABLE test_data;", params![]).unwrap();
+
+    let shadow_after: i64 = connection.query_row("\
+        SELECT count(*) FROM sqlite_master WHERE name LIKE 'test_data_%';\
+    ", params![], |row| row.get(0)).unwrap();
+
+    std::fs::remove_file(&path).unwrap();
+
+    assert_eq!(before, "A");
+    assert_eq!(after, "alpha");
+    assert_eq!(shadow_before, 2);
+    assert_eq!(shadow_after, 0);
+}
+
+#[test]
+fn test_xlite_sheets_lists_worksheets() {
+    let connection = init_connection();
+
+    let sheet: (String, i64, String, String, i64, i64) = connection.query_row("\
+        SELECT name, position, visibility, dimension, rows, columns FROM xlite_sheets('./tests/abcdef.xlsx');\
+    ", params![], |row| Ok((
+        row.get(0).unwrap(),
+        row.get(1).unwrap(),
+        row.get(2).unwrap(),
+        row.get(3).unwrap(),
+        row.get(4).unwrap(),
+        row.get(5).unwrap(),
+    ))).unwrap();
+
+    let missing = connection.query_row("\
+        SELECT count(*) FROM xlite_sheets('./tests/missing.xlsx');\
+    ", params![], |row| row.get::<_, i64>(0));
+
+    assert_eq!(sheet, ("Sheet1".to_string(), 0, "visible".to_string(), "A1:D6".to_string(), 6, 4));
+    assert!(missing.is_err());
+}
+
+#[test]
+fn test_xlite_cells_lists_cells() {
+    let connection = init_connection();
+
+    let mut query = connection.prepare("\
+        SELECT row, column, address, value, type FROM xlite_cells('./tests/errors.xlsx', 'Sheet1', 'B3:C3');\
+    ").unwrap();
+    let cells = query
+        .query_map(params![], |row| Ok((
+            row.get(0)?,
+            row.get(1)?,
+            row.get(2)?,
+            row.get(3)?,
+            row.get(4)?,
+        )))
+        .unwrap()
+        .map(|r| r.unwrap())
+        .collect::<Vec<(i64, i64, String, String, String)>>();
+
+    let count: i64 = connection.query_row("\
+        SELECT count(*) FROM xlite_cells('./tests/abcdef.xlsx', 0) WHERE type = 'float';\
+    ", params![], |row| row.get(0)).unwrap();
+    let invalid = connection.query_row("\
+        SELECT count(*) FROM xlite_cells('./tests/abcdef.xlsx', 'Sheet1', 'A:5');\
+    ", params![], |row| row.get::<_, i64>(0));
+
+    assert_eq!(cells, vec![
+        (3, 2, "B3".to_string(), "#DIV/0!".to_string(), "error".to_string()),
+        (3, 3, "C3".to_string(), "twelve".to_string(), "string".to_string()),
+    ]);
+    assert_eq!(count, 6);
+    assert!(invalid.unwrap_err().to_string().contains("Invalid RANGE 'A:5'"));
+}
+
+#[test]
+fn test_xlite_table_function() {
+    let connection = init_connection();
+
+    let mut query = connection.prepare("\
+        SELECT B, C FROM xlite('./tests/abcdef.xlsx', 'Sheet1', 'B2:C4');\
+    ").unwrap();
+
+    let rows = query.query_map(params![], |row| Ok(Bc {
+        number: row.get(0).unwrap(),
+        word: row.get(1).unwrap(),
+    })).unwrap();
+
+    let data = rows
+        .map(|r| r.unwrap())
+        .collect::<Vec<Bc>>();
+
+    let count: i64 = connection.query_row("\
+        SELECT count(*) FROM xlite('./tests/abcdef_colnames.xlsx', 'Sheet1', NULL, 1) WHERE D = 'odd';\
+    ", params![], |row| row.get(0)).unwrap();
+
+    let missing = connection.query_row("\
+        SELECT count(*) FROM xlite('./tests/abcdef.xlsx');\
+    ", params![], |row| row.get::<_, i64>(0));
+
+    let wide = connection.query_row("\
+        SELECT count(*) FROM xlite('./tests/abcdef.xlsx', 'Sheet1', 'A1:AAA3');\
+    ", params![], |row| row.get::<_, i64>(0));
+
+    assert_eq!(data, vec![
+        Bc { number: 11.0, word: "eleven".to_string() },
+        Bc { number: 12.0, word: "twelve".to_string() },
+        Bc { number: 13.0, word: "thirteen".to_string() },
+    ]);
+    assert_eq!(count, 3);
+    assert!(missing.is_err());
+    assert!(wide.unwrap_err().to_string().contains("past the last column ZZ"));
+}
+
+#[test]
+fn test_column_types_are_declared() {
+    let connection = init_connection();
+    connection.execute("\
+        CREATE VIRTUAL TABLE test_data USING xlite(\
+            FILENAME './tests/abcdef_colnames.xlsx',\
+            WORKSHEET 'Sheet1',\
+            RANGE 'A2:D',\
+            COLNAMES '1'\
+        );\
+    ", params![]).unwrap();
+    connection.execute("\
+        CREATE VIRTUAL TABLE untyped_data USING xlite(\
+            FILENAME './tests/abcdef.xlsx',\
+            WORKSHEET 'Sheet1',\
+            SAMPLE '0'\
+        );\
+    ", params![]).unwrap();
+
+    let read_columns = |table: &str| {
+        let mut query = connection.prepare(format!("SELECT name, type FROM pragma_table_info('{}');", table).as_str()).unwrap();
+        let rows = query.query_map(params![], |row| Ok((row.get(0).unwrap(), row.get(1).unwrap()))).unwrap();
+        rows.map(|r| r.unwrap()).collect::<Vec<(String, String)>>()
+    };
+
+    assert_eq!(read_columns("test_data"), vec![
+        ("alpha".to_string(), "TEXT".to_string()),
+        ("number".to_string(), "INTEGER".to_string()),
+        ("word".to_string(), "TEXT".to_string()),
+        ("kind".to_string(), "TEXT".to_string()),
+    ]);
+    assert_eq!(read_columns("untyped_data"), vec![
+        ("A".to_string(), "".to_string()),
+        ("B".to_string(), "".to_string()),
+        ("C".to_string(), "".to_string()),
+        ("D".to_string(), "".to_string()),
+    ]);
+}
+
+#[test]
+fn test_schema_declares_and_converts_columns() {
+    let connection = init_connection();
+    connection.execute("\
+        CREATE VIRTUAL TABLE test_data USING xlite(\
+            FILENAME './tests/abcdef.xlsx',\
+            WORKSHEET 'Sheet1',\
+            SCHEMA 'CREATE TABLE x(letter TEXT, number TEXT, word INTEGER)'\
+        );\
+    ", params![]).unwrap();
+
+    let mut query = connection.prepare("\
+        SELECT name, type FROM pragma_table_info('test_data');\
+    ").unwrap();
+    let columns = query
+        .query_map(params![], |row| Ok((row.get(0).unwrap(), row.get(1).unwrap())))
+        .unwrap()
+        .map(|r| r.unwrap())
+        .collect::<Vec<(String, String)>>();
+
+    let row: (String, String, Option<i64>) = connection.query_row("\
+        SELECT letter, typeof(number), word FROM test_data WHERE number = '12';\
+    ", params![], |row| Ok((row.get(0).unwrap(), row.get(1).unwrap(), row.get(2).unwrap()))).unwrap();
+
+    assert_eq!(columns, vec![
+        ("letter".to_string(), "TEXT".to_string()),
+        ("number".to_string(), "TEXT".to_string()),
+        ("word".to_string(), "INTEGER".to_string()),
+    ]);
+    assert_eq!(row, ("C".to_string(), "text".to_string(), None));
+}
+
+#[test]
+fn test_types_with_failing_conversion() {
+    let connection = init_connection();
+    connection.execute("\
+        CREATE VIRTUAL TABLE test_data USING xlite(\
+            FILENAME './tests/abcdef.xlsx',\
+            WORKSHEET 'Sheet1',\
+            TYPES ',REAL,INTEGER',\
+            TYPE_ERRORS 'fail'\
+        );\
+    ", params![]).unwrap();
+
+    let number: f64 = connection.query_row("\
+        SELECT B FROM test_data WHERE A = 'B';\
+    ", params![], |row| row.get(0)).unwrap();
+
+    let result = connection.query_row("\
+        SELECT C FROM test_data WHERE A = 'B';\
+    ", params![], |row| row.get::<_, Option<i64>>(0));
+
+    assert_eq!(number, 11.0);
+    assert!(result.unwrap_err().to_string().contains("Value 'eleven' in Sheet1!C2 can't be converted to INTEGER"));
+}
+
+#[test]
+fn test_dates_are_formatted() {
+    let connection = init_connection();
+    for (name, format) in [("iso_data", "iso"), ("unix_data", "unix"), ("serial_data", "serial")] {
+        connection.execute(format!("\
+            CREATE VIRTUAL TABLE {} USING xlite(\
+                FILENAME './tests/abcdef.xlsx',\
+                WORKSHEET 'Sheet1',\
+                TYPES ',DATETIME',\
+                DATES '{}'\
+            );\
+        ", name, format).as_str(), params![]).unwrap();
+    }
+
+    let iso: (String, String) = connection.query_row("\
+        SELECT B, date(B, '+1 day') FROM iso_data WHERE B = '1900-01-10';\
+    ", params![], |row| Ok((row.get(0).unwrap(), row.get(1).unwrap()))).unwrap();
+
+    let unix: i64 = connection.query_row("\
+        SELECT B FROM unix_data WHERE A = 'A';\
+    ", params![], |row| row.get(0)).unwrap();
+
+    let serial: f64 = connection.query_row("\
+        SELECT B FROM serial_data WHERE A = 'A';\
+    ", params![], |row| row.get(0)).unwrap();
+
+    assert_eq!(iso, ("1900-01-10".to_string(), "1900-01-11".to_string()));
+    assert_eq!(unix, -2208211200);
+    assert_eq!(serial, 10.0);
+}
+
+#[test]
+fn test_cell_errors_policies() {
+    let connection = init_connection();
+    for (name, policy) in [("null_data", "null"), ("text_data", "text"), ("fail_data", "fail")] {
+        connection.execute(format!("\
+            CREATE VIRTUAL TABLE {} USING xlite(\
+                FILENAME './tests/errors.xlsx',\
+                WORKSHEET 'Sheet1',\
+                ERRORS '{}'\
+            );\
+        ", name, policy).as_str(), params![]).unwrap();
+    }
+
+    let nulls: i64 = connection.query_row("\
+        SELECT count(*) FROM null_data WHERE B IS NULL;\
+    ", params![], |row| row.get(0)).unwrap();
+
+    let mut query = connection.prepare("\
+        SELECT A, B FROM text_data WHERE B LIKE '#%';\
+    ").unwrap();
+    let errors = query
+        .query_map(params![], |row| Ok((row.get(0).unwrap(), row.get(1).unwrap())))
+        .unwrap()
+        .map(|r| r.unwrap())
+        .collect::<Vec<(String, String)>>();
+
+    let failed = connection.query_row("\
+        SELECT sum(B) FROM fail_data;\
+    ", params![], |row| row.get::<_, f64>(0));
+
+    assert_eq!(nulls, 2);
+    assert_eq!(errors, vec![
+        ("C".to_string(), "#DIV/0!".to_string()),
+        ("E".to_string(), "#N/A".to_string()),
+    ]);
+    assert!(failed.unwrap_err().to_string().contains("Cell Sheet1!B3 contains the error #DIV/0!"));
+}
+
+#[test]
+fn test_column_names_sanitized() {
+    let connection = init_connection();
+    connection.execute("\
+        CREATE VIRTUAL TABLE raw_names USING xlite(\
+            FILENAME './tests/headers.xlsx',\
+            WORKSHEET 'Sheet1',\
+            RANGE 'A1:D6',\
+            COLNAMES '1'\
+        );\
+    ", params![]).unwrap();
+    connection.execute("\
+        CREATE VIRTUAL TABLE snake_names USING xlite(\
+            FILENAME './tests/headers.xlsx',\
+            WORKSHEET 'Sheet1',\
+            RANGE 'A1:D6',\
+            COLNAMES '1',\
+            NORMALIZE 'snake'\
+        );\
+    ", params![]).unwrap();
+
+    let names = |table: &str| {
+        let query = connection.prepare(format!("SELECT * FROM {};", table).as_str()).unwrap();
+        query.column_names().into_iter().map(String::from).collect::<Vec<String>>()
+    };
+    let price: String = connection.query_row("\
+        SELECT `Unit ``Price``` FROM raw_names WHERE Total = 'B';\
+    ", params![], |row| row.get(0)).unwrap();
+
+    assert_eq!(names("raw_names"), vec!["Total", "total_2", "C", "Unit `Price`"]);
+    assert_eq!(names("snake_names"), vec!["total", "total_2", "C", "unit_price"]);
+    assert_eq!(price, "odd");
+}
+
+#[test]
+fn test_header_rows_excluded_from_data() {
+    let connection = init_connection();
+    connection.execute("\
+        CREATE VIRTUAL TABLE test_data USING xlite(\
+            FILENAME './tests/abcdef_colnames.xlsx',\
+            WORKSHEET 'Sheet1',\
+            HEADER '1'\
+        );\
+    ", params![]).unwrap();
+    connection.execute("\
+        CREATE VIRTUAL TABLE two_rows USING xlite(\
+            FILENAME './tests/abcdef.xlsx',\
+            WORKSHEET 'Sheet1',\
+            RANGE 'A1:D',\
+            HEADER '2',\
+            HEADER_SEPARATOR ' / '\
+        );\
+    ", params![]).unwrap();
+
+    let mut query = connection.prepare("\
+        SELECT alpha, word FROM test_data;\
+    ").unwrap();
+    let data = query
+        .query_map(params![], |row| Ok((row.get(0).unwrap(), row.get(1).unwrap())))
+        .unwrap()
+        .map(|r| r.unwrap())
+        .collect::<Vec<(String, String)>>();
+
+    let query = connection.prepare("SELECT * FROM two_rows;").unwrap();
+    let names = query.column_names().into_iter().map(String::from).collect::<Vec<String>>();
+    let (count, first): (i64, String) = connection.query_row("\
+        SELECT count(*), min(`A / B`) FROM two_rows;\
+    ", params![], |row| Ok((row.get(0)?, row.get(1)?))).unwrap();
+
+    let combined = connection.execute("\
+        CREATE VIRTUAL TABLE combined USING xlite(\
+            FILENAME './tests/abcdef_colnames.xlsx',\
+            WORKSHEET 'Sheet1',\
+            COLNAMES '1',\
+            HEADER '1'\
+        );\
+    ", params![]);
+
+    assert_eq!(data.len(), 6);
+    assert_eq!(data[0], ("A".to_string(), "ten".to_string()));
+    assert_eq!(names, vec!["A / B", "10 / 11", "ten / eleven", "even / odd"]);
+    assert_eq!((count, first), (4, "C".to_string()));
+    assert!(combined.unwrap_err().to_string().contains("COLNAMES and HEADER can't be combined"));
+}
+
+#[test]
+fn test_header_only_table() {
+    let connection = init_connection();
+    connection.execute("\
+        CREATE VIRTUAL TABLE header_only USING xlite(\
+            FILENAME './tests/abcdef_colnames.xlsx',\
+            WORKSHEET 'Sheet1',\
+            RANGE 'A1:F1',\
+            HEADER '1'\
+        );\
+    ", params![]).unwrap();
+
+    let query = connection.prepare("SELECT * FROM header_only;").unwrap();
+    let names = query.column_names().into_iter().map(String::from).collect::<Vec<String>>();
+    let count: i64 = connection.query_row("SELECT count(*) FROM header_only;", params![], |row| row.get(0)).unwrap();
+
+    assert_eq!(names, vec!["alpha", "number", "word", "kind", "E", "F"]);
+    assert_eq!(count, 0);
+}
+
+#[test]
+fn test_range_references() {
+    let connection = init_connection();
+    let create = |name: &str, options: &str| connection.execute(format!("\
+        CREATE VIRTUAL TABLE {} USING xlite(\
+            FILENAME './tests/abcdef.xlsx',\
+            {}\
+        );\
+    ", name, options).as_str(), params![]);
+
+    create("absolute", "WORKSHEET 'Sheet1', RANGE '$a$2:$c$4'").unwrap();
+    create("qualified", "RANGE '''Sheet1''!A5:'").unwrap();
+    create("columns", "WORKSHEET 'Sheet1', RANGE 'B:C'").unwrap();
+    let invalid = create("invalid", "WORKSHEET 'Sheet1', RANGE 'A1-C4'");
+    let other_sheet = create("other_sheet", "WORKSHEET 'Sheet1', RANGE 'Sheet2!A1:C4'");
+
+    let absolute: (i64, String) = connection.query_row("\
+        SELECT count(*), group_concat(A, '') FROM absolute;\
+    ", params![], |row| Ok((row.get(0)?, row.get(1)?))).unwrap();
+    let qualified: (i64, String, String) = connection.query_row("\
+        SELECT count(*), group_concat(A, ''), group_concat(D, ',') FROM qualified;\
+    ", params![], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?))).unwrap();
+    let query = connection.prepare("SELECT * FROM columns;").unwrap();
+    let names = query.column_names().into_iter().map(String::from).collect::<Vec<String>>();
+
+    assert_eq!(absolute, (3, "BCD".to_string()));
+    assert_eq!(qualified, (2, "EF".to_string(), "even,odd".to_string()));
+    assert_eq!(names, vec!["B", "C"]);
+    assert!(invalid.unwrap_err().to_string().contains("Invalid RANGE 'A1-C4'"));
+    assert!(other_sheet.unwrap_err().to_string().contains("refers to another worksheet"));
+}
+
+#[test]
+fn test_table_without_options_needs_a_filename() {
+    let connection = init_connection();
+
+    let result = connection.execute("CREATE VIRTUAL TABLE test_data USING xlite;", params![]);
+
+    assert!(result.unwrap_err().to_string().contains("Filename is not provided"));
+}
+
+#[test]
+fn test_invalid_option_values() {
+    let connection = init_connection();
+
+    let create = |name: &str, options: &str| connection.execute(format!("\
+        CREATE VIRTUAL TABLE {} USING xlite(\
+            FILENAME './tests/abcdef.xlsx',\
+            WORKSHEET 'Sheet1',\
+            {}\
+        );\
+    ", name, options).as_str(), params![]);
+
+    for (options, expected) in [
+        ("MERGED 'fil'", "Invalid MERGED 'fil'"),
+        ("DATES 'iso8601'", "Invalid DATES 'iso8601'"),
+        ("TYPE_ERRORS 'ignore'", "Invalid TYPE_ERRORS 'ignore'"),
+        ("errors 'none'", "Invalid ERRORS 'none'"),
+        ("NORMALIZE 'upper'", "Invalid NORMALIZE 'upper'"),
+        ("FILE_ERRORS 'warn'", "Invalid FILE_ERRORS 'warn'"),
+        ("FORMULAS 'maybe'", "Invalid FORMULAS 'maybe'"),
+        ("COLNAMES '99999999999'", "Invalid COLNAMES '99999999999'"),
+        ("HEADER '99999999999'", "Invalid HEADER '99999999999'"),
+        ("SAMPLE '99999999999999999999999'", "Invalid SAMPLE '99999999999999999999999'"),
+        ("SHEET_INDEX '99999999999999999999999'", "Invalid SHEET_INDEX '99999999999999999999999'"),
+    ] {
+        let result = create("invalid", options);
+
+        assert!(result.unwrap_err().to_string().contains(expected), "{}", options);
+    }
+    create("valid", "MERGED 'keep', DATES 'ISO'").unwrap();
+}
+
+#[test]
+fn test_defined_names() {
+    let connection = init_connection();
+    let create = |name: &str, defined: &str| connection.execute(format!("\
+        CREATE VIRTUAL TABLE {} USING xlite(\
+            FILENAME './tests/names.xlsx',\
+            NAME '{}'\
+        );\
+    ", name, defined).as_str(), params![]);
+
+    create("middle", "middle").unwrap();
+    create("words", "Words").unwrap();
+    let formula = create("latest", "Latest");
+    let areas = create("corners", "Corners");
+    let missing = create("missing", "Q3_Actuals");
+
+    let middle: (i64, String) = connection.query_row("\
+        SELECT count(*), group_concat(A, '') FROM middle;\
+    ", params![], |row| Ok((row.get(0)?, row.get(1)?))).unwrap();
+    let words: (i64, String) = connection.query_row("\
+        SELECT count(*), max(C) FROM words;\
+    ", params![], |row| Ok((row.get(0)?, row.get(1)?))).unwrap();
+
+    assert_eq!(middle, (3, "BCD".to_string()));
+    assert_eq!(words, (6, "twelve".to_string()));
+    assert!(formula.unwrap_err().to_string().contains("Name 'Latest' refers to a formula"));
+    assert!(areas.unwrap_err().to_string().contains("Name 'Corners' refers to more than one area"));
+    assert!(missing.unwrap_err().to_string().contains("Name 'Q3_Actuals' not found"));
+}
+
+#[test]
+fn test_defined_name_follows_range() {
+    let path = std::env::temp_dir().join("xlite_test_defined_name_follows_range.xlsx");
+    std::fs::copy("./tests/names.xlsx", &path).unwrap();
+
+    let connection = init_connection();
+    connection.execute(format!("\
+        CREATE VIRTUAL TABLE middle USING xlite(\
+            FILENAME '{}',\
+            NAME 'Middle'\
+        );\
+    ", path.display()).as_str(), params![]).unwrap();
+
+    let query = "SELECT count(*), group_concat(A, '') FROM middle;";
+    let before: (i64, String) = connection.query_row(query, params![], |row| Ok((row.get(0)?, row.get(1)?))).unwrap();
+
+    // the name is moved to A3:D6 in the new version of the workbook
+    std::fs::copy("./tests/names_moved.xlsx", &path).unwrap();
+    let after: (i64, String) = connection.query_row(query, params![], |row| Ok((row.get(0)?, row.get(1)?))).unwrap();
+
+    std::fs::remove_file(&path).unwrap();
+
+    assert_eq!(before, (3, "BCD".to_string()));
+    assert_eq!(after, (4, "CDEF".to_string()));
+}
+
+#[test]
+fn test_excel_tables() {
+    let connection = init_connection();
+    connection.execute("\
+        CREATE VIRTUAL TABLE sales USING xlite(\
+            FILENAME './tests/tables.xlsx',\
+            TABLE 'salestable'\
+        );\
+    ", params![]).unwrap();
+    let missing = connection.execute("\
+        CREATE VIRTUAL TABLE missing USING xlite(\
+            FILENAME './tests/tables.xlsx',\
+            TABLE 'Q3_Actuals'\
+        );\
+    ", params![]);
+
+    let query = connection.prepare("SELECT * FROM sales;").unwrap();
+    let names = query.column_names().into_iter().map(String::from).collect::<Vec<String>>();
+    let (count, alphas, total): (i64, String, f64) = connection.query_row("\
+        SELECT count(*), group_concat(alpha, ''), sum(number) FROM sales;\
+    ", params![], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?))).unwrap();
+
+    assert_eq!(names, vec!["alpha", "number", "word", "kind"]);
+    assert_eq!((count, alphas, total), (5, "ABCDE".to_string(), 60.0));
+    assert!(missing.unwrap_err().to_string().contains("Table 'Q3_Actuals' not found, the workbook defines: SalesTable"));
+}
+
+#[test]
+fn test_worksheet_selection() {
+    let connection = init_connection();
+    let create = |name: &str, options: &str| connection.execute(format!("\
+        CREATE VIRTUAL TABLE {} USING xlite(\
+            FILENAME './tests/abcdef.xlsx',\
+            {}\
+        );\
+    ", name, options).as_str(), params![]);
+
+    create("lowercase", "WORKSHEET 'sheet1'").unwrap();
+    create("by_index", "SHEET_INDEX '0'").unwrap();
+    create("by_pattern", "WORKSHEET 'Sh*'").unwrap();
+    let typo = create("typo", "WORKSHEET 'Shet1'");
+    let out_of_range = create("out_of_range", "SHEET_INDEX '1'");
+
+    let count = |table: &str| connection.query_row(
+        format!("SELECT count(*) FROM {};", table).as_str(),
+        params![],
+        |row| row.get::<_, i64>(0),
+    ).unwrap();
+    let function: i64 = connection.query_row("\
+        SELECT count(*) FROM xlite('./tests/abcdef.xlsx', 0);\
+    ", params![], |row| row.get(0)).unwrap();
+
+    assert_eq!((count("lowercase"), count("by_index"), count("by_pattern"), function), (6, 6, 6, 6));
+    assert!(typo.unwrap_err().to_string().contains(
+        "Worksheet 'Shet1' not found, the workbook has: Sheet1. Did you mean 'Sheet1'?"
+    ));
+    assert!(out_of_range.unwrap_err().to_string().contains("Worksheet index 1 is out of range"));
+}
+
+#[test]
+fn test_worksheets_union() {
+    let connection = init_connection();
+    connection.execute("\
+        CREATE VIRTUAL TABLE months USING xlite(\
+            FILENAME './tests/months.xlsx',\
+            WORKSHEETS '2024-*',\
+            HEADER '1'\
+        );\
+    ", params![]).unwrap();
+    let combined = connection.execute("\
+        CREATE VIRTUAL TABLE combined USING xlite(\
+            FILENAME './tests/months.xlsx',\
+            WORKSHEETS '2024-*',\
+            WORKSHEET 'Summary'\
+        );\
+    ", params![]);
+    let unknown = connection.execute("\
+        CREATE VIRTUAL TABLE unknown USING xlite(\
+            FILENAME './tests/months.xlsx',\
+            WORKSHEETS '2023-*'\
+        );\
+    ", params![]);
+
+    let mut statement = connection.prepare("SELECT rowid, item, qty, _sheet FROM months;").unwrap();
+    let rows: Vec<(i64, String, f64, String)> = statement
+        .query_map(params![], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)))
+        .unwrap()
+        .map(|row| row.unwrap())
+        .collect();
+    let columns: i64 = connection.query_row("\
+        SELECT count(*) FROM pragma_table_info('months');\
+    ", params![], |row| row.get(0)).unwrap();
+    let feb: f64 = connection.query_row("\
+        SELECT sum(qty) FROM months WHERE _sheet = '2024-Feb';\
+    ", params![], |row| row.get(0)).unwrap();
+    let insert = connection.execute("INSERT INTO months VALUES ('fig', 1);", params![]);
+
+    assert_eq!(rows, vec![
+        (0, "apple".to_string(), 3.0, "2024-Jan".to_string()),
+        (1, "pear".to_string(), 5.0, "2024-Jan".to_string()),
+        (2, "plum".to_string(), 7.0, "2024-Feb".to_string()),
+    ]);
+    assert_eq!((columns, feb), (2, 7.0));
+    assert!(insert.unwrap_err().to_string().contains("Tables over several worksheets or files are read-only"));
+    assert!(combined.unwrap_err().to_string().contains("WORKSHEETS can't be combined"));
+    assert!(unknown.unwrap_err().to_string().contains("WORKSHEETS '2023-*' matches no worksheet"));
+}
+
+#[test]
+fn test_file_patterns() {
+    let connection = init_connection();
+    let create = |name: &str, options: &str| connection.execute(format!("\
+        CREATE VIRTUAL TABLE {} USING xlite(\
+            {},\
+            WORKSHEET 'Report',\
+            HEADER '1'\
+        );\
+    ", name, options).as_str(), params![]);
+
+    create("reports", "FILENAME './tests/reports', FILE_ERRORS 'skip'").unwrap();
+    create("glob", "FILENAME './tests/reports/WEEK?.xlsx', FILE_ERRORS 'skip'").unwrap();
+    let failing = create("failing", "FILENAME './tests/reports/*.xlsx'");
+    let no_files = create("no_files", "FILENAME './tests/reports/*.ods'");
+
+    let mut statement = connection.prepare("SELECT rowid, store, sales, _file FROM reports;").unwrap();
+    let rows: Vec<(i64, String, f64, String)> = statement
+        .query_map(params![], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)))
+        .unwrap()
+        .map(|row| row.unwrap())
+        .collect();
+    let glob: f64 = connection.query_row("\
+        SELECT sum(sales) FROM glob WHERE _file LIKE '%week1.xlsx';\
+    ", params![], |row| row.get(0)).unwrap();
+
+    assert_eq!(rows, vec![
+        (0, "north".to_string(), 10.0, "./tests/reports/week1.xlsx".to_string()),
+        (1, "south".to_string(), 20.0, "./tests/reports/week1.xlsx".to_string()),
+        (2, "north".to_string(), 30.0, "./tests/reports/week2.xlsx".to_string()),
+    ]);
+    assert_eq!(glob, 30.0);
+    assert!(failing.unwrap_err().to_string().contains("Can't read './tests/reports/week3.xlsx'"));
+    assert!(no_files.unwrap_err().to_string().contains("matches no readable file"));
+}
+
+#[test]
+fn test_provenance_columns() {
+    let connection = init_connection();
+    connection.execute("\
+        CREATE VIRTUAL TABLE test_data USING xlite(\
+            FILENAME './tests/abcdef_colnames.xlsx',\
+            WORKSHEET 'Sheet1',\
+            HEADER '1',\
+            PROVENANCE 'yes'\
+        );\
+    ", params![]).unwrap();
+    connection.execute("\
+        CREATE VIRTUAL TABLE months USING xlite(\
+            FILENAME './tests/months.xlsx',\
+            WORKSHEETS '2024-*',\
+            HEADER '1',\
+            PROVENANCE 'yes'\
+        );\
+    ", params![]).unwrap();
+
+    let eleven: (i64, String, String, String, String) = connection.query_row("\
+        SELECT _row, _address, _sheet, _file, alpha FROM test_data WHERE word = 'eleven';\
+    ", params![], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?, row.get(4)?))).unwrap();
+    let fourth: String = connection.query_row("\
+        SELECT alpha FROM test_data WHERE _row = 4;\
+    ", params![], |row| row.get(0)).unwrap();
+    let plum: (i64, String, String) = connection.query_row("\
+        SELECT _row, _address, _file FROM months WHERE item = 'plum';\
+    ", params![], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?))).unwrap();
+    let query = connection.prepare("SELECT * FROM test_data;").unwrap();
+
+    assert_eq!(eleven, (
+        3,
+        "Sheet1!A3:D3".to_string(),
+        "Sheet1".to_string(),
+        "./tests/abcdef_colnames.xlsx".to_string(),
+        "B".to_string(),
+    ));
+    assert_eq!(fourth, "C");
+    assert_eq!(plum, (2, "'2024-Feb'!A2:B2".to_string(), "./tests/months.xlsx".to_string()));
+    assert_eq!(query.column_count(), 4);
+}
+
+#[test]
+fn test_data_columns_leave_hidden_names_free() {
+    let connection = init_connection();
+    connection.execute("\
+        CREATE VIRTUAL TABLE test_data USING xlite(\
+            FILENAME './tests/abcdef_colnames.xlsx',\
+            WORKSHEET 'Sheet1',\
+            RANGE 'A2:D7',\
+            SCHEMA 'CREATE TABLE x(_row TEXT, number INTEGER, word TEXT, kind TEXT)',\
+            PROVENANCE 'yes'\
+        );\
+    ", params![]).unwrap();
+
+    let eleven: (String, i64) = connection.query_row("\
+        SELECT _row_2, _row FROM test_data WHERE word = 'eleven';\
+    ", params![], |row| Ok((row.get(0)?, row.get(1)?))).unwrap();
+
+    assert_eq!(eleven, ("B".to_string(), 3));
+}
+
+#[test]
+fn test_formulas_columns() {
+    let connection = init_connection();
+    connection.execute("\
+        CREATE VIRTUAL TABLE totals USING xlite(\
+            FILENAME './tests/formulas.xlsx',\
+            WORKSHEET 'Sheet1',\
+            HEADER '1',\
+            FORMULAS 'yes'\
+        );\
+    ", params![]).unwrap();
+
+    let mut query = connection.prepare("SELECT item, total, total_formula FROM totals;").unwrap();
+    let rows = query
+        .query_map(params![], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))
+        .unwrap()
+        .map(|r| r.unwrap())
+        .collect::<Vec<(String, f64, Option<String>)>>();
+    let hard_coded: String = connection.query_row("\
+        SELECT item FROM totals WHERE total_formula IS NULL;\
+    ", params![], |row| row.get(0)).unwrap();
+    let cell: String = connection.query_row("\
+        SELECT formula FROM xlite_cells('./tests/formulas.xlsx', 'Sheet1', 'D4');\
+    ", params![], |row| row.get(0)).unwrap();
+
+    assert_eq!(rows, vec![
+        ("apple".to_string(), 6.0, Some("=B2*C2".to_string())),
+        ("pear".to_string(), 20.0, None),
+        ("plum".to_string(), 7.0, Some("=B4*C4".to_string())),
+    ]);
+    assert_eq!(hard_coded, "pear");
+    assert_eq!(cell, "=B4*C4");
+}
+
+#[test]
+fn test_shared_formulas() {
+    let connection = init_connection();
+    connection.execute("\
+        CREATE VIRTUAL TABLE totals USING xlite(\
+            FILENAME './tests/shared_formulas.xlsx',\
+            WORKSHEET 'Sheet1',\
+            HEADER '1',\
+            FORMULAS 'yes'\
+        );\
+    ", params![]).unwrap();
+
+    let mut query = connection.prepare("SELECT item, total_formula FROM totals;").unwrap();
+    let rows = query
+        .query_map(params![], |row| Ok((row.get(0)?, row.get(1)?)))
+        .unwrap()
+        .map(|r| r.unwrap())
+        .collect::<Vec<(String, Option<String>)>>();
+    let cell: String = connection.query_row("\
+        SELECT formula FROM xlite_cells('./tests/shared_formulas.xlsx', 'Sheet1', 'D3');\
+    ", params![], |row| row.get(0)).unwrap();
+    let snapshot = connection.execute("\
+        CREATE VIRTUAL TABLE frozen USING xlite(\
+            FILENAME './tests/shared_formulas.xlsx',\
+            WORKSHEET 'Sheet1',\
+            HEADER '1',\
+            FORMULAS 'yes',\
+            SNAPSHOT 'yes'\
+        );\
+    ", params![]);
+
+    assert_eq!(rows, vec![
+        ("apple".to_string(), Some("=B2*C2".to_string())),
+        ("pear".to_string(), Some("=B3*C3".to_string())),
+        ("plum".to_string(), Some("=B4*C4".to_string())),
+    ]);
+    assert_eq!(cell, "=B3*C3");
+    assert!(snapshot.unwrap_err().to_string().contains("FORMULAS can't be combined with SNAPSHOT"));
+}
+
+#[test]
+fn test_merged_and_filldown() {
+    let connection = init_connection();
+    connection.execute("\
+        CREATE VIRTUAL TABLE sales USING xlite(\
+            FILENAME './tests/merged.xlsx',\
+            WORKSHEET 'Sheet1',\
+            HEADER '1',\
+            MERGED 'fill',\
+            FILLDOWN 'D'\
+        );\
+    ", params![]).unwrap();
+    connection.execute("\
+        CREATE VIRTUAL TABLE raw_sales USING xlite(\
+            FILENAME './tests/merged.xlsx',\
+            WORKSHEET 'Sheet1',\
+            HEADER '1'\
+        );\
+    ", params![]).unwrap();
+
+    let mut query = connection.prepare("SELECT region, count(*) FROM sales GROUP BY region ORDER BY region;").unwrap();
+    let regions = query
+        .query_map(params![], |row| Ok((row.get(0)?, row.get(1)?)))
+        .unwrap()
+        .map(|r| r.unwrap())
+        .collect::<Vec<(String, i64)>>();
+    let mut query = connection.prepare("SELECT manager FROM sales;").unwrap();
+    let managers = query
+        .query_map(params![], |row| row.get(0))
+        .unwrap()
+        .map(|r| r.unwrap())
+        .collect::<Vec<String>>();
+    let blanks: i64 = connection.query_row("\
+        SELECT count(*) FROM raw_sales WHERE region IS NULL;\
+    ", params![], |row| row.get(0)).unwrap();
+    let update = connection.execute("UPDATE sales SET sales = 0;", params![]);
+
+    assert_eq!(regions, vec![("North".to_string(), 3), ("South".to_string(), 2)]);
+    assert_eq!(managers, vec!["Ann", "Ann", "Ann", "Bob", "Bob"]);
+    assert_eq!(blanks, 3);
+    assert!(update.is_err());
+}
+
+#[test]
+fn test_blank_rows_and_columns() {
+    let connection = init_connection();
+    for (table, options) in [
+        ("trimmed", "TRIM 'yes'"),
+        ("compact", "TRIM 'yes', SKIP_EMPTY 'yes'"),
+        ("first_block", "UNTIL_BLANK 'yes'"),
+    ] {
+        connection.execute(format!("\
+            CREATE VIRTUAL TABLE {} USING xlite(\
+                FILENAME './tests/blanks.xlsx',\
+                WORKSHEET 'Sheet1',\
+                HEADER '1',\
+                {}\
+            );\
+        ", table, options).as_str(), params![]).unwrap();
+    }
+
+    let count = |sql: &str| -> i64 { connection.query_row(sql, params![], |row| row.get(0)).unwrap() };
+
+    assert_eq!(count("SELECT count(*) FROM pragma_table_info('trimmed');"), 2);
+    assert_eq!(count("SELECT count(*) FROM trimmed;"), 6);
+    assert_eq!(count("SELECT count(*) FROM compact;"), 4);
+    assert_eq!(count("SELECT count(*) FROM compact WHERE name IS NULL;"), 0);
+    assert_eq!(count("SELECT count(*) FROM first_block;"), 2);
+    assert_eq!(count("SELECT count(*) FROM first_block WHERE qty > 0;"), 2);
+}
+
+#[test]
+fn test_auto_detects_range_and_header() {
+    let connection = init_connection();
+    connection.execute("\
+        CREATE VIRTUAL TABLE sales USING xlite(\
+            FILENAME './tests/report.xlsx',\
+            WORKSHEET 'Sales',\
+            AUTO 'yes'\
+        );\
+    ", params![]).unwrap();
+
+    let mut query = connection.prepare("SELECT name FROM pragma_table_info('sales');").unwrap();
+    let columns = query
+        .query_map(params![], |row| row.get(0))
+        .unwrap()
+        .map(|r| r.unwrap())
+        .collect::<Vec<String>>();
+    let total: f64 = connection.query_row("SELECT sum(sales) FROM sales;", params![], |row| row.get(0)).unwrap();
+    let detected = connection.query_row("\
+        SELECT range, header, rows, options FROM xlite_detect('./tests/report.xlsx', 'Sales');\
+    ", params![], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?))).unwrap();
+    let combined = connection.execute("\
+        CREATE VIRTUAL TABLE ranged USING xlite(\
+            FILENAME './tests/report.xlsx',\
+            WORKSHEET 'Sales',\
+            RANGE 'B3:D7',\
+            AUTO 'yes'\
+        );\
+    ", params![]);
+
+    assert_eq!(columns, vec!["store", "month", "sales"]);
+    assert_eq!(total, 100.0);
+    assert_eq!(detected, (
+        "B3:D7".to_string(),
+        1,
+        4,
+        "RANGE 'B3:D7', HEADER '1'".to_string(),
+    ));
+    assert!(combined.unwrap_err().to_string().contains("AUTO can't be combined"));
+}
+
+#[test]
+fn test_auto_table_grows_with_inserted_rows() {
+    let path = std::env::temp_dir().join("xlite_test_auto_table_grows_with_inserted_rows.xlsx");
+    std::fs::copy("./tests/report.xlsx", &path).unwrap();
+
+    let connection = init_connection();
+    connection.execute(format!("\
+        CREATE VIRTUAL TABLE sales USING xlite(\
+            FILENAME '{}',\
+            WORKSHEET 'Sales',\
+            AUTO 'yes'\
+        );\
+    ", path.display()).as_str(), params![]).unwrap();
+    connection.execute("INSERT INTO sales VALUES ('east', 'Mar', 50);", params![]).unwrap();
+
+    let (count, total): (i64, f64) = connection
+        .query_row("SELECT count(*), sum(sales) FROM sales;", params![], |row| Ok((row.get(0)?, row.get(1)?)))
+        .unwrap();
+    let east: String = connection
+        .query_row(format!("\
+            SELECT address FROM xlite_cells('{}', 'Sales') WHERE value = 'east';\
+        ", path.display()).as_str(), params![], |row| row.get(0))
+        .unwrap();
+
+    assert_eq!((count, total), (5, 150.0));
+    assert_eq!(east, "B8");
+
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn test_writes_keep_formats_and_formulas() {
+    let path = std::env::temp_dir().join("xlite_test_writes_keep_formats_and_formulas.xlsx");
+    std::fs::copy("./tests/styled.xlsx", &path).unwrap();
+
+    let connection = init_connection();
+    connection.execute(format!("\
+        CREATE VIRTUAL TABLE orders USING xlite(\
+            FILENAME '{}',\
+            WORKSHEET 'Sheet1',\
+            RANGE 'A1:C',\
+            HEADER '1'\
+        );\
+    ", path.display()).as_str(), params![]).unwrap();
+    connection.execute("INSERT INTO orders VALUES ('plum', 45294, 7);", params![]).unwrap();
+
+    let cells = |connection: &rusqlite::Connection| {
+        let mut query = connection.prepare(format!("\
+            SELECT address, type, formula FROM xlite_cells('{}', 'Sheet1') WHERE column IN (2, 5) AND row > 1;\
+        ", path.display()).as_str()).unwrap();
+        query
+            .query_map(params![], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))
+            .unwrap()
+            .map(|r| r.unwrap())
+            .collect::<Vec<(String, String, Option<String>)>>()
+    };
+    let inserted = cells(&connection);
+
+    connection.execute("DELETE FROM orders WHERE item = 'apple';", params![]).unwrap();
+    let deleted = cells(&connection);
+
+    assert_eq!(inserted, vec![
+        ("B2".to_string(), "datetime".to_string(), None),
+        ("E2".to_string(), "float".to_string(), Some("=SUM(C2:C3)".to_string())),
+        ("B3".to_string(), "datetime".to_string(), None),
+        ("B4".to_string(), "datetime".to_string(), None),
+    ]);
+    assert_eq!(deleted, vec![
+        ("B2".to_string(), "datetime".to_string(), None),
+        ("E2".to_string(), "float".to_string(), Some("=SUM(C2:C3)".to_string())),
+        ("B3".to_string(), "datetime".to_string(), None),
+    ]);
+
+    std::fs::remove_file(&path).unwrap();
+}